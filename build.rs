@@ -0,0 +1,511 @@
+//! Generates `$OUT_DIR/messages.rs`, which `src/messages.rs` includes, from
+//! the Kafka JSON message specs in `messages/`, the same files Apache Kafka
+//! keeps in `clients/src/main/resources/common/message/`.
+//!
+//! Each spec becomes a module named after the message, holding a struct for
+//! the message and one for each nested or common struct:
+//!
+//! - `versions` outside the message's valid versions become `versions=`
+//!   field attributes, tagged fields become `tag=` attributes placed after the
+//!   `TaggedFields` field, see `serde_kafka::Version` and
+//!   `serde_kafka::TaggedFields`.
+//! - `nullableVersions` fields are `Option`s with `#[serde(with = "nullable")]`,
+//!   and a `nullable=` attribute when they aren't nullable in every version
+//!   they are present in. Nullable structs implement `Nullable` once, however
+//!   many fields hold them.
+//! - `bytes` and `records` fields are `Bytes` with
+//!   `#[serde(with = "shared_bytes")]`, so record batches aren't copied.
+//! - `default` values are used by `Default` and, for tagged fields, to leave
+//!   them out of the tagged field section.
+
+use std::{collections::HashSet, env, fmt::Write, fs, path::Path};
+
+use serde::Deserialize;
+use serde_json::Value;
+
+const SPECS_DIR: &str = "messages";
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Spec {
+    api_key: Option<i16>,
+    name: String,
+    valid_versions: String,
+    flexible_versions: String,
+    #[serde(default)]
+    fields: Vec<Field>,
+    #[serde(default)]
+    common_structs: Vec<CommonStruct>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CommonStruct {
+    name: String,
+    fields: Vec<Field>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Field {
+    name: String,
+    #[serde(rename = "type")]
+    ty: String,
+    versions: String,
+    nullable_versions: Option<String>,
+    flexible_versions: Option<String>,
+    tag: Option<u32>,
+    default: Option<Value>,
+    about: Option<String>,
+    #[serde(default)]
+    fields: Vec<Field>,
+}
+
+/// A `versions` spec string: `N+`, `N-M`, `N` or `none`.
+#[derive(Clone, Copy)]
+struct Versions {
+    min: i16,
+    max: i16,
+}
+
+impl Versions {
+    fn parse(versions: &str) -> Versions {
+        let parse = |v: &str| {
+            v.parse()
+                .unwrap_or_else(|_| panic!("Invalid versions `{versions}`"))
+        };
+
+        if versions == "none" {
+            Versions { min: 0, max: -1 }
+        } else if let Some(min) = versions.strip_suffix('+') {
+            Versions {
+                min: parse(min),
+                max: i16::MAX,
+            }
+        } else if let Some((min, max)) = versions.split_once('-') {
+            Versions {
+                min: parse(min),
+                max: parse(max),
+            }
+        } else {
+            let version = parse(versions);
+            Versions {
+                min: version,
+                max: version,
+            }
+        }
+    }
+
+    fn covers(&self, other: Versions) -> bool {
+        self.min <= other.min && self.max >= other.max
+    }
+
+    fn is_none(&self) -> bool {
+        self.min > self.max
+    }
+
+    fn intersection(&self, other: Versions) -> Versions {
+        Versions {
+            min: self.min.max(other.min),
+            max: self.max.min(other.max),
+        }
+    }
+}
+
+struct Generator<'a> {
+    spec: &'a Spec,
+    flexible: bool,
+    /// Structs already given an `impl Nullable`.
+    nullable_structs: HashSet<String>,
+    output: String,
+}
+
+impl Generator<'_> {
+    /// Generates a struct whose fields are on the wire in `versions`.
+    fn generate_struct(
+        &mut self,
+        name: &str,
+        about: Option<&str>,
+        fields: &[Field],
+        versions: Versions,
+    ) {
+        // Nested structs first, so each struct reads top-down.
+        for field in fields {
+            if !field.fields.is_empty() {
+                let nested = field.ty.trim_start_matches("[]");
+                let field_versions = Versions::parse(&field.versions);
+                self.generate_struct(
+                    nested,
+                    field.about.as_deref(),
+                    &field.fields,
+                    field_versions,
+                );
+            }
+        }
+
+        let has_defaults = fields.iter().any(has_custom_default);
+
+        let out = &mut self.output;
+        writeln!(out).unwrap();
+        write_doc(out, "    ", about);
+        let derive_default = if has_defaults { "" } else { "Default, " };
+        writeln!(
+            out,
+            "    #[derive(Debug, Clone, PartialEq, {derive_default}Serialize, Deserialize)]"
+        )
+        .unwrap();
+        writeln!(out, "    #[serde(default)]").unwrap();
+        writeln!(out, "    pub struct {name} {{").unwrap();
+
+        for field in fields.iter().filter(|field| field.tag.is_none()) {
+            self.generate_field(name, field, versions);
+        }
+        if self.flexible {
+            writeln!(self.output, "        pub tagged_fields: TaggedFields,").unwrap();
+        }
+        for field in fields.iter().filter(|field| field.tag.is_some()) {
+            self.generate_field(name, field, versions);
+        }
+        writeln!(self.output, "    }}").unwrap();
+
+        for field in fields.iter().filter(|field| is_nullable(field)) {
+            if struct_type(field).is_some()
+                && !field.ty.starts_with("[]")
+                && self.nullable_structs.insert(field.ty.clone())
+            {
+                writeln!(
+                    self.output,
+                    "\n    impl Nullable for {} {{\n        const NULLABLE_NAME: &'static str = NULLABLE_STRUCT_NAME;\n    }}",
+                    field.ty
+                )
+                .unwrap();
+            }
+        }
+
+        if has_defaults {
+            let out = &mut self.output;
+            writeln!(out, "\n    impl Default for {name} {{").unwrap();
+            writeln!(out, "        fn default() -> Self {{").unwrap();
+            writeln!(out, "            {name} {{").unwrap();
+            for field in fields {
+                writeln!(
+                    out,
+                    "                {}: {},",
+                    field_name(&field.name),
+                    default_value(field)
+                )
+                .unwrap();
+            }
+            if self.flexible {
+                writeln!(
+                    out,
+                    "                tagged_fields: TaggedFields::default(),"
+                )
+                .unwrap();
+            }
+            writeln!(out, "            }}\n        }}\n    }}").unwrap();
+        }
+
+        for field in fields {
+            if field.tag.is_some() && has_custom_default(field) {
+                let out = &mut self.output;
+                writeln!(
+                    out,
+                    "\n    fn {}(value: &{}) -> bool {{\n        let default: {} = {};\n        *value == default\n    }}",
+                    is_default_fn(name, field),
+                    rust_type(field),
+                    rust_type(field),
+                    default_value(field)
+                )
+                .unwrap();
+            }
+        }
+    }
+
+    fn generate_field(&mut self, struct_name: &str, field: &Field, struct_versions: Versions) {
+        let mut attributes = Vec::new();
+        let versions = Versions::parse(&field.versions);
+        if !versions.covers(struct_versions) {
+            attributes.push(format!("versions={}", field.versions));
+        }
+        if let Some(nullable_versions) = field.nullable_versions.as_deref() {
+            let present = versions.intersection(struct_versions);
+            if is_nullable(field) && !Versions::parse(nullable_versions).covers(present) {
+                attributes.push(format!("nullable={nullable_versions}"));
+            }
+        }
+        if let Some(tag) = field.tag {
+            attributes.push(format!("tag={tag}"));
+        }
+
+        let out = &mut self.output;
+        write_doc(out, "        ", field.about.as_deref());
+
+        let name = field_name(&field.name);
+        if !attributes.is_empty() {
+            let key = name.trim_start_matches("r#");
+            let attributes = attributes.join(";");
+            writeln!(out, "        #[serde(rename = \"{key};{attributes}\")]").unwrap();
+        }
+        if is_bytes(field) {
+            let with = if is_nullable(field) {
+                "shared_bytes::nullable"
+            } else {
+                "shared_bytes"
+            };
+            writeln!(out, "        #[serde(with = \"{with}\")]").unwrap();
+        } else if is_nullable(field) {
+            writeln!(out, "        #[serde(with = \"nullable\")]").unwrap();
+        } else if field.ty == "uuid" {
+            writeln!(out, "        #[serde(with = \"uuid_as_bytes\")]").unwrap();
+        } else if field.ty == "[]uuid" {
+            writeln!(out, "        #[serde(with = \"uuid_as_bytes::vec\")]").unwrap();
+        }
+        if field.tag.is_some() {
+            let skip = if has_custom_default(field) {
+                is_default_fn(struct_name, field)
+            } else {
+                "is_default".to_string()
+            };
+            writeln!(out, "        #[serde(skip_serializing_if = \"{skip}\")]").unwrap();
+        }
+        writeln!(out, "        pub {name}: {},", rust_type(field)).unwrap();
+    }
+}
+
+fn is_nullable(field: &Field) -> bool {
+    field
+        .nullable_versions
+        .as_deref()
+        .is_some_and(|versions| !Versions::parse(versions).is_none())
+}
+
+/// Whether `field` holds bytes, kept as `Bytes` sliced from the message.
+fn is_bytes(field: &Field) -> bool {
+    matches!(field.ty.as_str(), "bytes" | "records")
+}
+
+/// The struct a field holds, if any.
+fn struct_type(field: &Field) -> Option<&str> {
+    let ty = field.ty.trim_start_matches("[]");
+    ty.starts_with(char::is_uppercase).then_some(ty)
+}
+
+fn scalar_type(ty: &str, field: &Field) -> String {
+    match ty {
+        "bool" => "bool".into(),
+        "int8" => "i8".into(),
+        "int16" => "i16".into(),
+        "uint16" => "u16".into(),
+        "int32" => "i32".into(),
+        "uint32" => "u32".into(),
+        "int64" => "i64".into(),
+        "float64" => "f64".into(),
+        "uuid" => "Uuid".into(),
+        "bytes" | "records" => "Bytes".into(),
+        // Strings that opt out of flexible versions keep their `INT16` length.
+        "string" if field.flexible_versions.as_deref() == Some("none") => "String".into(),
+        "string" => "CompactString".into(),
+        ty if ty.starts_with(char::is_uppercase) => ty.into(),
+        ty => panic!("Unsupported type `{ty}` of field `{}`", field.name),
+    }
+}
+
+fn rust_type(field: &Field) -> String {
+    let ty = match field.ty.strip_prefix("[]") {
+        Some(element) => format!("Vec<{}>", scalar_type(element, field)),
+        None => scalar_type(&field.ty, field),
+    };
+
+    if is_nullable(field) {
+        format!("Option<{ty}>")
+    } else {
+        ty
+    }
+}
+
+fn default_value(field: &Field) -> String {
+    let default = match &field.default {
+        None => return "Default::default()".into(),
+        Some(Value::String(default)) => default.clone(),
+        Some(default) => default.to_string(),
+    };
+
+    match field.ty.as_str() {
+        _ if default == "null" => "None".into(),
+        "bool" => default,
+        "int8" | "int16" | "uint16" | "int32" | "uint32" | "int64" => {
+            match default.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16)
+                    .unwrap_or_else(|_| panic!("Invalid default of field `{}`", field.name))
+                    .to_string(),
+                None => default,
+            }
+        }
+        "float64" => format!("{default}_f64"),
+        "string" if is_nullable(field) => format!("Some({default:?}.into())"),
+        "string" => format!("{default:?}.into()"),
+        ty => panic!(
+            "Unsupported default for type `{ty}` of field `{}`",
+            field.name
+        ),
+    }
+}
+
+/// Whether the spec default of `field` differs from its type's `Default`.
+fn has_custom_default(field: &Field) -> bool {
+    !matches!(
+        default_value(field).as_str(),
+        "Default::default()" | "None" | "false" | "0"
+    )
+}
+
+fn is_default_fn(struct_name: &str, field: &Field) -> String {
+    format!(
+        "{}_{}_is_default",
+        snake_case(struct_name),
+        field_name(&field.name).trim_start_matches("r#")
+    )
+}
+
+fn field_name(name: &str) -> String {
+    let name = snake_case(name);
+    match name.as_str() {
+        "type" | "match" | "ref" | "self" | "mod" | "use" => format!("r#{name}"),
+        _ => name,
+    }
+}
+
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut snake = String::new();
+
+    for (i, &c) in chars.iter().enumerate() {
+        if c.is_uppercase() {
+            let previous_lower = i > 0 && chars[i - 1].is_lowercase();
+            let acronym_end = i > 0
+                && chars[i - 1].is_uppercase()
+                && chars.get(i + 1).is_some_and(|next| next.is_lowercase());
+            if previous_lower || acronym_end {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+
+    snake
+}
+
+fn write_doc(out: &mut String, indent: &str, about: Option<&str>) {
+    if let Some(about) = about {
+        writeln!(out, "{indent}/// {about}").unwrap();
+    }
+}
+
+/// Strips the `//` comments Kafka specs carry, which JSON doesn't allow.
+fn strip_comments(spec: &str) -> String {
+    spec.lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn generate(spec: &Spec) -> String {
+    let valid_versions = Versions::parse(&spec.valid_versions);
+    let flexible_versions = Versions::parse(&spec.flexible_versions);
+
+    let mut generator = Generator {
+        spec,
+        flexible: !flexible_versions.is_none(),
+        nullable_structs: HashSet::new(),
+        output: String::new(),
+    };
+
+    let out = &mut generator.output;
+    writeln!(out, "pub mod {} {{", snake_case(&spec.name)).unwrap();
+    writeln!(out, "    #[allow(unused_imports)]").unwrap();
+    writeln!(out, "    use serde::{{Deserialize, Serialize}};").unwrap();
+    writeln!(out, "    #[allow(unused_imports)]").unwrap();
+    writeln!(out, "    use uuid::Uuid;").unwrap();
+    writeln!(out, "    #[allow(unused_imports)]").unwrap();
+    writeln!(out, "    use bytes::Bytes;").unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "    #[allow(unused_imports)]").unwrap();
+    writeln!(
+        out,
+        "    use crate::serde_kafka::{{is_default, nullable::{{self, Nullable, NULLABLE_STRUCT_NAME}}, shared_bytes, uuid_as_bytes, CompactString, TaggedFields, Version}};"
+    )
+    .unwrap();
+
+    for common in &spec.common_structs {
+        generator.generate_struct(&common.name, None, &common.fields, valid_versions);
+    }
+    generator.generate_struct(&spec.name, None, &spec.fields, valid_versions);
+
+    let spec = generator.spec;
+    let out = &mut generator.output;
+    writeln!(out, "\n    impl {} {{", spec.name).unwrap();
+    if let Some(api_key) = spec.api_key {
+        writeln!(out, "        pub const API_KEY: i16 = {api_key};").unwrap();
+    }
+    writeln!(
+        out,
+        "        pub const LOWEST_SUPPORTED_VERSION: i16 = {};",
+        valid_versions.min
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        pub const HIGHEST_SUPPORTED_VERSION: i16 = {};",
+        valid_versions.max
+    )
+    .unwrap();
+    writeln!(out).unwrap();
+    writeln!(out, "        pub fn version(version: i16) -> Version {{").unwrap();
+    writeln!(out, "            Version {{").unwrap();
+    writeln!(out, "                version,").unwrap();
+    let flexible = if flexible_versions.is_none() {
+        "false".to_string()
+    } else if flexible_versions.max == i16::MAX {
+        format!("version >= {}", flexible_versions.min)
+    } else {
+        format!(
+            "({}..={}).contains(&version)",
+            flexible_versions.min, flexible_versions.max
+        )
+    };
+    writeln!(out, "                flexible: {flexible},").unwrap();
+    writeln!(out, "            }}\n        }}\n    }}\n}}\n").unwrap();
+
+    generator.output
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={SPECS_DIR}");
+
+    let mut paths: Vec<_> = fs::read_dir(SPECS_DIR)
+        .expect("Failed to read message specs")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect();
+    paths.sort();
+
+    let mut output = String::new();
+    for path in paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        let spec = fs::read_to_string(&path).unwrap();
+        let spec: Spec = serde_json::from_str(&strip_comments(&spec))
+            .unwrap_or_else(|e| panic!("Invalid message spec {}: {e}", path.display()));
+
+        output.push_str(&generate(&spec));
+    }
+
+    let out_dir = env::var("OUT_DIR").unwrap();
+    fs::write(Path::new(&out_dir).join("messages.rs"), output).unwrap();
+}
@@ -0,0 +1,572 @@
+//! The topics and partitions of the cluster, replayed from the records of the
+//! `__cluster_metadata` log.
+
+use std::{collections::BTreeMap, path::Path};
+
+use uuid::Uuid;
+
+use crate::{
+    modules::metadata_log_file::{
+        load,
+        payloads::{MetadataLogFile, RecordValue, NO_LEADER_CHANGE},
+    },
+    serde_kafka::{self, ErrorKind},
+};
+
+/// Topics Kafka keeps for itself.
+const INTERNAL_TOPICS: [&str; 2] = ["__consumer_offsets", "__transaction_state"];
+
+/// The `resource_type` of config records setting a topic config.
+const TOPIC_RESOURCE_TYPE: i8 = 2;
+
+/// The directory of replicas whose log directory is offline, Kafka's
+/// `DirectoryId.LOST`.
+pub const LOST_DIRECTORY: Uuid = Uuid::from_u128(1);
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ClusterMetadata {
+    cluster_id: Option<String>,
+    // By name, the order topics are described in.
+    topics: BTreeMap<String, TopicMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TopicMetadata {
+    pub name: String,
+    pub id: Uuid,
+    pub partitions: BTreeMap<i32, PartitionMetadata>,
+    /// The configs set on the topic, by name.
+    pub configs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartitionMetadata {
+    pub leader: i32,
+    pub leader_epoch: i32,
+    pub replicas: Vec<i32>,
+    pub in_sync_replicas: Vec<i32>,
+    /// The log directory of each replica, empty when not known.
+    pub directories: Vec<Uuid>,
+    /// The replicas that may be elected leader, though not in sync.
+    pub eligible_leader_replicas: Vec<i32>,
+    /// The replicas last in the ELR, when it became empty.
+    pub last_known_elr: Vec<i32>,
+}
+
+impl ClusterMetadata {
+    /// Loads the metadata log at `path`.
+    pub async fn load(path: impl AsRef<Path>) -> serde_kafka::Result<Self> {
+        Self::from_log(&load::load(path).await?)
+    }
+
+    /// Replays the records of a metadata log. Records of types this broker
+    /// doesn't know are skipped.
+    pub fn from_log(batches: &[MetadataLogFile]) -> serde_kafka::Result<Self> {
+        let mut metadata = ClusterMetadata::default();
+
+        for record in batches.iter().flat_map(|batch| &batch.records) {
+            match RecordValue::from_record(record) {
+                Ok(value) => metadata.replay(value)?,
+                Err(e) if matches!(e.kind(), ErrorKind::UnknownDiscriminant { .. }) => {
+                    tracing::trace!("metadata record skipped: {e}");
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(metadata)
+    }
+
+    /// Applies a metadata record. A partition record replaces the partition
+    /// it names, which must belong to a known topic, and a partition change
+    /// record updates it, a new leader bumping its leader epoch. A config
+    /// record sets a topic config, or deletes it when null; other configs are
+    /// ignored.
+    pub fn replay(&mut self, value: RecordValue) -> serde_kafka::Result<()> {
+        match value {
+            RecordValue::TopicRecordValue {
+                topic_name,
+                topic_uuid,
+                ..
+            } => {
+                self.topics.insert(
+                    topic_name.to_string(),
+                    TopicMetadata {
+                        name: topic_name.to_string(),
+                        id: topic_uuid,
+                        partitions: BTreeMap::new(),
+                        configs: BTreeMap::new(),
+                    },
+                );
+            }
+            RecordValue::PartitionRecordValue {
+                partition_id,
+                topic_uuid,
+                replicas,
+                in_sync_replicas,
+                leader,
+                leader_epoch,
+                directories,
+                eligible_leader_replicas,
+                last_known_elr,
+                ..
+            } => {
+                let topic = self.topic_by_id_mut(topic_uuid)?;
+                topic.partitions.insert(
+                    partition_id,
+                    PartitionMetadata {
+                        leader,
+                        leader_epoch,
+                        replicas,
+                        in_sync_replicas,
+                        directories,
+                        eligible_leader_replicas: eligible_leader_replicas.unwrap_or_default(),
+                        last_known_elr: last_known_elr.unwrap_or_default(),
+                    },
+                );
+            }
+            RecordValue::PartitionChangeRecordValue {
+                partition_id,
+                topic_uuid,
+                in_sync_replicas,
+                leader,
+                replicas,
+                eligible_leader_replicas,
+                last_known_elr,
+                directories,
+                ..
+            } => {
+                let partition = self
+                    .topic_by_id_mut(topic_uuid)?
+                    .partitions
+                    .get_mut(&partition_id)
+                    .ok_or_else(|| {
+                        serde_kafka::Error::message(format!(
+                            "Partition change record of unknown partition {topic_uuid}-{partition_id}"
+                        ))
+                    })?;
+                if leader != NO_LEADER_CHANGE {
+                    partition.leader = leader;
+                    partition.leader_epoch += 1;
+                }
+                // The directories of new replicas aren't known unless the
+                // record gives them.
+                if let Some(replicas) = replicas {
+                    partition.replicas = replicas;
+                    partition.directories.clear();
+                }
+                if let Some(directories) = directories {
+                    partition.directories = directories;
+                }
+                if let Some(in_sync_replicas) = in_sync_replicas {
+                    partition.in_sync_replicas = in_sync_replicas;
+                }
+                if let Some(eligible_leader_replicas) = eligible_leader_replicas {
+                    partition.eligible_leader_replicas = eligible_leader_replicas;
+                }
+                if let Some(last_known_elr) = last_known_elr {
+                    partition.last_known_elr = last_known_elr;
+                }
+            }
+            RecordValue::RemoveTopicRecordValue { topic_uuid, .. } => {
+                let name = self.topic_by_id_mut(topic_uuid)?.name.clone();
+                self.topics.remove(&name);
+            }
+            RecordValue::ConfigRecordValue {
+                resource_type: TOPIC_RESOURCE_TYPE,
+                resource_name,
+                name,
+                value,
+                ..
+            } => {
+                let Some(topic) = self.topics.get_mut(resource_name.as_str()) else {
+                    tracing::trace!(
+                        "config record of unknown topic {} skipped",
+                        resource_name.as_str()
+                    );
+                    return Ok(());
+                };
+                match value {
+                    Some(value) => topic.configs.insert(name.to_string(), value.to_string()),
+                    None => topic.configs.remove(name.as_str()),
+                };
+            }
+            RecordValue::ConfigRecordValue { .. } | RecordValue::FeatureLevelValue { .. } => {}
+        }
+
+        Ok(())
+    }
+
+    fn topic_by_id_mut(&mut self, id: Uuid) -> serde_kafka::Result<&mut TopicMetadata> {
+        self.topics
+            .values_mut()
+            .find(|topic| topic.id == id)
+            .ok_or_else(|| serde_kafka::Error::message(format!("Record of unknown topic {id}")))
+    }
+
+    /// Sets the id of the cluster, which the metadata log doesn't hold but
+    /// `meta.properties` does.
+    pub fn with_cluster_id(mut self, cluster_id: impl Into<String>) -> Self {
+        self.cluster_id = Some(cluster_id.into());
+        self
+    }
+
+    pub fn cluster_id(&self) -> Option<&str> {
+        self.cluster_id.as_deref()
+    }
+
+    pub fn topics(&self) -> impl Iterator<Item = &TopicMetadata> {
+        self.topics.values()
+    }
+
+    pub fn topic(&self, name: &str) -> Option<&TopicMetadata> {
+        self.topics.get(name)
+    }
+
+    pub fn topic_by_id(&self, id: Uuid) -> Option<&TopicMetadata> {
+        self.topics.values().find(|topic| topic.id == id)
+    }
+
+    pub fn partition(&self, topic: &str, partition: i32) -> Option<&PartitionMetadata> {
+        self.topic(topic)?.partitions.get(&partition)
+    }
+}
+
+impl TopicMetadata {
+    pub fn is_internal(&self) -> bool {
+        INTERNAL_TOPICS.contains(&self.name.as_str())
+    }
+
+    pub fn config(&self, name: &str) -> Option<&str> {
+        self.configs.get(name).map(String::as_str)
+    }
+}
+
+impl PartitionMetadata {
+    /// The replicas that are offline: only `node_id` is alive, so those on
+    /// other brokers, and those whose log directory is lost.
+    pub fn offline_replicas(&self, node_id: i32) -> Vec<i32> {
+        self.replicas
+            .iter()
+            .enumerate()
+            .filter(|&(i, &replica)| {
+                replica != node_id || self.directories.get(i) == Some(&LOST_DIRECTORY)
+            })
+            .map(|(_, &replica)| replica)
+            .collect()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        records::Record,
+        serde_kafka::{TaggedFields, VarInt, Version},
+    };
+
+    use super::*;
+
+    const TOPIC_ID: Uuid = Uuid::from_u128(0x91);
+
+    /// A record of `value`, encoded at its `version`.
+    fn record(offset_delta: i32, value: &RecordValue) -> Record {
+        let version = Version {
+            version: serde_kafka::to_bytes_mut(value).unwrap()[2].into(),
+            flexible: true,
+        };
+        Record {
+            offset_delta: VarInt(offset_delta),
+            value: Some(
+                serde_kafka::to_bytes_mut_with_version(value, version)
+                    .unwrap()
+                    .freeze(),
+            ),
+            ..Record::default()
+        }
+    }
+
+    fn log(records: &[RecordValue]) -> MetadataLogFile {
+        MetadataLogFile {
+            last_offset_delta: records.len() as i32 - 1,
+            records: records
+                .iter()
+                .enumerate()
+                .map(|(i, value)| record(i as i32, value))
+                .collect(),
+            ..MetadataLogFile::default()
+        }
+    }
+
+    fn partition_record(partition_id: i32) -> RecordValue {
+        RecordValue::PartitionRecordValue {
+            frame_version: 1,
+            value_type: 3,
+            version: 1,
+            partition_id,
+            topic_uuid: TOPIC_ID,
+            replicas: vec![1],
+            in_sync_replicas: vec![1],
+            removing_replicas: vec![],
+            adding_replicas: vec![],
+            leader: 1,
+            leader_epoch: 2,
+            partition_epoch: 0,
+            directories: vec![],
+            tagged_fields: TaggedFields::default(),
+            eligible_leader_replicas: None,
+            last_known_elr: None,
+        }
+    }
+
+    #[test]
+    fn test_from_log() {
+        let topic = RecordValue::TopicRecordValue {
+            frame_version: 1,
+            value_type: 2,
+            version: 0,
+            topic_name: "saz".into(),
+            topic_uuid: TOPIC_ID,
+            tagged_fields: TaggedFields::default(),
+        };
+        let mut unknown = serde_kafka::to_bytes_mut(&topic).unwrap();
+        // value_type
+        unknown[1] = 0x7f;
+
+        let batch = MetadataLogFile {
+            last_offset_delta: 3,
+            records: vec![
+                record(0, &topic),
+                record(1, &partition_record(1)),
+                record(2, &partition_record(0)),
+                Record {
+                    offset_delta: VarInt(3),
+                    value: Some(unknown.freeze()),
+                    ..Record::default()
+                },
+            ],
+            ..MetadataLogFile::default()
+        };
+        let metadata = ClusterMetadata::from_log(&[batch]).unwrap();
+
+        let topic = metadata.topic("saz").unwrap();
+        assert_eq!(topic.id, TOPIC_ID);
+        assert_eq!(topic.partitions.keys().collect::<Vec<_>>(), [&0, &1]);
+        assert_eq!(metadata.topic_by_id(TOPIC_ID), Some(topic));
+        assert_eq!(metadata.partition("saz", 1).unwrap().leader_epoch, 2);
+        assert_eq!(metadata.partition("saz", 2), None);
+        assert_eq!(metadata.topic("foo"), None);
+
+        // A partition of a topic that wasn't created.
+        let batch = MetadataLogFile {
+            records: vec![record(0, &partition_record(0))],
+            ..MetadataLogFile::default()
+        };
+        assert!(ClusterMetadata::from_log(&[batch]).is_err());
+    }
+
+    fn topic_record() -> RecordValue {
+        RecordValue::TopicRecordValue {
+            frame_version: 1,
+            value_type: 2,
+            version: 0,
+            topic_name: "saz".into(),
+            topic_uuid: TOPIC_ID,
+            tagged_fields: TaggedFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_partition_record_versions() {
+        let mut v0 = partition_record(0);
+        if let RecordValue::PartitionRecordValue { version, .. } = &mut v0 {
+            *version = 0;
+        }
+        let v0_record = record(0, &v0);
+        let v1_record = record(0, &partition_record(0));
+
+        // Directories, an empty compact array, are only in v1.
+        let v0_len = v0_record.value.as_ref().unwrap().len();
+        assert_eq!(v0_len + 1, v1_record.value.as_ref().unwrap().len());
+        assert_eq!(RecordValue::from_record(&v0_record).unwrap(), v0);
+        assert_eq!(
+            RecordValue::from_record(&v1_record).unwrap(),
+            partition_record(0)
+        );
+    }
+
+    #[test]
+    fn test_eligible_leader_replicas() {
+        let mut v2 = partition_record(0);
+        if let RecordValue::PartitionRecordValue {
+            version,
+            eligible_leader_replicas,
+            last_known_elr,
+            ..
+        } = &mut v2
+        {
+            *version = 2;
+            *eligible_leader_replicas = Some(vec![2]);
+            *last_known_elr = Some(vec![3]);
+        }
+        assert_eq!(RecordValue::from_record(&record(0, &v2)).unwrap(), v2);
+
+        let mut change = change_record(NO_LEADER_CHANGE, None);
+        if let RecordValue::PartitionChangeRecordValue {
+            version,
+            eligible_leader_replicas,
+            ..
+        } = &mut change
+        {
+            *version = 2;
+            *eligible_leader_replicas = Some(vec![]);
+        }
+        let records = [topic_record(), v2, change];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+
+        let partition = metadata.partition("saz", 0).unwrap();
+        assert_eq!(partition.eligible_leader_replicas, Vec::<i32>::new());
+        assert_eq!(partition.last_known_elr, [3]);
+    }
+
+    fn change_record(leader: i32, in_sync_replicas: Option<Vec<i32>>) -> RecordValue {
+        RecordValue::PartitionChangeRecordValue {
+            frame_version: 1,
+            value_type: 5,
+            version: 0,
+            partition_id: 0,
+            topic_uuid: TOPIC_ID,
+            tagged_fields: TaggedFields::default(),
+            in_sync_replicas,
+            leader,
+            replicas: None,
+            eligible_leader_replicas: None,
+            last_known_elr: None,
+            directories: None,
+        }
+    }
+
+    #[test]
+    fn test_partition_changes() {
+        let records = [
+            topic_record(),
+            partition_record(0),
+            change_record(NO_LEADER_CHANGE, Some(vec![1, 2])),
+            change_record(-1, None),
+        ];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+
+        let partition = metadata.partition("saz", 0).unwrap();
+        assert_eq!(partition.leader, -1);
+        assert_eq!(partition.leader_epoch, 3);
+        assert_eq!(partition.replicas, [1]);
+        assert_eq!(partition.in_sync_replicas, [1, 2]);
+
+        // New replicas, in directories not known until given.
+        let mut replicas = change_record(NO_LEADER_CHANGE, None);
+        if let RecordValue::PartitionChangeRecordValue { replicas, .. } = &mut replicas {
+            *replicas = Some(vec![1, 2, 3]);
+        }
+        let mut directories = change_record(NO_LEADER_CHANGE, None);
+        if let RecordValue::PartitionChangeRecordValue {
+            version,
+            directories,
+            ..
+        } = &mut directories
+        {
+            *version = 1;
+            *directories = Some(vec![Uuid::from_u128(5), LOST_DIRECTORY, Uuid::nil()]);
+        }
+        let records = [topic_record(), partition_record(0), replicas];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+        let partition = metadata.partition("saz", 0).unwrap();
+        assert_eq!(partition.replicas, [1, 2, 3]);
+        assert!(partition.directories.is_empty());
+
+        let records = [topic_record(), partition_record(0), directories];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+        let partition = metadata.partition("saz", 0).unwrap();
+        assert_eq!(partition.replicas, [1]);
+        assert_eq!(partition.directories[1], LOST_DIRECTORY);
+
+        // A change of a partition that wasn't created.
+        let records = [topic_record(), change_record(1, None)];
+        assert!(ClusterMetadata::from_log(&[log(&records)]).is_err());
+    }
+
+    #[test]
+    fn test_offline_replicas() {
+        let mut partition = PartitionMetadata {
+            leader: 1,
+            leader_epoch: 0,
+            replicas: vec![1, 2, 3],
+            in_sync_replicas: vec![1],
+            directories: vec![],
+            eligible_leader_replicas: vec![],
+            last_known_elr: vec![],
+        };
+        assert_eq!(partition.offline_replicas(1), [2, 3]);
+        assert_eq!(partition.offline_replicas(4), [1, 2, 3]);
+
+        partition.directories = vec![LOST_DIRECTORY, Uuid::from_u128(5), LOST_DIRECTORY];
+        assert_eq!(partition.offline_replicas(2), [1, 3]);
+    }
+
+    #[test]
+    fn test_remove_topic() {
+        let records = [
+            topic_record(),
+            partition_record(0),
+            RecordValue::RemoveTopicRecordValue {
+                frame_version: 1,
+                value_type: 9,
+                version: 0,
+                topic_uuid: TOPIC_ID,
+                tagged_fields: TaggedFields::default(),
+            },
+        ];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+        assert_eq!(metadata.topic("saz"), None);
+        assert_eq!(metadata.topic_by_id(TOPIC_ID), None);
+    }
+
+    fn config_record(resource_type: i8, name: &str, value: Option<&str>) -> RecordValue {
+        RecordValue::ConfigRecordValue {
+            frame_version: 1,
+            value_type: 4,
+            version: 0,
+            resource_type,
+            resource_name: "saz".into(),
+            name: name.into(),
+            value: value.map(Into::into),
+            tagged_fields: TaggedFields::default(),
+        }
+    }
+
+    #[test]
+    fn test_topic_configs() {
+        let topic = RecordValue::TopicRecordValue {
+            frame_version: 1,
+            value_type: 2,
+            version: 0,
+            topic_name: "saz".into(),
+            topic_uuid: TOPIC_ID,
+            tagged_fields: TaggedFields::default(),
+        };
+        let records = [
+            topic,
+            config_record(TOPIC_RESOURCE_TYPE, "compression.type", Some("gzip")),
+            config_record(TOPIC_RESOURCE_TYPE, "cleanup.policy", Some("compact")),
+            config_record(TOPIC_RESOURCE_TYPE, "cleanup.policy", None),
+            // A broker config.
+            config_record(4, "retention.ms", Some("1000")),
+        ];
+        let metadata = ClusterMetadata::from_log(&[log(&records)]).unwrap();
+
+        let topic = metadata.topic("saz").unwrap();
+        assert_eq!(topic.config("compression.type"), Some("gzip"));
+        assert_eq!(topic.config("cleanup.policy"), None);
+        assert_eq!(topic.config("retention.ms"), None);
+    }
+}
@@ -0,0 +1,703 @@
+//! Broker configuration, read from a `server.properties` file and overridden
+//! by `--override key=value` flags, with the keys and defaults of Kafka.
+//! Keys this broker doesn't use are ignored.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::records::CompressionType;
+
+mod properties;
+
+pub use properties::parse as parse_properties;
+
+/// Default of `connections.max.idle.ms`.
+pub const DEFAULT_CONNECTIONS_MAX_IDLE: Duration = Duration::from_secs(10 * 60);
+/// Default of `num.io.threads`.
+pub const DEFAULT_NUM_IO_THREADS: usize = 8;
+/// Default of `queued.max.requests`.
+pub const DEFAULT_QUEUED_MAX_REQUESTS: usize = 500;
+/// Default of `socket.request.max.bytes`.
+pub const DEFAULT_SOCKET_REQUEST_MAX_BYTES: usize = 100 * 1024 * 1024;
+/// Default of `max.connections` and `max.connections.per.ip`, no limit.
+pub const DEFAULT_MAX_CONNECTIONS: usize = i32::MAX as usize;
+/// Default of `log.retention.hours`, 7 days.
+pub const DEFAULT_LOG_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Default of `listener.security.protocol.map`, each protocol for the listener
+/// of the same name.
+pub const DEFAULT_LISTENER_SECURITY_PROTOCOL_MAP: &str =
+    "PLAINTEXT:PLAINTEXT,SSL:SSL,SASL_PLAINTEXT:SASL_PLAINTEXT,SASL_SSL:SASL_SSL";
+
+pub const METADATA_LOG_DIR: &str = "__cluster_metadata-0";
+pub const FIRST_SEGMENT: &str = "00000000000000000000.log";
+/// Written to each log directory when it's formatted, holds the cluster id.
+pub const META_PROPERTIES: &str = "meta.properties";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BrokerConfig {
+    /// `node.id`, or `broker.id`. 1 when neither is set.
+    pub node_id: i32,
+    pub listeners: Vec<Listener>,
+    /// `advertised.listeners`, the `listeners` when unset. These are the
+    /// addresses given to clients, each listener's by name.
+    pub advertised_listeners: Vec<Listener>,
+    /// `listener.security.protocol.map`, the protocol of each listener name.
+    pub listener_security_protocol_map: BTreeMap<String, SecurityProtocol>,
+    /// `controller.listener.names`, the listeners of the KRaft controller,
+    /// which don't serve clients.
+    pub controller_listener_names: Vec<String>,
+    /// `log.dirs`, or `log.dir`.
+    pub log_dirs: Vec<PathBuf>,
+    /// `log.retention.ms`, `log.retention.minutes` or `log.retention.hours`,
+    /// `None` to keep logs forever.
+    pub log_retention: Option<Duration>,
+    /// `log.retention.bytes`, `None` for no limit.
+    pub log_retention_bytes: Option<u64>,
+    /// `compression.type`, for topics that don't set their own.
+    pub compression_type: CompressionType,
+    /// `connections.max.idle.ms`, `None` when -1 to keep idle connections
+    /// open.
+    pub connections_max_idle: Option<Duration>,
+    pub socket_request_max_bytes: usize,
+    pub num_io_threads: usize,
+    pub queued_max_requests: usize,
+    pub max_connections: usize,
+    pub max_connections_per_ip: usize,
+}
+
+/// A listener of `listeners` or `advertised.listeners`, `NAME://host:port`.
+/// An empty host binds every interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listener {
+    pub name: String,
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityProtocol {
+    Plaintext,
+    Ssl,
+    SaslPlaintext,
+    SaslSsl,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io { path: PathBuf, error: io::Error },
+    InvalidValue { key: String, value: String },
+    InvalidListener { name: String, reason: &'static str },
+    InvalidArgument(String),
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        let listeners = vec![Listener {
+            name: "PLAINTEXT".to_string(),
+            host: String::new(),
+            port: 9092,
+        }];
+
+        BrokerConfig {
+            node_id: 1,
+            advertised_listeners: listeners.clone(),
+            listeners,
+            listener_security_protocol_map: [
+                SecurityProtocol::Plaintext,
+                SecurityProtocol::Ssl,
+                SecurityProtocol::SaslPlaintext,
+                SecurityProtocol::SaslSsl,
+            ]
+            .into_iter()
+            .map(|protocol| (protocol.to_string(), protocol))
+            .collect(),
+            controller_listener_names: Vec::new(),
+            log_dirs: vec![PathBuf::from("/tmp/kafka-logs")],
+            log_retention: Some(DEFAULT_LOG_RETENTION),
+            log_retention_bytes: None,
+            compression_type: CompressionType::Producer,
+            connections_max_idle: Some(DEFAULT_CONNECTIONS_MAX_IDLE),
+            socket_request_max_bytes: DEFAULT_SOCKET_REQUEST_MAX_BYTES,
+            num_io_threads: DEFAULT_NUM_IO_THREADS,
+            queued_max_requests: DEFAULT_QUEUED_MAX_REQUESTS,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_connections_per_ip: DEFAULT_MAX_CONNECTIONS,
+        }
+    }
+}
+
+impl BrokerConfig {
+    /// The configuration from the command line arguments, without the program
+    /// name: an optional properties file followed by `--override key=value`
+    /// flags, as `kafka-server-start.sh` takes them.
+    pub fn from_args<I>(args: I) -> Result<Self, ConfigError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut path = None;
+        let mut overrides = Vec::new();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--override" => {
+                    let property = args.next().ok_or(ConfigError::InvalidArgument(arg))?;
+                    let (key, value) = property
+                        .split_once('=')
+                        .ok_or_else(|| ConfigError::InvalidArgument(property.clone()))?;
+                    overrides.push((key.trim().to_string(), value.trim().to_string()));
+                }
+                _ if arg.starts_with("--") || path.is_some() => {
+                    return Err(ConfigError::InvalidArgument(arg));
+                }
+                _ => path = Some(PathBuf::from(arg)),
+            }
+        }
+
+        let mut properties = match path {
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(content) => properties::parse(&content),
+                Err(error) => return Err(ConfigError::Io { path, error }),
+            },
+            None => BTreeMap::new(),
+        };
+        properties.extend(overrides);
+
+        Self::from_properties(&properties)
+    }
+
+    pub fn from_properties(properties: &BTreeMap<String, String>) -> Result<Self, ConfigError> {
+        let defaults = BrokerConfig::default();
+        let get = |key: &'static str| properties.get(key).map(|value| (key, value.trim()));
+
+        let listeners = match get("listeners") {
+            Some(property) => parse_list(property)?,
+            None => defaults.listeners,
+        };
+        if listeners.is_empty() {
+            return Err(ConfigError::InvalidValue {
+                key: "listeners".to_string(),
+                value: String::new(),
+            });
+        }
+        let advertised_listeners = match get("advertised.listeners") {
+            Some(property) => parse_list(property)?,
+            None => listeners.clone(),
+        };
+        let listener_security_protocol_map = match get("listener.security.protocol.map") {
+            Some(property) => parse_protocol_map(property)?,
+            None => defaults.listener_security_protocol_map,
+        };
+
+        let controller_listener_names = match get("controller.listener.names") {
+            Some((_, value)) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+            None => defaults.controller_listener_names,
+        };
+
+        let log_dirs = match get("log.dirs").or_else(|| get("log.dir")) {
+            Some((_, value)) => value
+                .split(',')
+                .map(str::trim)
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            None => defaults.log_dirs,
+        };
+
+        // Negative retentions keep logs forever.
+        let log_retention_ms = if let Some(property) = get("log.retention.ms") {
+            Some(parse::<i64>(property)?)
+        } else if let Some(property) = get("log.retention.minutes") {
+            Some(parse::<i64>(property)?.saturating_mul(60 * 1000))
+        } else if let Some(property) = get("log.retention.hours") {
+            Some(parse::<i64>(property)?.saturating_mul(60 * 60 * 1000))
+        } else {
+            None
+        };
+        let log_retention = match log_retention_ms {
+            Some(ms) => u64::try_from(ms).ok().map(Duration::from_millis),
+            None => defaults.log_retention,
+        };
+        let log_retention_bytes = match get("log.retention.bytes") {
+            Some(property) => u64::try_from(parse::<i64>(property)?).ok(),
+            None => defaults.log_retention_bytes,
+        };
+
+        let connections_max_idle = match get("connections.max.idle.ms") {
+            Some((_, "-1")) => None,
+            Some(property) => Some(Duration::from_millis(parse(property)?)),
+            None => defaults.connections_max_idle,
+        };
+
+        let config = BrokerConfig {
+            node_id: parse_or(
+                get("node.id").or_else(|| get("broker.id")),
+                defaults.node_id,
+            )?,
+            listeners,
+            advertised_listeners,
+            listener_security_protocol_map,
+            controller_listener_names,
+            log_dirs,
+            log_retention,
+            log_retention_bytes,
+            compression_type: parse_or(get("compression.type"), defaults.compression_type)?,
+            connections_max_idle,
+            socket_request_max_bytes: parse_or(
+                get("socket.request.max.bytes"),
+                defaults.socket_request_max_bytes,
+            )?,
+            num_io_threads: parse_or(get("num.io.threads"), defaults.num_io_threads)?,
+            queued_max_requests: parse_or(
+                get("queued.max.requests"),
+                defaults.queued_max_requests,
+            )?,
+            max_connections: parse_or(get("max.connections"), defaults.max_connections)?,
+            max_connections_per_ip: parse_or(
+                get("max.connections.per.ip"),
+                defaults.max_connections_per_ip,
+            )?,
+        };
+        config.validate_listeners()?;
+
+        Ok(config)
+    }
+
+    /// Listener names must be unique and have a security protocol, and
+    /// advertised ones must name a listener, as Kafka requires.
+    fn validate_listeners(&self) -> Result<(), ConfigError> {
+        let invalid = |listener: &Listener, reason| ConfigError::InvalidListener {
+            name: listener.name.clone(),
+            reason,
+        };
+
+        let mut names = BTreeSet::new();
+        for listener in &self.listeners {
+            if !names.insert(listener.name.as_str()) {
+                return Err(invalid(listener, "duplicate name in `listeners`"));
+            }
+            if self.security_protocol(&listener.name).is_none() {
+                return Err(invalid(
+                    listener,
+                    "no security protocol in `listener.security.protocol.map`",
+                ));
+            }
+        }
+        for listener in &self.advertised_listeners {
+            if !names.contains(listener.name.as_str()) {
+                return Err(invalid(listener, "advertised but not in `listeners`"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The listeners serving clients, all but the controller's.
+    pub fn broker_listeners(&self) -> impl Iterator<Item = &Listener> {
+        self.listeners
+            .iter()
+            .filter(|listener| !self.controller_listener_names.contains(&listener.name))
+    }
+
+    pub fn security_protocol(&self, listener_name: &str) -> Option<SecurityProtocol> {
+        self.listener_security_protocol_map
+            .get(listener_name)
+            .copied()
+    }
+
+    /// The address advertised to clients connected through the listener
+    /// named `listener_name`.
+    pub fn advertised_listener(&self, listener_name: &str) -> Option<&Listener> {
+        self.advertised_listeners
+            .iter()
+            .find(|listener| listener.name == listener_name)
+    }
+
+    /// The first segment of the `__cluster_metadata` log, in the first log
+    /// directory.
+    pub fn metadata_log_path(&self) -> PathBuf {
+        self.first_log_dir()
+            .join(METADATA_LOG_DIR)
+            .join(FIRST_SEGMENT)
+    }
+
+    /// The `meta.properties` of the first log directory.
+    pub fn meta_properties_path(&self) -> PathBuf {
+        self.first_log_dir().join(META_PROPERTIES)
+    }
+
+    fn first_log_dir(&self) -> &Path {
+        self.log_dirs
+            .first()
+            .map_or(Path::new(""), PathBuf::as_path)
+    }
+}
+
+fn parse<T: FromStr>((key, value): (&str, &str)) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::InvalidValue {
+        key: key.to_string(),
+        value: value.to_string(),
+    })
+}
+
+fn parse_or<T: FromStr>(property: Option<(&str, &str)>, default: T) -> Result<T, ConfigError> {
+    property.map_or(Ok(default), parse)
+}
+
+fn parse_list<T: FromStr>((key, value): (&str, &str)) -> Result<Vec<T>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| parse((key, item)))
+        .collect()
+}
+
+fn parse_protocol_map(
+    (key, value): (&str, &str),
+) -> Result<BTreeMap<String, SecurityProtocol>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let invalid = || ConfigError::InvalidValue {
+                key: key.to_string(),
+                value: entry.to_string(),
+            };
+            let (name, protocol) = entry.split_once(':').ok_or_else(invalid)?;
+            let protocol = protocol.trim().parse().map_err(|_| invalid())?;
+            Ok((name.trim().to_string(), protocol))
+        })
+        .collect()
+}
+
+impl Listener {
+    /// The address to bind, every interface for an empty host.
+    pub fn bind_address(&self) -> String {
+        match self.host.as_str() {
+            "" => format!("0.0.0.0:{}", self.port),
+            host if host.contains(':') => format!("[{host}]:{}", self.port),
+            host => format!("{host}:{}", self.port),
+        }
+    }
+}
+
+impl FromStr for Listener {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid listener `{s}`");
+
+        let (name, address) = s.split_once("://").ok_or_else(invalid)?;
+        let (host, port) = address.rsplit_once(':').ok_or_else(invalid)?;
+        let host = host
+            .strip_prefix('[')
+            .and_then(|host| host.strip_suffix(']'))
+            .unwrap_or(host);
+
+        Ok(Listener {
+            name: name.to_string(),
+            host: host.to_string(),
+            port: port.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl fmt::Display for Listener {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        if self.host.contains(':') {
+            write!(formatter, "{}://[{}]:{}", self.name, self.host, self.port)
+        } else {
+            write!(formatter, "{}://{}:{}", self.name, self.host, self.port)
+        }
+    }
+}
+
+impl SecurityProtocol {
+    pub fn name(self) -> &'static str {
+        match self {
+            SecurityProtocol::Plaintext => "PLAINTEXT",
+            SecurityProtocol::Ssl => "SSL",
+            SecurityProtocol::SaslPlaintext => "SASL_PLAINTEXT",
+            SecurityProtocol::SaslSsl => "SASL_SSL",
+        }
+    }
+}
+
+impl FromStr for SecurityProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "PLAINTEXT" => Ok(SecurityProtocol::Plaintext),
+            "SSL" => Ok(SecurityProtocol::Ssl),
+            "SASL_PLAINTEXT" => Ok(SecurityProtocol::SaslPlaintext),
+            "SASL_SSL" => Ok(SecurityProtocol::SaslSsl),
+            _ => Err(format!("unknown security protocol `{s}`")),
+        }
+    }
+}
+
+impl fmt::Display for SecurityProtocol {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.name())
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, error } => {
+                write!(formatter, "can't read `{}`: {error}", path.display())
+            }
+            ConfigError::InvalidValue { key, value } => {
+                write!(formatter, "invalid value `{value}` for `{key}`")
+            }
+            ConfigError::InvalidListener { name, reason } => {
+                write!(formatter, "invalid listener `{name}`: {reason}")
+            }
+            ConfigError::InvalidArgument(arg) => write!(formatter, "invalid argument `{arg}`"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use crate::records::Compression;
+
+    use super::*;
+
+    // The `server.properties` the codecrafters tester passes.
+    const SERVER_PROPERTIES: &str = "\
+process.roles=broker,controller
+node.id=1
+controller.quorum.voters=1@localhost:9093
+listeners=PLAINTEXT://:9092,CONTROLLER://:9093
+inter.broker.listener.name=PLAINTEXT
+advertised.listeners=PLAINTEXT://localhost:9092
+controller.listener.names=CONTROLLER
+listener.security.protocol.map=CONTROLLER:PLAINTEXT,PLAINTEXT:PLAINTEXT,SSL:SSL,\\
+    SASL_PLAINTEXT:SASL_PLAINTEXT,SASL_SSL:SASL_SSL
+num.io.threads=8
+socket.request.max.bytes=104857600
+log.dirs=/tmp/kraft-combined-logs
+log.retention.hours=168
+";
+
+    #[test]
+    fn test_from_properties() {
+        let config = BrokerConfig::from_properties(&parse_properties(SERVER_PROPERTIES)).unwrap();
+
+        assert_eq!(config.node_id, 1);
+        assert_eq!(
+            config.listeners,
+            vec![
+                "PLAINTEXT://:9092".parse().unwrap(),
+                "CONTROLLER://:9093".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.listeners[0].bind_address(), "0.0.0.0:9092");
+        assert_eq!(
+            config.advertised_listeners,
+            vec![Listener {
+                name: "PLAINTEXT".to_string(),
+                host: "localhost".to_string(),
+                port: 9092
+            }]
+        );
+        assert_eq!(
+            config.security_protocol("CONTROLLER"),
+            Some(SecurityProtocol::Plaintext)
+        );
+        assert_eq!(
+            config.security_protocol("SASL_SSL"),
+            Some(SecurityProtocol::SaslSsl)
+        );
+        assert_eq!(
+            config.advertised_listener("PLAINTEXT").unwrap().to_string(),
+            "PLAINTEXT://localhost:9092"
+        );
+        assert_eq!(config.advertised_listener("CONTROLLER"), None);
+        assert_eq!(config.controller_listener_names, ["CONTROLLER"]);
+        assert_eq!(
+            config
+                .broker_listeners()
+                .map(|listener| listener.name.as_str())
+                .collect::<Vec<_>>(),
+            ["PLAINTEXT"]
+        );
+        assert_eq!(
+            config.metadata_log_path(),
+            Path::new("/tmp/kraft-combined-logs/__cluster_metadata-0/00000000000000000000.log")
+        );
+        assert_eq!(config.log_retention, Some(DEFAULT_LOG_RETENTION));
+        assert_eq!(config.log_retention_bytes, None);
+        assert_eq!(
+            config.connections_max_idle,
+            Some(DEFAULT_CONNECTIONS_MAX_IDLE)
+        );
+        assert_eq!(config.num_io_threads, 8);
+
+        assert_eq!(
+            BrokerConfig::from_properties(&BTreeMap::new()).unwrap(),
+            BrokerConfig::default()
+        );
+    }
+
+    #[test]
+    fn test_from_args() {
+        let path = std::env::temp_dir().join(format!("server-{}.properties", std::process::id()));
+        fs::write(&path, SERVER_PROPERTIES).unwrap();
+
+        let args = [
+            path.to_str().unwrap(),
+            "--override",
+            "node.id=3",
+            "--override",
+            "connections.max.idle.ms=-1",
+            "--override",
+            "log.retention.ms=-1",
+            "--override",
+            "listeners=PLAINTEXT://[::1]:9094",
+            "--override",
+            "compression.type=zstd",
+        ];
+        let config = BrokerConfig::from_args(args.map(String::from));
+        fs::remove_file(&path).unwrap();
+        let config = config.unwrap();
+
+        assert_eq!(config.node_id, 3);
+        assert_eq!(config.connections_max_idle, None);
+        assert_eq!(config.log_retention, None);
+        assert_eq!(
+            config.compression_type,
+            CompressionType::Codec(Compression::Zstd)
+        );
+        assert_eq!(config.listeners[0].bind_address(), "[::1]:9094");
+        assert_eq!(config.listeners[0].to_string(), "PLAINTEXT://[::1]:9094");
+        // Still from the file.
+        assert_eq!(config.advertised_listeners[0].host, "localhost");
+
+        assert!(matches!(
+            BrokerConfig::from_args([path.to_str().unwrap().to_string()]),
+            Err(ConfigError::Io { .. })
+        ));
+        assert!(matches!(
+            BrokerConfig::from_args(["--override".to_string(), "node.id".to_string()]),
+            Err(ConfigError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            BrokerConfig::from_args(["a".to_string(), "b".to_string()]),
+            Err(ConfigError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_log_retention() {
+        let retention = |properties: &[(&str, &str)]| {
+            let properties = properties
+                .iter()
+                .map(|&(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+            let config = BrokerConfig::from_properties(&properties).unwrap();
+            (config.log_retention, config.log_retention_bytes)
+        };
+        let minutes = Duration::from_secs(60);
+
+        assert_eq!(
+            retention(&[("log.retention.hours", "2")]),
+            (Some(120 * minutes), None)
+        );
+        assert_eq!(
+            retention(&[("log.retention.hours", "2"), ("log.retention.minutes", "3")]),
+            (Some(3 * minutes), None)
+        );
+        assert_eq!(
+            retention(&[
+                ("log.retention.hours", "2"),
+                ("log.retention.minutes", "3"),
+                ("log.retention.ms", "500"),
+                ("log.retention.bytes", "1024"),
+            ]),
+            (Some(Duration::from_millis(500)), Some(1024))
+        );
+        assert_eq!(
+            retention(&[
+                ("log.retention.minutes", "-1"),
+                ("log.retention.bytes", "-1")
+            ]),
+            (None, None)
+        );
+    }
+
+    #[test]
+    fn test_invalid_values() {
+        for (key, value) in [
+            ("node.id", "one"),
+            ("listeners", "localhost:9092"),
+            ("listeners", "PLAINTEXT://:port"),
+            ("listeners", ""),
+            ("num.io.threads", "-1"),
+            ("connections.max.idle.ms", "-2"),
+            ("log.retention.ms", "forever"),
+            ("log.retention.bytes", "1GB"),
+            ("compression.type", "brotli"),
+            ("listener.security.protocol.map", "PLAINTEXT"),
+            ("listener.security.protocol.map", "PLAINTEXT:TLS"),
+        ] {
+            let properties = BTreeMap::from([(key.to_string(), value.to_string())]);
+            let error = BrokerConfig::from_properties(&properties).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("invalid value `{value}` for `{key}`")
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_listeners() {
+        for (properties, expected) in [
+            (
+                "listeners=PLAINTEXT://:9092,PLAINTEXT://:9093",
+                "invalid listener `PLAINTEXT`: duplicate name in `listeners`",
+            ),
+            (
+                "listeners=CONTROLLER://:9093",
+                "invalid listener `CONTROLLER`: no security protocol in \
+                 `listener.security.protocol.map`",
+            ),
+            (
+                "advertised.listeners=SSL://localhost:9093",
+                "invalid listener `SSL`: advertised but not in `listeners`",
+            ),
+        ] {
+            let error = BrokerConfig::from_properties(&parse_properties(properties))
+                .unwrap_err()
+                .to_string();
+            assert_eq!(error, expected);
+        }
+    }
+}
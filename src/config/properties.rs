@@ -0,0 +1,133 @@
+//! The Java properties syntax of `server.properties`: `key=value`, `key:value`
+//! or `key value` lines, `#` and `!` comments, backslash escapes and lines
+//! continued by a trailing backslash.
+
+use std::collections::BTreeMap;
+
+/// The properties in `content`, later ones replacing earlier ones with the
+/// same key.
+pub fn parse(content: &str) -> BTreeMap<String, String> {
+    let mut properties = BTreeMap::new();
+
+    let mut lines = content.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('!') {
+            continue;
+        }
+
+        let mut logical_line = String::new();
+        let mut line = line;
+        loop {
+            match line.strip_suffix('\\') {
+                // An even number of trailing backslashes are escaped ones.
+                Some(continued) if !ends_with_escape(continued) => {
+                    logical_line.push_str(continued);
+                    line = lines.next().unwrap_or_default().trim_start();
+                }
+                _ => {
+                    logical_line.push_str(line);
+                    break;
+                }
+            }
+        }
+
+        let (key, value) = split_key_value(&logical_line);
+        properties.insert(unescape(key), unescape(value));
+    }
+
+    properties
+}
+
+fn ends_with_escape(s: &str) -> bool {
+    s.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
+
+/// Splits at the first unescaped `=`, `:` or whitespace, skipping the
+/// whitespace around it.
+fn split_key_value(line: &str) -> (&str, &str) {
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' => escaped = true,
+            '=' | ':' => return (&line[..index], line[index + 1..].trim_start()),
+            c if c.is_whitespace() => {
+                let rest = line[index..].trim_start();
+                let rest = rest
+                    .strip_prefix(['=', ':'])
+                    .map_or(rest, |rest| rest.trim_start());
+                return (&line[..index], rest);
+            }
+            _ => {}
+        }
+    }
+    (line, "")
+}
+
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => unescaped.push('\t'),
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some('f') => unescaped.push('\u{c}'),
+            Some('u') => {
+                let code: String = chars.by_ref().take(4).collect();
+                match u32::from_str_radix(&code, 16).ok().and_then(char::from_u32) {
+                    Some(c) => unescaped.push(c),
+                    None => unescaped.push_str(&code),
+                }
+            }
+            Some(c) => unescaped.push(c),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let properties = parse(
+            "# comment\n\
+             ! comment\n\
+             \n\
+             node.id=1\n\
+             listeners = PLAINTEXT://:9092,\\\n    CONTROLLER://:9093\n\
+             log.dirs:/tmp/logs\n\
+             key\\ with\\=escapes value\\twith\\u0041tab\n\
+             empty\n\
+             path=C:\\\\logs\\\\\n\
+             node.id=2\n",
+        );
+
+        let expected = [
+            ("empty", ""),
+            ("key with=escapes", "value\twithAtab"),
+            ("listeners", "PLAINTEXT://:9092,CONTROLLER://:9093"),
+            ("log.dirs", "/tmp/logs"),
+            ("node.id", "2"),
+            ("path", "C:\\logs\\"),
+        ];
+        let expected: BTreeMap<_, _> = expected
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(properties, expected);
+    }
+}
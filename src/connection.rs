@@ -0,0 +1,197 @@
+//! A connection is read and answered concurrently, so clients can pipeline
+//! requests: a reader reads requests and queues them to the request channel,
+//! and a writer writes their responses in request order. At most
+//! `max_in_flight` requests are read but not yet answered.
+
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+use tokio::{
+    io::AsyncWriteExt,
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::{mpsc, watch},
+    time::{self, Instant},
+};
+
+use crate::{
+    headers::RequestHeader,
+    request_channel::{RequestChannel, ResponseReceiver},
+    serde_kafka, ClosePolicy, ConnectionConfig, Outcome, RequestContext, Response,
+};
+
+mod limits;
+
+pub(crate) use limits::{ConnectionLimits, ConnectionSlot};
+
+/// A request being handled.
+struct InFlight {
+    response: ResponseReceiver,
+    start_time: Instant,
+}
+
+pub(crate) async fn handle(
+    io: TcpStream,
+    context: RequestContext,
+    config: ConnectionConfig,
+    shutdown: watch::Receiver<()>,
+    requests: RequestChannel,
+    // Held until the connection is closed.
+    _slot: ConnectionSlot,
+) {
+    let remote_addr = context.client_address;
+    tracing::trace!(
+        "connection {remote_addr:?} accepted on {}",
+        context.listener_name
+    );
+
+    let (reader, writer) = io.into_split();
+    let (in_flight_tx, in_flight_rx) = mpsc::channel(config.max_in_flight);
+
+    // The connection closes once both are done: the writer finishes with the
+    // requests already read, and the reader stops when the writer does.
+    tokio::join!(
+        read_requests(reader, context, config, shutdown, requests, in_flight_tx),
+        write_responses(writer, remote_addr, config.close_policy, in_flight_rx),
+    );
+}
+
+async fn read_requests(
+    mut reader: OwnedReadHalf,
+    context: RequestContext,
+    config: ConnectionConfig,
+    mut shutdown: watch::Receiver<()>,
+    requests: RequestChannel,
+    in_flight: mpsc::Sender<InFlight>,
+) {
+    let remote_addr = context.client_address;
+    let mut first_byte = [0; 1];
+    loop {
+        // Waits for the next request without consuming it, so a client
+        // closing between requests isn't mistaken for a truncated one.
+        tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::debug!("connection {remote_addr:?} closing on shutdown");
+                break;
+            }
+            () = in_flight.closed() => break,
+            readable = idle_timeout(config.max_idle, reader.peek(&mut first_byte)) => {
+                match readable {
+                    Some(Ok(0)) => {
+                        tracing::trace!("connection {remote_addr:?} closed by the client");
+                        break;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => {
+                        tracing::debug!("connection {remote_addr:?} closed: {e}");
+                        break;
+                    }
+                    None => {
+                        tracing::debug!("connection {remote_addr:?} closed after being idle");
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Waits for a slot before reading, so at most `max_in_flight`
+        // requests are pending.
+        let permit = tokio::select! {
+            biased;
+            _ = shutdown.changed() => break,
+            permit = in_flight.reserve() => match permit {
+                Ok(permit) => permit,
+                Err(_) => break,
+            },
+        };
+
+        // A client that sends part of a request and stalls is as idle as
+        // one that sends nothing.
+        let start_time = Instant::now();
+        let read = idle_timeout(
+            config.max_idle,
+            serde_kafka::read_message(&mut reader, config.max_request_size),
+        );
+        let request = tokio::select! {
+            biased;
+            _ = shutdown.changed() => {
+                tracing::debug!("connection {remote_addr:?} closing on shutdown");
+                break;
+            }
+            request = read => request,
+        };
+        let (header, raw_body) = match request {
+            Some(message) => match message.and_then(|message| RequestHeader::decode(&message)) {
+                Ok(request) => request,
+                Err(e) => {
+                    tracing::debug!("connection {remote_addr:?} closed: {e}");
+                    break;
+                }
+            },
+            None => {
+                tracing::debug!("connection {remote_addr:?} closed in the middle of a request");
+                break;
+            }
+        };
+
+        // Waits while the request queue is full, so no more requests are
+        // read meanwhile.
+        let Some(response) = requests.send(context.clone(), header, raw_body).await else {
+            break;
+        };
+        permit.send(InFlight {
+            response,
+            start_time,
+        });
+    }
+}
+
+/// `future`'s output, or `None` if it takes longer than `max_idle`.
+async fn idle_timeout<F: Future>(max_idle: Option<Duration>, future: F) -> Option<F::Output> {
+    match max_idle {
+        Some(max_idle) => time::timeout(max_idle, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+async fn write_responses(
+    mut writer: OwnedWriteHalf,
+    remote_addr: SocketAddr,
+    close_policy: ClosePolicy,
+    mut in_flight: mpsc::Receiver<InFlight>,
+) {
+    while let Some(InFlight {
+        response,
+        start_time,
+    }) = in_flight.recv().await
+    {
+        let Response { message, outcome } = match response.await {
+            Ok(Ok(response)) => response,
+            Ok(Err(e)) => {
+                tracing::debug!("connection {remote_addr:?} closed: {e}");
+                break;
+            }
+            Err(_) => {
+                tracing::error!("connection {remote_addr:?} closed, handler failed");
+                break;
+            }
+        };
+
+        if outcome == Outcome::Closed {
+            tracing::debug!("connection {remote_addr:?} closed after an unanswerable request");
+            break;
+        }
+        if let Err(e) = writer.write_all(&message).await {
+            tracing::debug!("connection {remote_addr:?} closed: {e}");
+            break;
+        }
+        tracing::debug!("elapsed: {:?}", start_time.elapsed());
+
+        if outcome == Outcome::Rejected && close_policy == ClosePolicy::CloseOnError {
+            tracing::debug!("connection {remote_addr:?} closed after a rejected request");
+            break;
+        }
+    }
+}
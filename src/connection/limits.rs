@@ -0,0 +1,92 @@
+//! `max.connections` and `max.connections.per.ip`, enforced when connections
+//! are accepted.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+pub(crate) struct ConnectionLimits {
+    pub(crate) max_connections: usize,
+    pub(crate) max_connections_per_ip: usize,
+    counts: Arc<Mutex<Counts>>,
+}
+
+#[derive(Default)]
+struct Counts {
+    total: usize,
+    per_ip: HashMap<IpAddr, usize>,
+}
+
+/// Counts a connection as open until it is dropped, even if its task is
+/// aborted.
+pub(crate) struct ConnectionSlot {
+    ip: IpAddr,
+    counts: Arc<Mutex<Counts>>,
+}
+
+impl ConnectionLimits {
+    pub(crate) fn new(max_connections: usize, max_connections_per_ip: usize) -> Self {
+        ConnectionLimits {
+            max_connections,
+            max_connections_per_ip,
+            counts: Arc::default(),
+        }
+    }
+
+    /// A slot for a connection from `ip`, `None` when a limit is reached.
+    pub(crate) fn acquire(&self, ip: IpAddr) -> Option<ConnectionSlot> {
+        let mut counts = self.counts.lock().unwrap();
+        let from_ip = counts.per_ip.get(&ip).copied().unwrap_or(0);
+        if counts.total >= self.max_connections || from_ip >= self.max_connections_per_ip {
+            return None;
+        }
+
+        counts.total += 1;
+        counts.per_ip.insert(ip, from_ip + 1);
+        Some(ConnectionSlot {
+            ip,
+            counts: self.counts.clone(),
+        })
+    }
+}
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        let mut counts = self.counts.lock().unwrap();
+        counts.total -= 1;
+        if let Some(from_ip) = counts.per_ip.get_mut(&self.ip) {
+            *from_ip -= 1;
+            if *from_ip == 0 {
+                counts.per_ip.remove(&self.ip);
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn test_limits() {
+        let a = IpAddr::from(Ipv4Addr::new(10, 0, 0, 1));
+        let b = IpAddr::from(Ipv4Addr::new(10, 0, 0, 2));
+        let limits = ConnectionLimits::new(3, 2);
+
+        let a1 = limits.acquire(a).unwrap();
+        let _a2 = limits.acquire(a).unwrap();
+        assert!(limits.acquire(a).is_none());
+
+        let _b1 = limits.acquire(b).unwrap();
+        assert!(limits.acquire(b).is_none());
+
+        drop(a1);
+        assert!(limits.acquire(b).is_some());
+    }
+}
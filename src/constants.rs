@@ -1,19 +1,178 @@
+use std::ops::RangeInclusive;
+
+use serde::{Deserialize, Serialize};
 use serde_repr::{Deserialize_repr, Serialize_repr};
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Eq, Debug, Default)]
+use crate::{
+    messages::{
+        api_versions_request::ApiVersionsRequest, describe_cluster_request::DescribeClusterRequest,
+        describe_topic_partitions_request::DescribeTopicPartitionsRequest,
+        fetch_request::FetchRequest, metadata_request::MetadataRequest,
+        produce_request::ProduceRequest, request_header::RequestHeader,
+        response_header::ResponseHeader,
+    },
+    serde_kafka::Version,
+};
+
+#[derive(Serialize_repr, Deserialize_repr, Clone, Copy, PartialEq, Eq, Debug, Default)]
 #[repr(i16)]
 pub enum ErrorCode {
     #[default]
     NoError = 0,
+    OffsetOutOfRange = 1,
+    CorruptMessage = 2,
     UnknownTopic = 3,
+    NotLeaderOrFollower = 6,
+    InvalidRequiredAcks = 21,
     UnsupportedVersion = 35,
+    InvalidRequest = 42,
+    KafkaStorageError = 56,
+    FetchSessionIdNotFound = 70,
+    InvalidRecord = 87,
+    UnknownTopicId = 100,
+    MismatchedEndpointType = 114,
+    UnsupportedEndpointType = 115,
 }
 
-#[derive(Serialize_repr, Deserialize_repr, PartialEq, Eq, Debug, Default)]
-#[repr(i16)]
+/// The operations on a topic a client is authorized for: all of them, as
+/// there is no authorizer. READ, WRITE, CREATE, DELETE, ALTER, DESCRIBE,
+/// DESCRIBE_CONFIGS and ALTER_CONFIGS, each a bit of its ACL operation code.
+pub const TOPIC_AUTHORIZED_OPERATIONS: i32 = 0x0000_0df8;
+
+/// The operations on the cluster a client is authorized for, all of them:
+/// CREATE, ALTER, DESCRIBE, CLUSTER_ACTION, DESCRIBE_CONFIGS, ALTER_CONFIGS
+/// and IDEMPOTENT_WRITE.
+pub const CLUSTER_AUTHORIZED_OPERATIONS: i32 = 0x0000_1fa0;
+
+/// The API of a request. Keys this broker doesn't know are kept as
+/// `Unknown`, so the request can still be answered.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(from = "i16", into = "i16")]
 pub enum ApiKey {
+    Produce,
     #[default]
-    Fetch = 1,
-    ApiVersions = 18,
-    DescribeTopicPartitions = 75,
+    Fetch,
+    Metadata,
+    ApiVersions,
+    DescribeCluster,
+    DescribeTopicPartitions,
+    Unknown(i16),
+}
+
+impl From<i16> for ApiKey {
+    fn from(key: i16) -> Self {
+        match key {
+            0 => ApiKey::Produce,
+            1 => ApiKey::Fetch,
+            3 => ApiKey::Metadata,
+            18 => ApiKey::ApiVersions,
+            60 => ApiKey::DescribeCluster,
+            75 => ApiKey::DescribeTopicPartitions,
+            key => ApiKey::Unknown(key),
+        }
+    }
+}
+
+impl From<ApiKey> for i16 {
+    fn from(key: ApiKey) -> Self {
+        match key {
+            ApiKey::Produce => 0,
+            ApiKey::Fetch => 1,
+            ApiKey::Metadata => 3,
+            ApiKey::ApiVersions => 18,
+            ApiKey::DescribeCluster => 60,
+            ApiKey::DescribeTopicPartitions => 75,
+            ApiKey::Unknown(key) => key,
+        }
+    }
+}
+
+impl ApiKey {
+    /// The APIs this broker handles, in the order ApiVersions lists them.
+    pub const SUPPORTED: [ApiKey; 6] = [
+        ApiKey::Fetch,
+        ApiKey::ApiVersions,
+        ApiKey::DescribeTopicPartitions,
+        ApiKey::Produce,
+        ApiKey::Metadata,
+        ApiKey::DescribeCluster,
+    ];
+
+    /// `version` of this API's messages, as given by their message specs.
+    /// Unknown APIs are assumed not to be flexible.
+    pub fn version(&self, version: i16) -> Version {
+        match self {
+            ApiKey::Produce => ProduceRequest::version(version),
+            ApiKey::Fetch => FetchRequest::version(version),
+            ApiKey::Metadata => MetadataRequest::version(version),
+            ApiKey::ApiVersions => ApiVersionsRequest::version(version),
+            ApiKey::DescribeCluster => DescribeClusterRequest::version(version),
+            ApiKey::DescribeTopicPartitions => DescribeTopicPartitionsRequest::version(version),
+            ApiKey::Unknown(_) => Version {
+                version,
+                flexible: false,
+            },
+        }
+    }
+
+    /// The versions of this API the broker handles, those of its message
+    /// specs. `None` for unknown APIs.
+    pub fn supported_versions(&self) -> Option<RangeInclusive<i16>> {
+        let (lowest, highest) = match self {
+            ApiKey::Produce => (
+                ProduceRequest::LOWEST_SUPPORTED_VERSION,
+                ProduceRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::Fetch => (
+                FetchRequest::LOWEST_SUPPORTED_VERSION,
+                FetchRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::Metadata => (
+                MetadataRequest::LOWEST_SUPPORTED_VERSION,
+                MetadataRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::ApiVersions => (
+                ApiVersionsRequest::LOWEST_SUPPORTED_VERSION,
+                ApiVersionsRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::DescribeCluster => (
+                DescribeClusterRequest::LOWEST_SUPPORTED_VERSION,
+                DescribeClusterRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::DescribeTopicPartitions => (
+                DescribeTopicPartitionsRequest::LOWEST_SUPPORTED_VERSION,
+                DescribeTopicPartitionsRequest::HIGHEST_SUPPORTED_VERSION,
+            ),
+            ApiKey::Unknown(_) => return None,
+        };
+
+        Some(lowest..=highest)
+    }
+
+    /// The request header version of `version` of this API: v2 for flexible
+    /// versions, v1 otherwise, and v0 for ControlledShutdown v0, which
+    /// predates client ids.
+    pub fn request_header_version(&self, version: i16) -> Version {
+        let header_version = match self {
+            ApiKey::Unknown(7) if version == 0 => 0,
+            _ if self.version(version).flexible => 2,
+            _ => 1,
+        };
+
+        RequestHeader::version(header_version)
+    }
+
+    /// The response header version of `version` of this API: v1 for flexible
+    /// versions, v0 otherwise. ApiVersions responses always use v0, so
+    /// clients can read them before they know which versions the broker
+    /// supports.
+    pub fn response_header_version(&self, version: i16) -> Version {
+        let header_version = match self {
+            ApiKey::ApiVersions => 0,
+            _ if self.version(version).flexible => 1,
+            _ => 0,
+        };
+
+        ResponseHeader::version(header_version)
+    }
 }
@@ -1,22 +1,198 @@
-use crate::constants::ApiKey;
-use serde::{Deserialize, Serialize};
-
-#[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct RequestHeaderV2 {
-    pub api_key: ApiKey,
-    pub api_version: i16,
-    pub correlation_id: i32,
-    pub client_id: String,
-    pub tag_buffer: i8,
+//! Request and response headers. Their version follows from the API key and
+//! version of the request, see `ApiKey::request_header_version` and
+//! `ApiKey::response_header_version`, so a message is encoded in two parts: the
+//! header at its header version, then the body at the API version.
+
+use bytes::{Bytes, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{constants::ApiKey, serde_kafka};
+
+pub use crate::messages::{request_header::RequestHeader, response_header::ResponseHeader};
+
+impl RequestHeader {
+    /// Decodes the header of a request message, returning the body after it.
+    pub fn decode(message: &Bytes) -> serde_kafka::Result<(Self, Bytes)> {
+        // Every header version starts with the API key and version.
+        let ((api_key, api_version), _): ((ApiKey, i16), _) =
+            serde_kafka::from_bytes_trail(message)?;
+
+        let (header, body) = serde_kafka::from_bytes_trail_with_version(
+            message,
+            api_key.request_header_version(api_version),
+        )?;
+
+        Ok((header, message.slice_ref(body)))
+    }
+
+    pub fn api_key(&self) -> ApiKey {
+        self.request_api_key.into()
+    }
+}
+
+/// A request message of `header.api_key`, preceded by its size.
+pub fn encode_request<B>(header: &RequestHeader, body: &B) -> serde_kafka::Result<BytesMut>
+where
+    B: Serialize,
+{
+    let api_key = header.api_key();
+    let mut message = serde_kafka::to_bytes_mut_with_version(
+        header,
+        api_key.request_header_version(header.request_api_version),
+    )?;
+    message.unsplit(serde_kafka::to_bytes_mut_with_version(
+        body,
+        api_key.version(header.request_api_version),
+    )?);
+
+    serde_kafka::with_message_size(&message)
+}
+
+/// A response message to a request of `api_key` at `api_version`, preceded by
+/// its size.
+pub fn encode_response<B>(
+    header: &ResponseHeader,
+    body: &B,
+    api_key: ApiKey,
+    api_version: i16,
+) -> serde_kafka::Result<BytesMut>
+where
+    B: Serialize,
+{
+    let mut message = serde_kafka::to_bytes_mut_with_version(
+        header,
+        api_key.response_header_version(api_version),
+    )?;
+    message.unsplit(serde_kafka::to_bytes_mut_with_version(
+        body,
+        api_key.version(api_version),
+    )?);
+
+    serde_kafka::with_message_size(&message)
 }
 
-#[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ResponseHeaderV0 {
-    pub correlation_id: i32,
+/// Decodes a response message, without its size, to a request of `api_key`
+/// at `api_version`.
+pub fn decode_response<B>(
+    message: &Bytes,
+    api_key: ApiKey,
+    api_version: i16,
+) -> serde_kafka::Result<(ResponseHeader, B)>
+where
+    B: DeserializeOwned,
+{
+    let (header, body) = serde_kafka::from_bytes_trail_with_version(
+        message,
+        api_key.response_header_version(api_version),
+    )?;
+    let body = serde_kafka::from_shared_bytes_with_version(
+        &message.slice_ref(body),
+        api_key.version(api_version),
+    )?;
+
+    Ok((header, body))
 }
 
-#[derive(Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ResponseHeaderV1 {
-    pub correlation_id: i32,
-    pub tag_buffer: i8,
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use crate::messages::api_versions_response::ApiVersionsResponse;
+
+    use super::*;
+
+    #[test]
+    fn test_request_header_versions() {
+        // ApiVersions v2, header v1.
+        let message = Bytes::from_static(&[
+            0x00, 0x12, 0x00, 0x02, // api_key: 18, api_version: 2
+            0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+            0x00, 0x03, 0x63, 0x6c, 0x69, // client_id: "cli"
+            0xaa, // body
+        ]);
+        let (header, body) = RequestHeader::decode(&message).unwrap();
+        assert_eq!(header.client_id.as_deref(), Some("cli"));
+        assert_eq!(body, &[0xaa][..]);
+
+        // ApiVersions v3, header v2 with its tagged fields.
+        let message = Bytes::from_static(&[
+            0x00, 0x12, 0x00, 0x03, // api_key: 18, api_version: 3
+            0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+            0xff, 0xff, // client_id: null
+            0x00, // tagged fields
+            0xaa, // body
+        ]);
+        let (header, body) = RequestHeader::decode(&message).unwrap();
+        assert_eq!(header.client_id, None);
+        assert_eq!(body, &[0xaa][..]);
+
+        // ControlledShutdown v0, header v0 without a client id.
+        let message = Bytes::from_static(&[
+            0x00, 0x07, 0x00, 0x00, // api_key: 7, api_version: 0
+            0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+            0xaa, // body
+        ]);
+        let (header, body) = RequestHeader::decode(&message).unwrap();
+        assert_eq!(header.api_key(), ApiKey::Unknown(7));
+        assert_eq!(header.correlation_id, 7);
+        assert_eq!(body, &[0xaa][..]);
+        assert_eq!(encode_request(&header, &0xaa_u8).unwrap()[4..], message);
+
+        let header = RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 2,
+            correlation_id: 7,
+            client_id: Some("cli".into()),
+            ..Default::default()
+        };
+        let message = encode_request(&header, &0xaa_u8).unwrap();
+        assert_eq!(
+            message[4..],
+            [
+                0x00, 0x12, 0x00, 0x02, // api_key: 18, api_version: 2
+                0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+                0x00, 0x03, 0x63, 0x6c, 0x69, // client_id: "cli"
+                0xaa, // body
+            ]
+        );
+    }
+
+    #[test]
+    fn test_response_header_versions() {
+        let header = ResponseHeader {
+            correlation_id: 7,
+            ..Default::default()
+        };
+
+        // DescribeTopicPartitions is flexible, its header is v1.
+        let message =
+            encode_response(&header, &0xaa_u8, ApiKey::DescribeTopicPartitions, 0).unwrap();
+        assert_eq!(
+            message,
+            &[
+                0x00, 0x00, 0x00, 0x06, // size: 6
+                0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+                0x00, // tagged fields
+                0xaa, // body
+            ][..]
+        );
+
+        // ApiVersions responses keep header v0 in flexible versions.
+        let body = ApiVersionsResponse::default();
+        let message = encode_response(&header, &body, ApiKey::ApiVersions, 3).unwrap();
+        assert_eq!(
+            message[4..],
+            [
+                0x00, 0x00, 0x00, 0x07, // correlation_id: 7, no tagged fields
+                0x00, 0x00, // error_code: 0
+                0x01, // api_keys: compact len 0
+                0x00, 0x00, 0x00, 0x00, // throttle_time_ms: 0
+                0x00, // tagged fields
+            ]
+        );
+
+        let message = message.freeze().slice(4..);
+        let decoded = decode_response(&message, ApiKey::ApiVersions, 3).unwrap();
+        assert_eq!(decoded, (header, body));
+    }
 }
@@ -1,91 +1,595 @@
-use std::{fmt::Debug, io, net::SocketAddr};
+use std::{
+    fmt::{Debug, Display},
+    future::{self, Future},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    time::Duration,
+};
 
+use bytes::{Bytes, BytesMut};
 use serde::Serialize;
 use tokio::{
-    io::AsyncWriteExt,
     net::{TcpListener, TcpStream},
-    time::Instant,
+    sync::{mpsc, watch},
+    task::{self, JoinSet},
 };
 
+pub mod cluster_metadata;
+pub mod config;
+mod connection;
 pub mod constants;
 pub mod headers;
+pub mod messages;
 pub mod modules;
+pub mod partition_log;
+pub mod records;
+mod request_channel;
 pub mod serde_kafka;
 
 #[cfg(feature = "test-helpers")]
 pub mod test_helpers;
 
 use crate::{
-    constants::ApiKey,
-    headers::RequestHeaderV2,
-    modules::{api_versions, describe_topic_partitions},
+    cluster_metadata::ClusterMetadata,
+    config::BrokerConfig,
+    connection::ConnectionLimits,
+    constants::{ApiKey, ErrorCode},
+    headers::{RequestHeader, ResponseHeader},
+    messages::{
+        describe_cluster_response::DescribeClusterBroker, metadata_response::MetadataResponseBroker,
+    },
+    modules::{
+        api_versions, describe_cluster, describe_topic_partitions, error_response,
+        fetch::{self, Fetched},
+        metadata, produce,
+    },
+    partition_log::LogManager,
+    request_channel::RequestChannel,
 };
 
+pub use request_channel::RequestMetrics;
+
+/// Default number of requests of a connection handled at once, the default
+/// `max.in.flight.requests.per.connection` of the Java client.
+pub const DEFAULT_MAX_IN_FLIGHT_REQUESTS: usize = 5;
+
+/// Serves on `listener`, named `PLAINTEXT`, with the default `BrokerConfig`,
+/// see `Serve::config`.
 pub fn serve(listener: TcpListener) -> Serve {
-    Serve { listener }
+    serve_listeners([("PLAINTEXT", listener)])
+}
+
+/// Serves on several listeners, each named after its entry in `listeners`,
+/// so handlers can tell which one a request came through.
+pub fn serve_listeners<I, N>(listeners: I) -> Serve
+where
+    I: IntoIterator<Item = (N, TcpListener)>,
+    N: Into<Arc<str>>,
+{
+    Serve {
+        listeners: listeners
+            .into_iter()
+            .map(|(name, listener)| (name.into(), listener))
+            .collect(),
+        config: ConnectionConfig {
+            close_policy: ClosePolicy::default(),
+            max_idle: Some(config::DEFAULT_CONNECTIONS_MAX_IDLE),
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT_REQUESTS,
+            max_request_size: config::DEFAULT_SOCKET_REQUEST_MAX_BYTES,
+        },
+        limits: ConnectionLimits::new(
+            config::DEFAULT_MAX_CONNECTIONS,
+            config::DEFAULT_MAX_CONNECTIONS,
+        ),
+        io_threads: config::DEFAULT_NUM_IO_THREADS,
+        queued_max_requests: config::DEFAULT_QUEUED_MAX_REQUESTS,
+        metrics: Arc::default(),
+        broker: BrokerConfig::default(),
+        metadata: ClusterMetadata::default(),
+    }
 }
 
 pub struct Serve {
-    listener: TcpListener,
+    listeners: Vec<(Arc<str>, TcpListener)>,
+    config: ConnectionConfig,
+    limits: ConnectionLimits,
+    io_threads: usize,
+    queued_max_requests: usize,
+    metrics: Arc<RequestMetrics>,
+    broker: BrokerConfig,
+    metadata: ClusterMetadata,
+}
+
+/// A `Serve` that stops when its shutdown signal completes, see
+/// `Serve::with_graceful_shutdown`.
+pub struct WithGracefulShutdown<F> {
+    serve: Serve,
+    signal: F,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ConnectionConfig {
+    close_policy: ClosePolicy,
+    max_idle: Option<Duration>,
+    max_in_flight: usize,
+    max_request_size: usize,
+}
+
+/// Whether a connection stays open after a request was rejected with an
+/// error response. Requests whose header can't be decoded always close it,
+/// since the next request can't be found.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ClosePolicy {
+    #[default]
+    KeepOpen,
+    CloseOnError,
+}
+
+/// How a request was answered.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Handled,
+    Rejected,
+    /// Not answered, as no valid response could be, and the connection is
+    /// closed whatever the `ClosePolicy`.
+    Closed,
 }
 
 impl Serve {
-    pub async fn run(self) -> io::Result<()> {
-        let Self { listener } = self;
+    /// Applies the connection and request settings of `broker`, and hands it
+    /// to the request handlers. The settings can still be changed afterwards.
+    pub fn config(self, broker: BrokerConfig) -> Self {
+        let mut serve = self
+            .connections_max_idle(broker.connections_max_idle)
+            .socket_request_max_bytes(broker.socket_request_max_bytes)
+            .max_connections(broker.max_connections)
+            .max_connections_per_ip(broker.max_connections_per_ip)
+            .num_io_threads(broker.num_io_threads)
+            .queued_max_requests(broker.queued_max_requests);
+        serve.broker = broker;
+        serve
+    }
+
+    /// The topics and partitions requests are checked against, replayed from
+    /// the metadata log. None by default.
+    pub fn metadata(mut self, metadata: ClusterMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn close_policy(mut self, close_policy: ClosePolicy) -> Self {
+        self.config.close_policy = close_policy;
+        self
+    }
 
-        loop {
-            let (io, remote_addr) = listener.accept().await?;
+    /// How long a connection may wait for its next request before it is
+    /// closed, `connections.max.idle.ms`. `None` keeps it open.
+    pub fn connections_max_idle(mut self, max_idle: Option<Duration>) -> Self {
+        self.config.max_idle = max_idle;
+        self
+    }
+
+    /// How many pipelined requests of a connection are handled at once.
+    /// Their responses are still written in request order.
+    pub fn max_in_flight_requests(mut self, max_in_flight: usize) -> Self {
+        self.config.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// The largest request accepted, `socket.request.max.bytes`. Larger
+    /// requests close their connection before they are read.
+    pub fn socket_request_max_bytes(mut self, max_request_size: usize) -> Self {
+        self.config.max_request_size = max_request_size;
+        self
+    }
+
+    /// How many connections may be open at once, `max.connections`. Excess
+    /// connections are closed as soon as they are accepted.
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.limits.max_connections = max_connections;
+        self
+    }
+
+    /// How many connections may be open at once from the same IP address,
+    /// `max.connections.per.ip`.
+    pub fn max_connections_per_ip(mut self, max_connections_per_ip: usize) -> Self {
+        self.limits.max_connections_per_ip = max_connections_per_ip;
+        self
+    }
 
-            handle_connection(io, remote_addr).await;
+    /// How many workers handle requests, `num.io.threads`.
+    pub fn num_io_threads(mut self, io_threads: usize) -> Self {
+        self.io_threads = io_threads.max(1);
+        self
+    }
+
+    /// How many requests of all connections wait for a worker before
+    /// connections stop reading, `queued.max.requests`.
+    pub fn queued_max_requests(mut self, queued_max_requests: usize) -> Self {
+        self.queued_max_requests = queued_max_requests.max(1);
+        self
+    }
+
+    pub fn metrics(&self) -> Arc<RequestMetrics> {
+        self.metrics.clone()
+    }
+
+    /// Stops serving when `signal` completes: no more connections are
+    /// accepted, and open ones are closed once their in-flight request is
+    /// answered.
+    pub fn with_graceful_shutdown<F>(self, signal: F) -> WithGracefulShutdown<F>
+    where
+        F: Future<Output = ()>,
+    {
+        WithGracefulShutdown {
+            serve: self,
+            signal,
         }
     }
+
+    pub async fn run(self) -> io::Result<()> {
+        run(self, future::pending()).await
+    }
+}
+
+impl<F> WithGracefulShutdown<F>
+where
+    F: Future<Output = ()>,
+{
+    pub async fn run(self) -> io::Result<()> {
+        run(self.serve, self.signal).await
+    }
 }
 
-async fn handle_connection(mut io: TcpStream, remote_addr: SocketAddr) {
-    tokio::spawn(async move {
-        tracing::trace!("connection {remote_addr:?} accepted");
+async fn run(serve: Serve, signal: impl Future<Output = ()>) -> io::Result<()> {
+    let Serve {
+        listeners,
+        config,
+        limits,
+        io_threads,
+        queued_max_requests,
+        metrics,
+        broker,
+        metadata,
+    } = serve;
+
+    let log_dir = broker.log_dirs.first().cloned().unwrap_or_default();
+    // A produced batch can't decompress to more than the largest request.
+    let max_records_size = config.max_request_size;
+    let logs = task::spawn_blocking(move || LogManager::load(log_dir, max_records_size))
+        .await
+        .map_err(io::Error::other)??;
+    let broker = Arc::new(Broker {
+        config: broker,
+        metadata,
+        logs,
+    });
+    let (requests, mut workers) =
+        RequestChannel::start(queued_max_requests, io_threads, metrics, broker);
+
+    let (accepted_tx, mut accepted_rx) = mpsc::channel(1);
+    let mut acceptors = JoinSet::new();
+    for (name, listener) in listeners {
+        acceptors.spawn(accept(name, listener, accepted_tx.clone()));
+    }
+    drop(accepted_tx);
 
-        loop {
-            let start_time = Instant::now();
+    // Dropped to tell connections to close.
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
+    let mut connections = JoinSet::new();
 
-            handle_package(&mut io, start_time).await;
+    tokio::pin!(signal);
+    loop {
+        tokio::select! {
+            Some(accepted) = accepted_rx.recv() => {
+                let (io, context) = accepted?;
+                let remote_addr = context.client_address;
+                match limits.acquire(remote_addr.ip()) {
+                    Some(slot) => {
+                        let shutdown = shutdown_rx.clone();
+                        let requests = requests.clone();
+                        connections.spawn(connection::handle(
+                            io,
+                            context,
+                            config,
+                            shutdown,
+                            requests,
+                            slot,
+                        ));
+                    }
+                    None => tracing::debug!("connection {remote_addr:?} refused, too many connections"),
+                }
+            }
+            // Reaps finished connections.
+            Some(_) = connections.join_next() => {}
+            () = &mut signal => break,
         }
-    });
-}
+    }
 
-async fn handle_package(io: &mut TcpStream, start_time: Instant) {
-    let (header, raw_body): (RequestHeaderV2, Vec<u8>) =
-        serde_kafka::from_async_reader_trail_with_message_size(io)
-            .await
-            .unwrap();
+    tracing::debug!("shutting down, {} connections open", connections.len());
+    acceptors.shutdown().await;
+    drop(shutdown_tx);
+    while connections.join_next().await.is_some() {}
+    drop(requests);
+    while workers.join_next().await.is_some() {}
 
-    tracing::debug!("header: {:?}", header);
+    Ok(())
+}
+
+/// Accepts the connections of the listener named `name` until accepting
+/// fails or nothing receives them anymore.
+async fn accept(
+    name: Arc<str>,
+    listener: TcpListener,
+    accepted: mpsc::Sender<io::Result<(TcpStream, RequestContext)>>,
+) {
+    loop {
+        let connection = listener.accept().await.and_then(|(io, client_address)| {
+            let context = RequestContext {
+                listener_name: name.clone(),
+                client_address,
+                local_address: io.local_addr()?,
+            };
+            Ok((io, context))
+        });
 
-    match header.api_key {
-        ApiKey::ApiVersions => {
-            send_response(io, api_versions::handler(&header, raw_body), start_time).await
+        let failed = connection.is_err();
+        if accepted.send(connection).await.is_err() || failed {
+            break;
         }
+    }
+}
+
+/// What the request handlers share.
+#[derive(Debug)]
+struct Broker {
+    config: BrokerConfig,
+    metadata: ClusterMetadata,
+    logs: LogManager,
+}
+
+/// The connection a request came through.
+#[derive(Debug, Clone)]
+struct RequestContext {
+    listener_name: Arc<str>,
+    client_address: SocketAddr,
+    local_address: SocketAddr,
+}
+
+/// A response framed with its size, ready to be written. Empty for requests
+/// that aren't answered.
+struct Response {
+    message: BytesMut,
+    outcome: Outcome,
+}
+
+/// How a request is answered: right away, or once a delayed operation like a
+/// long-polling fetch completes, which then runs outside the workers.
+enum Reply {
+    Now(serde_kafka::Result<Response>),
+    Delayed(Pin<Box<dyn Future<Output = serde_kafka::Result<Response>> + Send>>),
+}
+
+/// Handles a request whose body was read whole using the message size, so
+/// requests that can't be handled are skipped and the next one can still be
+/// read.
+async fn handle_request(
+    broker: &Arc<Broker>,
+    context: &RequestContext,
+    header: &RequestHeader,
+    raw_body: Bytes,
+) -> Reply {
+    tracing::debug!(
+        "node {} listener {} header: {:?}",
+        broker.config.node_id,
+        context.listener_name,
+        header
+    );
+
+    // ApiVersions answers the versions it doesn't support itself, with the
+    // versions it does.
+    let api_key = header.api_key();
+    let unsupported = api_key != ApiKey::ApiVersions
+        && api_key
+            .supported_versions()
+            .is_some_and(|versions| !versions.contains(&header.request_api_version));
+    if unsupported {
+        return Reply::Now(reject(
+            header,
+            ErrorCode::UnsupportedVersion,
+            "version not supported",
+        ));
+    }
+
+    let response = match api_key {
+        ApiKey::ApiVersions => match api_versions::handler(header, raw_body) {
+            Ok(body) => {
+                // Unsupported versions are answered with v0, which every
+                // client can parse.
+                let version = match body.error_code {
+                    code if code == ErrorCode::UnsupportedVersion as i16 => 0,
+                    _ => header.request_api_version,
+                };
+
+                encode_response(&response_header(header), &body, header.api_key(), version)
+            }
+            Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+        },
         ApiKey::DescribeTopicPartitions => {
-            send_response(
-                io,
-                describe_topic_partitions::handler(&header, raw_body),
-                start_time,
-            )
-            .await
+            match describe_topic_partitions::handler(
+                header,
+                raw_body,
+                broker.config.node_id,
+                &broker.metadata,
+            ) {
+                Ok(body) => encode_response(
+                    &response_header(header),
+                    &body,
+                    header.api_key(),
+                    header.request_api_version,
+                ),
+                Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+            }
+        }
+        ApiKey::Produce => match produce::handler(
+            header,
+            raw_body,
+            &broker.config,
+            &broker.metadata,
+            &broker.logs,
+        )
+        .await
+        {
+            Ok(Some(body)) => encode_response(
+                &response_header(header),
+                &body,
+                header.api_key(),
+                header.request_api_version,
+            ),
+            Ok(None) => Ok(Response {
+                message: BytesMut::new(),
+                outcome: Outcome::Handled,
+            }),
+            Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+        },
+        ApiKey::Fetch => match fetch::handler(
+            header,
+            raw_body,
+            broker.config.node_id,
+            &broker.metadata,
+            &broker.logs,
+        )
+        .await
+        {
+            Ok(Fetched::Complete(body)) => encode_response(
+                &response_header(header),
+                &body,
+                header.api_key(),
+                header.request_api_version,
+            ),
+            Ok(Fetched::Delayed(fetch)) => {
+                let broker = broker.clone();
+                let response_header = response_header(header);
+                let api_version = header.request_api_version;
+
+                return Reply::Delayed(Box::pin(async move {
+                    let body = fetch
+                        .complete(broker.config.node_id, &broker.metadata, &broker.logs)
+                        .await;
+                    encode_response(&response_header, &body, ApiKey::Fetch, api_version)
+                }));
+            }
+            Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+        },
+        ApiKey::Metadata => {
+            let (host, port) = advertised_address(&broker.config, context);
+            let advertised = MetadataResponseBroker {
+                node_id: broker.config.node_id,
+                host: host.into(),
+                port: port.into(),
+                ..Default::default()
+            };
+            match metadata::handler(header, raw_body, advertised, &broker.metadata) {
+                Ok(body) => encode_response(
+                    &response_header(header),
+                    &body,
+                    header.api_key(),
+                    header.request_api_version,
+                ),
+                Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+            }
+        }
+        ApiKey::DescribeCluster => {
+            let (host, port) = advertised_address(&broker.config, context);
+            let advertised = DescribeClusterBroker {
+                broker_id: broker.config.node_id,
+                host: host.into(),
+                port: port.into(),
+                ..Default::default()
+            };
+            match describe_cluster::handler(header, raw_body, advertised, &broker.metadata) {
+                Ok(body) => encode_response(
+                    &response_header(header),
+                    &body,
+                    header.api_key(),
+                    header.request_api_version,
+                ),
+                Err(e) => reject(header, ErrorCode::InvalidRequest, e),
+            }
         }
-        _ => panic!("Api key not found"),
+        ApiKey::Unknown(_) => reject(header, ErrorCode::UnsupportedVersion, "API not supported"),
     };
+
+    Reply::Now(response)
 }
 
-async fn send_response<I, S>(io: &mut I, response: S, start_time: Instant)
+/// The address clients of the listener `context` came through reach this
+/// broker at: its advertised listener, completed with the address the client
+/// connected to when it leaves the host out.
+fn advertised_address(config: &BrokerConfig, context: &RequestContext) -> (String, u16) {
+    let local_host = context.local_address.ip().to_string();
+    match config.advertised_listener(&context.listener_name) {
+        Some(listener) if !listener.host.is_empty() => (listener.host.clone(), listener.port),
+        Some(listener) => (local_host, listener.port),
+        None => (local_host, context.local_address.port()),
+    }
+}
+
+fn response_header(header: &RequestHeader) -> ResponseHeader {
+    ResponseHeader {
+        correlation_id: header.correlation_id,
+        ..Default::default()
+    }
+}
+
+/// Answers a request that can't be handled with `error_code`. The response
+/// is an error code alone, which only the ApiVersions response, and those of
+/// APIs the client can't expect a shape of, are. Requests of other APIs
+/// close the connection instead, as Kafka does with requests it can't parse.
+fn reject(
+    header: &RequestHeader,
+    error_code: ErrorCode,
+    reason: impl Display,
+) -> serde_kafka::Result<Response> {
+    tracing::debug!("rejected {:?} request: {reason}", header.api_key());
+
+    if !matches!(header.api_key(), ApiKey::ApiVersions | ApiKey::Unknown(_)) {
+        return Ok(Response {
+            message: BytesMut::new(),
+            outcome: Outcome::Closed,
+        });
+    }
+
+    let response = error_response::handler(header, error_code);
+    let Response { message, .. } = encode_response(
+        &response.header,
+        &response.body,
+        header.api_key(),
+        header.request_api_version,
+    )?;
+
+    Ok(Response {
+        message,
+        outcome: Outcome::Rejected,
+    })
+}
+
+fn encode_response<B>(
+    header: &ResponseHeader,
+    body: &B,
+    api_key: ApiKey,
+    api_version: i16,
+) -> serde_kafka::Result<Response>
 where
-    I: AsyncWriteExt + Unpin,
-    S: Serialize + Debug,
+    B: Serialize + Debug,
 {
-    serde_kafka::to_async_writer_with_message_size(io, &response)
-        .await
-        .unwrap();
+    let message = headers::encode_response(header, body, api_key, api_version)?;
+    tracing::debug!("response: {:?} {:?}", header, body);
 
-    let elapsed_time = start_time.elapsed();
-    tracing::debug!("response: {:?} elapsed: {:?}", response, elapsed_time);
+    Ok(Response {
+        message,
+        outcome: Outcome::Handled,
+    })
 }
@@ -1,27 +1,72 @@
-use std::{env, io, net::SocketAddr, str::FromStr};
+use std::{env, fs, io, process};
 
-use tokio::{
-    fs::{self},
-    net::TcpListener,
+use codecrafters_kafka::{
+    cluster_metadata::ClusterMetadata,
+    config::{self, BrokerConfig, SecurityProtocol},
 };
+use tokio::net::TcpListener;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     init_tracing();
 
-    let content =
-        fs::read("/tmp/kraft-combined-logs/__cluster_metadata-0/00000000000000000000.log")
-            .await
-            .unwrap();
-    tracing::info!("{content:?}");
+    let config = match BrokerConfig::from_args(env::args().skip(1)) {
+        Ok(config) => config,
+        Err(e) => {
+            tracing::error!("{e}");
+            process::exit(1);
+        }
+    };
+    tracing::debug!("{config:?}");
 
-    let addr = SocketAddr::from_str("127.0.0.1:9092").unwrap();
+    let metadata_log_path = config.metadata_log_path();
+    let mut metadata = ClusterMetadata::load(&metadata_log_path)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::warn!("can't load {}: {e}", metadata_log_path.display());
+            ClusterMetadata::default()
+        });
 
-    let listener = TcpListener::bind(&addr).await.unwrap();
-    tracing::info!("Server listening on {addr}");
+    let meta_properties_path = config.meta_properties_path();
+    match fs::read_to_string(&meta_properties_path) {
+        Ok(content) => {
+            if let Some(cluster_id) = config::parse_properties(&content).remove("cluster.id") {
+                metadata = metadata.with_cluster_id(cluster_id);
+            }
+        }
+        Err(e) => tracing::warn!("can't read {}: {e}", meta_properties_path.display()),
+    }
 
-    codecrafters_kafka::serve(listener).run().await?;
+    // The controller's listeners are left to it, as this broker isn't one.
+    let mut listeners = Vec::new();
+    for listener in config.broker_listeners() {
+        // TLS and SASL aren't supported, so those listeners aren't opened
+        // rather than served in plaintext.
+        let protocol = config.security_protocol(&listener.name);
+        if let Some(protocol) = protocol.filter(|&p| p != SecurityProtocol::Plaintext) {
+            tracing::warn!("listener {listener} skipped, {protocol} isn't supported");
+            continue;
+        }
+
+        let addr = listener.bind_address();
+        listeners.push((listener.name.clone(), TcpListener::bind(&addr).await?));
+        tracing::info!("Server listening on {addr} for {}", listener.name);
+    }
+    if listeners.is_empty() {
+        tracing::error!("no PLAINTEXT listener to serve on");
+        process::exit(1);
+    }
+
+    codecrafters_kafka::serve_listeners(listeners)
+        .config(config)
+        .metadata(metadata)
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+            tracing::info!("Shutting down");
+        })
+        .run()
+        .await?;
 
     Ok(())
 }
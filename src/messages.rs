@@ -0,0 +1,135 @@
+//! Message types generated by `build.rs` from the Kafka JSON message specs in
+//! `messages/`. Supporting a new API starts with dropping its request and
+//! response specs there.
+
+include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use crate::serde_kafka::{from_bytes_with_version, to_bytes_mut_with_version};
+
+    use super::{
+        api_versions_response::{ApiVersion, ApiVersionsResponse},
+        describe_topic_partitions_request::{Cursor, DescribeTopicPartitionsRequest},
+        request_header::RequestHeader,
+    };
+
+    #[test]
+    fn test_api_versions_response() {
+        let response = ApiVersionsResponse {
+            api_keys: vec![ApiVersion {
+                api_key: 18,
+                min_version: 0,
+                max_version: 4,
+                ..Default::default()
+            }],
+            throttle_time_ms: 1,
+            ..Default::default()
+        };
+
+        let v0 = ApiVersionsResponse::version(0);
+        let expected: &[u8] = &[
+            0x00, 0x00, // error_code: 0
+            0x00, 0x00, 0x00, 0x01, // api_keys: INT32 len 1
+            0x00, 0x12, 0x00, 0x00, 0x00, 0x04, // api_key: 18, min: 0, max: 4
+        ];
+        let bytes = to_bytes_mut_with_version(&response, v0).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            from_bytes_with_version::<ApiVersionsResponse>(&bytes, v0).unwrap(),
+            ApiVersionsResponse {
+                throttle_time_ms: 0,
+                ..response.clone()
+            }
+        );
+
+        let v3 = ApiVersionsResponse::version(3);
+        let expected: &[u8] = &[
+            0x00, 0x00, // error_code: 0
+            0x02, // api_keys: compact len 1
+            0x00, 0x12, 0x00, 0x00, 0x00, 0x04, // api_key: 18, min: 0, max: 4
+            0x00, // api_keys tagged fields
+            0x00, 0x00, 0x00, 0x01, // throttle_time_ms: 1
+            0x00, // tagged fields, finalized_features_epoch is left out at -1
+        ];
+        let bytes = to_bytes_mut_with_version(&response, v3).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            from_bytes_with_version::<ApiVersionsResponse>(&bytes, v3).unwrap(),
+            response
+        );
+    }
+
+    #[test]
+    fn test_nullable_struct() {
+        let version = DescribeTopicPartitionsRequest::version(0);
+
+        let request = DescribeTopicPartitionsRequest::default();
+        let expected: &[u8] = &[
+            0x01, // topics: compact len 0
+            0x00, 0x00, 0x07, 0xd0, // response_partition_limit: 2000
+            0xff, // cursor: null
+            0x00, // tagged fields
+        ];
+        let bytes = to_bytes_mut_with_version(&request, version).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            from_bytes_with_version::<DescribeTopicPartitionsRequest>(&bytes, version).unwrap(),
+            request
+        );
+
+        let request = DescribeTopicPartitionsRequest {
+            cursor: Some(Cursor {
+                topic_name: "foo".into(),
+                partition_index: 1,
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let expected: &[u8] = &[
+            0x01, // topics: compact len 0
+            0x00, 0x00, 0x07, 0xd0, // response_partition_limit: 2000
+            0x01, // cursor: present
+            0x04, 0x66, 0x6f, 0x6f, // topic_name: "foo"
+            0x00, 0x00, 0x00, 0x01, // partition_index: 1
+            0x00, // cursor tagged fields
+            0x00, // tagged fields
+        ];
+        let bytes = to_bytes_mut_with_version(&request, version).unwrap();
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            from_bytes_with_version::<DescribeTopicPartitionsRequest>(&bytes, version).unwrap(),
+            request
+        );
+    }
+
+    #[test]
+    fn test_request_header() {
+        let header = RequestHeader {
+            request_api_key: 18,
+            request_api_version: 4,
+            correlation_id: 7,
+            client_id: Some("cli".into()),
+            ..Default::default()
+        };
+
+        let expected: &[u8] = &[
+            0x00, 0x12, 0x00, 0x04, // request_api_key: 18, request_api_version: 4
+            0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+            0x00, 0x03, 0x63, 0x6c, 0x69, // client_id: INT16 len, "cli"
+        ];
+        let bytes = to_bytes_mut_with_version(&header, RequestHeader::version(1)).unwrap();
+        assert_eq!(bytes, expected);
+
+        let expected: &[u8] = &[
+            0x00, 0x12, 0x00, 0x04, // request_api_key: 18, request_api_version: 4
+            0x00, 0x00, 0x00, 0x07, // correlation_id: 7
+            0x00, 0x03, 0x63, 0x6c, 0x69, // client_id keeps its INT16 length
+            0x00, // tagged fields
+        ];
+        let bytes = to_bytes_mut_with_version(&header, RequestHeader::version(2)).unwrap();
+        assert_eq!(bytes, expected);
+    }
+}
@@ -1,3 +1,8 @@
 pub mod api_versions;
+pub mod describe_cluster;
 pub mod describe_topic_partitions;
+pub mod error_response;
+pub mod fetch;
+pub mod metadata;
 pub mod metadata_log_file;
+pub mod produce;
@@ -1,48 +1,48 @@
+use bytes::Bytes;
+
 use crate::{
     constants::{ApiKey, ErrorCode},
-    headers::{RequestHeaderV2, ResponseHeaderV0},
-    modules::api_versions::payloads::{
-        ApiVersion, ApiVersionsRequestBody, ApiVersionsResponse, ApiVersionsResponseBody,
+    headers::RequestHeader,
+    messages::{
+        api_versions_request::ApiVersionsRequest,
+        api_versions_response::{ApiVersion, ApiVersionsResponse},
     },
     serde_kafka,
 };
 
-pub fn handler(header: &RequestHeaderV2, raw_body: Vec<u8>) -> ApiVersionsResponse {
-    let _body: ApiVersionsRequestBody = serde_kafka::from_bytes(&raw_body).unwrap();
+/// Advertises the versions of every API this broker handles, those of their
+/// message specs. A version of ApiVersions itself it doesn't handle is
+/// answered with the versions of ApiVersions it does, so the client can retry
+/// with one (KIP-511).
+pub fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+) -> serde_kafka::Result<ApiVersionsResponse> {
+    let supported = ApiKey::ApiVersions.supported_versions().unwrap();
+    if !supported.contains(&header.request_api_version) {
+        return Ok(ApiVersionsResponse {
+            error_code: ErrorCode::UnsupportedVersion as i16,
+            api_keys: vec![api_version(ApiKey::ApiVersions)],
+            ..ApiVersionsResponse::default()
+        });
+    }
+
+    let version = ApiKey::ApiVersions.version(header.request_api_version);
+    let _body: ApiVersionsRequest =
+        serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    Ok(ApiVersionsResponse {
+        api_keys: ApiKey::SUPPORTED.into_iter().map(api_version).collect(),
+        ..ApiVersionsResponse::default()
+    })
+}
 
-    match header.api_version {
-        0..=4 => ApiVersionsResponse {
-            header: ResponseHeaderV0 {
-                correlation_id: header.correlation_id,
-            },
-            body: ApiVersionsResponseBody {
-                api_versions: vec![
-                    ApiVersion {
-                        api_key: ApiKey::Fetch,
-                        max_supported_api_version: 17,
-                        ..ApiVersion::default()
-                    },
-                    ApiVersion {
-                        api_key: ApiKey::ApiVersions,
-                        max_supported_api_version: 4,
-                        ..ApiVersion::default()
-                    },
-                    ApiVersion {
-                        api_key: ApiKey::DescribeTopicPartitions,
-                        ..ApiVersion::default()
-                    },
-                ],
-                ..ApiVersionsResponseBody::default()
-            },
-        },
-        _ => ApiVersionsResponse {
-            header: ResponseHeaderV0 {
-                correlation_id: header.correlation_id,
-            },
-            body: ApiVersionsResponseBody {
-                error_code: ErrorCode::UnsupportedVersion,
-                ..ApiVersionsResponseBody::default()
-            },
-        },
+fn api_version(api_key: ApiKey) -> ApiVersion {
+    let versions = api_key.supported_versions().unwrap();
+    ApiVersion {
+        api_key: api_key.into(),
+        min_version: *versions.start(),
+        max_version: *versions.end(),
+        ..ApiVersion::default()
     }
 }
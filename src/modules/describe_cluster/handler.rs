@@ -0,0 +1,57 @@
+use bytes::Bytes;
+
+use crate::{
+    cluster_metadata::ClusterMetadata,
+    constants::{ApiKey, ErrorCode, CLUSTER_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        describe_cluster_request::DescribeClusterRequest,
+        describe_cluster_response::{DescribeClusterBroker, DescribeClusterResponse},
+    },
+    serde_kafka,
+};
+
+/// The `endpoint_type` of requests for the brokers of the cluster.
+const BROKER_ENDPOINT_TYPE: i8 = 1;
+/// The `endpoint_type` of requests for the controllers of the cluster.
+const CONTROLLER_ENDPOINT_TYPE: i8 = 2;
+
+/// Describes the cluster: `broker`, its only broker, which is also its
+/// controller. Controllers are described by controller listeners, which this
+/// broker doesn't serve.
+pub fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+    broker: DescribeClusterBroker,
+    metadata: &ClusterMetadata,
+) -> serde_kafka::Result<DescribeClusterResponse> {
+    let version = ApiKey::DescribeCluster.version(header.request_api_version);
+    let body: DescribeClusterRequest =
+        serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    let error_code = match body.endpoint_type {
+        BROKER_ENDPOINT_TYPE => ErrorCode::NoError,
+        CONTROLLER_ENDPOINT_TYPE => ErrorCode::MismatchedEndpointType,
+        _ => ErrorCode::UnsupportedEndpointType,
+    };
+    if error_code != ErrorCode::NoError {
+        return Ok(DescribeClusterResponse {
+            error_code: error_code as i16,
+            endpoint_type: body.endpoint_type,
+            ..Default::default()
+        });
+    }
+
+    let cluster_authorized_operations = match body.include_cluster_authorized_operations {
+        true => CLUSTER_AUTHORIZED_OPERATIONS,
+        false => i32::MIN,
+    };
+
+    Ok(DescribeClusterResponse {
+        cluster_id: metadata.cluster_id().unwrap_or_default().into(),
+        controller_id: broker.broker_id,
+        brokers: vec![broker],
+        cluster_authorized_operations,
+        ..Default::default()
+    })
+}
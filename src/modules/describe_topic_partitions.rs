@@ -1,4 +1,3 @@
 mod handler;
-pub mod payloads;
 
 pub use handler::handler;
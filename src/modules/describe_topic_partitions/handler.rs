@@ -1,34 +1,134 @@
-use uuid::Uuid;
+use std::collections::BTreeSet;
+
+use bytes::Bytes;
 
 use crate::{
-    constants::ErrorCode,
-    headers::{RequestHeaderV2, ResponseHeaderV1},
-    modules::describe_topic_partitions::payloads::{
-        DescribeTopicPartitionsRequestBody, DescribeTopicPartitionsResponse,
-        DescribeTopicPartitionsResponseBody, TopicResponse,
+    cluster_metadata::{ClusterMetadata, TopicMetadata},
+    constants::{ApiKey, ErrorCode, TOPIC_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        describe_topic_partitions_request::DescribeTopicPartitionsRequest,
+        describe_topic_partitions_response::{
+            Cursor, DescribeTopicPartitionsResponse, DescribeTopicPartitionsResponsePartition,
+            DescribeTopicPartitionsResponseTopic,
+        },
     },
     serde_kafka,
 };
 
-pub fn handler(header: &RequestHeaderV2, raw_body: Vec<u8>) -> DescribeTopicPartitionsResponse {
-    let body: DescribeTopicPartitionsRequestBody = serde_kafka::from_bytes(&raw_body).unwrap();
+/// Most partitions a response describes, whatever the request's
+/// `response_partition_limit`, Kafka's default
+/// `max.request.partition.size.limit`.
+pub const MAX_RESPONSE_PARTITION_LIMIT: i32 = 2000;
 
-    DescribeTopicPartitionsResponse {
-        header: ResponseHeaderV1 {
-            correlation_id: header.correlation_id,
-            ..Default::default()
-        },
-        body: DescribeTopicPartitionsResponseBody {
-            topics: vec![TopicResponse {
-                error_code: ErrorCode::UnknownTopic,
-                name: body.topics[0].name.clone(),
-                uuid: Uuid::nil(),
-                partitions_array: 1,
-                authorized_operations: 0x00_00_0d_f8,
+/// Describes the requested topics, or all of them when none are, by name.
+/// Once `response_partition_limit` partitions are described, the response
+/// ends with a cursor at the next partition, which the client sends back to
+/// get the rest.
+pub fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+    node_id: i32,
+    metadata: &ClusterMetadata,
+) -> serde_kafka::Result<DescribeTopicPartitionsResponse> {
+    let version = ApiKey::DescribeTopicPartitions.version(header.request_api_version);
+    let body: DescribeTopicPartitionsRequest =
+        serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    let names: BTreeSet<&str> = match body.topics.is_empty() {
+        true => metadata.topics().map(|topic| topic.name.as_str()).collect(),
+        false => body
+            .topics
+            .iter()
+            .map(|topic| topic.name.as_str())
+            .collect(),
+    };
+    let (first_topic, first_partition) = match &body.cursor {
+        Some(cursor) => (cursor.topic_name.as_str(), cursor.partition_index),
+        None => ("", 0),
+    };
+
+    let mut remaining = body
+        .response_partition_limit
+        .clamp(1, MAX_RESPONSE_PARTITION_LIMIT) as usize;
+    let mut topics = Vec::new();
+    let mut next_cursor = None;
+    for &name in names.range(first_topic..) {
+        let first_partition = match name == first_topic {
+            true => first_partition,
+            false => 0,
+        };
+        if remaining == 0 {
+            next_cursor = Some(cursor(name, first_partition));
+            break;
+        }
+
+        let Some(topic) = metadata.topic(name) else {
+            topics.push(DescribeTopicPartitionsResponseTopic {
+                error_code: ErrorCode::UnknownTopic as i16,
+                name: Some(name.into()),
                 ..Default::default()
-            }],
-            next_cursor: 0xff,
-            ..Default::default()
-        },
+            });
+            continue;
+        };
+
+        let mut partitions = topic
+            .partitions
+            .range(first_partition..)
+            .map(|(&index, _)| index);
+        let described = describe(topic, partitions.by_ref().take(remaining), node_id);
+        remaining -= described.partitions.len();
+        topics.push(described);
+
+        if let Some(index) = partitions.next() {
+            next_cursor = Some(cursor(name, index));
+            break;
+        }
+    }
+
+    Ok(DescribeTopicPartitionsResponse {
+        topics,
+        next_cursor,
+        ..Default::default()
+    })
+}
+
+/// `topic` with its `partitions`. Only `node_id` is alive, so the replicas on
+/// other brokers are offline.
+fn describe(
+    topic: &TopicMetadata,
+    partitions: impl Iterator<Item = i32>,
+    node_id: i32,
+) -> DescribeTopicPartitionsResponseTopic {
+    DescribeTopicPartitionsResponseTopic {
+        name: Some(topic.name.as_str().into()),
+        topic_id: topic.id,
+        is_internal: topic.is_internal(),
+        partitions: partitions
+            .map(|index| {
+                let partition = &topic.partitions[&index];
+                DescribeTopicPartitionsResponsePartition {
+                    partition_index: index,
+                    leader_id: partition.leader,
+                    leader_epoch: partition.leader_epoch,
+                    replica_nodes: partition.replicas.clone(),
+                    isr_nodes: partition.in_sync_replicas.clone(),
+                    eligible_leader_replicas: Some(partition.eligible_leader_replicas.clone()),
+                    last_known_elr: Some(partition.last_known_elr.clone()),
+                    offline_replicas: partition.offline_replicas(node_id),
+                    ..Default::default()
+                }
+            })
+            .collect(),
+        topic_authorized_operations: TOPIC_AUTHORIZED_OPERATIONS,
+        ..Default::default()
+    }
+}
+
+fn cursor(topic_name: &str, partition_index: i32) -> Cursor {
+    Cursor {
+        topic_name: topic_name.into(),
+        partition_index,
+        ..Default::default()
     }
 }
@@ -0,0 +1,4 @@
+mod handler;
+pub mod payloads;
+
+pub use handler::handler;
@@ -0,0 +1,15 @@
+use crate::{
+    constants::ErrorCode,
+    headers::{RequestHeader, ResponseHeader},
+    modules::error_response::payloads::{ErrorResponse, ErrorResponseBody},
+};
+
+pub fn handler(header: &RequestHeader, error_code: ErrorCode) -> ErrorResponse {
+    ErrorResponse {
+        header: ResponseHeader {
+            correlation_id: header.correlation_id,
+            ..Default::default()
+        },
+        body: ErrorResponseBody { error_code },
+    }
+}
@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::ErrorCode, headers::ResponseHeader};
+
+/// The response to an ApiVersions request this broker can't handle, or to a
+/// request of an unknown API. It has the shape of a v0 `ApiVersions` error
+/// response, a header and an error code.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    pub header: ResponseHeader,
+    pub body: ErrorResponseBody,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorResponseBody {
+    pub error_code: ErrorCode,
+}
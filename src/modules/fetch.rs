@@ -0,0 +1,3 @@
+mod handler;
+
+pub use handler::{handler, DelayedFetch, Fetched};
@@ -0,0 +1,265 @@
+use std::io;
+
+use bytes::Bytes;
+use tokio::{
+    task,
+    time::{self, Duration, Instant},
+};
+
+use crate::{
+    cluster_metadata::{ClusterMetadata, TopicMetadata},
+    constants::{ApiKey, ErrorCode},
+    headers::RequestHeader,
+    messages::{
+        fetch_request::{FetchPartition, FetchRequest, FetchTopic},
+        fetch_response::{FetchResponse, FetchableTopicResponse, LeaderIdAndEpoch, PartitionData},
+    },
+    partition_log::{Appends, LogManager},
+    serde_kafka,
+};
+
+/// First version naming topics by id rather than by name.
+const TOPIC_ID_VERSION: i16 = 13;
+
+pub enum Fetched {
+    Complete(FetchResponse),
+    Delayed(DelayedFetch),
+}
+
+/// A fetch that didn't get `min_bytes` of records yet, answered once enough
+/// are appended or its `max_wait_ms` runs out, like the fetches Kafka parks
+/// in its purgatory.
+pub struct DelayedFetch {
+    request: FetchRequest,
+    api_version: i16,
+    deadline: Instant,
+    appended: Appends,
+}
+
+/// Reads the records of each partition from its log, on blocking threads.
+/// Sessions aren't kept, so every fetch is a full one.
+pub async fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+    node_id: i32,
+    metadata: &ClusterMetadata,
+    logs: &LogManager,
+) -> serde_kafka::Result<Fetched> {
+    let version = ApiKey::Fetch.version(header.request_api_version);
+    let request: FetchRequest = serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    if request.session_id != 0 {
+        return Ok(Fetched::Complete(FetchResponse {
+            error_code: ErrorCode::FetchSessionIdNotFound as i16,
+            ..Default::default()
+        }));
+    }
+
+    let api_version = header.request_api_version;
+    let (response, complete) = fetch(&request, api_version, node_id, metadata, logs).await;
+    if complete || request.max_wait_ms <= 0 {
+        return Ok(Fetched::Complete(response));
+    }
+
+    // Only appends to the partitions fetched wake the fetch. They're read
+    // again once subscribed to, so appends made meanwhile aren't missed.
+    let appended = logs.subscribe(request.topics.iter().flat_map(|fetch_topic| {
+        let topic = topic(fetch_topic, api_version, metadata);
+        fetch_topic
+            .partitions
+            .iter()
+            .filter_map(move |partition| Some((topic?.name.as_str(), partition.partition)))
+    }));
+    let (response, complete) = fetch(&request, api_version, node_id, metadata, logs).await;
+    if complete {
+        return Ok(Fetched::Complete(response));
+    }
+
+    Ok(Fetched::Delayed(DelayedFetch {
+        deadline: Instant::now() + Duration::from_millis(request.max_wait_ms as u64),
+        request,
+        api_version,
+        appended,
+    }))
+}
+
+impl DelayedFetch {
+    /// Reads the partitions again after each append to them, until enough
+    /// records were appended or the deadline passes.
+    pub async fn complete(
+        self,
+        node_id: i32,
+        metadata: &ClusterMetadata,
+        logs: &LogManager,
+    ) -> FetchResponse {
+        while time::timeout_at(self.deadline, self.appended.notified())
+            .await
+            .is_ok()
+        {
+            let (response, complete) =
+                fetch(&self.request, self.api_version, node_id, metadata, logs).await;
+            if complete {
+                return response;
+            }
+        }
+
+        fetch(&self.request, self.api_version, node_id, metadata, logs)
+            .await
+            .0
+    }
+}
+
+/// The response to `request`, and whether it's complete: it has `min_bytes`
+/// of records or a partition failed.
+async fn fetch(
+    request: &FetchRequest,
+    api_version: i16,
+    node_id: i32,
+    metadata: &ClusterMetadata,
+    logs: &LogManager,
+) -> (FetchResponse, bool) {
+    let max_bytes = request.max_bytes.max(0) as usize;
+    let mut bytes = 0;
+    let mut failed = false;
+
+    let mut responses = Vec::new();
+    for fetch_topic in &request.topics {
+        let topic = topic(fetch_topic, api_version, metadata);
+        let unknown_topic = if api_version >= TOPIC_ID_VERSION {
+            ErrorCode::UnknownTopicId
+        } else {
+            ErrorCode::UnknownTopic
+        };
+
+        let mut partitions = Vec::new();
+        for partition in &fetch_topic.partitions {
+            let data = match topic {
+                // KIP-74: the first batch is returned even if it's larger
+                // than the limits.
+                Some(topic) => {
+                    read(
+                        topic,
+                        partition,
+                        node_id,
+                        logs,
+                        max_bytes.saturating_sub(bytes),
+                        bytes == 0,
+                    )
+                    .await
+                }
+                None => error(partition.partition, unknown_topic),
+            };
+
+            bytes += data.records.as_ref().map_or(0, Bytes::len);
+            failed |= data.error_code != ErrorCode::NoError as i16;
+            partitions.push(data);
+        }
+
+        responses.push(FetchableTopicResponse {
+            topic: fetch_topic.topic.clone(),
+            topic_id: fetch_topic.topic_id,
+            partitions,
+            ..Default::default()
+        });
+    }
+
+    let complete = failed || bytes >= request.min_bytes.max(0) as usize;
+    let response = FetchResponse {
+        responses,
+        ..Default::default()
+    };
+
+    (response, complete)
+}
+
+/// The topic `fetch_topic` names, by id since `TOPIC_ID_VERSION`.
+fn topic<'a>(
+    fetch_topic: &FetchTopic,
+    api_version: i16,
+    metadata: &'a ClusterMetadata,
+) -> Option<&'a TopicMetadata> {
+    if api_version >= TOPIC_ID_VERSION {
+        metadata.topic_by_id(fetch_topic.topic_id)
+    } else {
+        metadata.topic(&fetch_topic.topic)
+    }
+}
+
+/// Reads `partition` of `topic`, which this broker must lead, up to the
+/// smaller of its `partition_max_bytes` and `max_bytes`.
+async fn read(
+    topic: &TopicMetadata,
+    partition: &FetchPartition,
+    node_id: i32,
+    logs: &LogManager,
+    max_bytes: usize,
+    min_one: bool,
+) -> PartitionData {
+    let index = partition.partition;
+    let Some(partition_metadata) = topic.partitions.get(&index) else {
+        return error(index, ErrorCode::UnknownTopic);
+    };
+    if partition_metadata.leader != node_id {
+        return PartitionData {
+            current_leader: LeaderIdAndEpoch {
+                leader_id: partition_metadata.leader,
+                leader_epoch: partition_metadata.leader_epoch,
+                ..Default::default()
+            },
+            ..error(index, ErrorCode::NotLeaderOrFollower)
+        };
+    }
+
+    // Partitions nothing was appended to have no log yet, and reading them
+    // doesn't create one. The log is only held to find the batches to read.
+    let max_bytes = max_bytes.min(partition.partition_max_bytes.max(0) as usize);
+    let (log_start_offset, high_watermark, slice) = match logs.existing_log(&topic.name, index) {
+        Some(log) => {
+            let log = log.lock().unwrap();
+            // Without followers, everything appended is committed.
+            let slice = log.slice(partition.fetch_offset, max_bytes, min_one);
+            (log.log_start_offset(), log.next_offset(), Some(slice))
+        }
+        None => (0, 0, None),
+    };
+
+    let offsets = PartitionData {
+        high_watermark,
+        last_stable_offset: high_watermark,
+        log_start_offset,
+        ..error(index, ErrorCode::NoError)
+    };
+    if !(log_start_offset..=high_watermark).contains(&partition.fetch_offset) {
+        return PartitionData {
+            error_code: ErrorCode::OffsetOutOfRange as i16,
+            ..offsets
+        };
+    }
+
+    let records = match slice {
+        Some(slice) => task::spawn_blocking(move || slice.read())
+            .await
+            .unwrap_or_else(|e| Err(io::Error::other(e))),
+        None => Ok(Bytes::new()),
+    };
+    match records {
+        Ok(records) => PartitionData {
+            records: Some(records),
+            ..offsets
+        },
+        Err(e) => {
+            tracing::warn!("can't read the log of {}-{index}: {e}", topic.name);
+            error(index, ErrorCode::KafkaStorageError)
+        }
+    }
+}
+
+fn error(index: i32, error_code: ErrorCode) -> PartitionData {
+    PartitionData {
+        partition_index: index,
+        error_code: error_code as i16,
+        high_watermark: -1,
+        records: Some(Bytes::new()),
+        ..Default::default()
+    }
+}
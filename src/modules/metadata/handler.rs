@@ -0,0 +1,104 @@
+use bytes::Bytes;
+
+use crate::{
+    cluster_metadata::{ClusterMetadata, TopicMetadata},
+    constants::{ApiKey, ErrorCode, TOPIC_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        metadata_request::MetadataRequest,
+        metadata_response::{
+            MetadataResponse, MetadataResponseBroker, MetadataResponsePartition,
+            MetadataResponseTopic,
+        },
+    },
+    serde_kafka,
+};
+
+/// Describes the requested topics, or all of them, and `broker`, the only
+/// broker of the cluster, which is also its controller.
+///
+/// Topics aren't created here, so unknown topics are reported as such even
+/// when `allow_auto_topic_creation` is set, as Kafka does with
+/// `auto.create.topics.enable=false`.
+pub fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+    broker: MetadataResponseBroker,
+    metadata: &ClusterMetadata,
+) -> serde_kafka::Result<MetadataResponse> {
+    let version = ApiKey::Metadata.version(header.request_api_version);
+    let body: MetadataRequest = serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    let authorized_operations = match body.include_topic_authorized_operations {
+        true => TOPIC_AUTHORIZED_OPERATIONS,
+        false => i32::MIN,
+    };
+    let node_id = broker.node_id;
+    let describe = |topic| describe(topic, node_id, authorized_operations);
+
+    let topics = match body.topics {
+        // v0 asks for every topic with an empty array rather than null.
+        Some(topics) if !(topics.is_empty() && header.request_api_version == 0) => topics
+            .into_iter()
+            .map(|requested| {
+                let topic = match &requested.name {
+                    Some(name) => metadata.topic(name),
+                    None => metadata.topic_by_id(requested.topic_id),
+                };
+
+                match (topic, requested.name) {
+                    (Some(topic), _) => describe(topic),
+                    (None, Some(name)) => MetadataResponseTopic {
+                        error_code: ErrorCode::UnknownTopic as i16,
+                        name: Some(name),
+                        ..Default::default()
+                    },
+                    (None, None) => MetadataResponseTopic {
+                        error_code: ErrorCode::UnknownTopicId as i16,
+                        name: None,
+                        topic_id: requested.topic_id,
+                        ..Default::default()
+                    },
+                }
+            })
+            .collect(),
+        _ => metadata.topics().map(describe).collect(),
+    };
+
+    Ok(MetadataResponse {
+        brokers: vec![broker],
+        cluster_id: metadata.cluster_id().map(Into::into),
+        controller_id: node_id,
+        topics,
+        ..Default::default()
+    })
+}
+
+/// The partitions of `topic`. Only `node_id` is alive, so the replicas on
+/// other brokers are offline.
+fn describe(
+    topic: &TopicMetadata,
+    node_id: i32,
+    authorized_operations: i32,
+) -> MetadataResponseTopic {
+    MetadataResponseTopic {
+        name: Some(topic.name.as_str().into()),
+        topic_id: topic.id,
+        is_internal: topic.is_internal(),
+        partitions: topic
+            .partitions
+            .iter()
+            .map(|(&index, partition)| MetadataResponsePartition {
+                partition_index: index,
+                leader_id: partition.leader,
+                leader_epoch: partition.leader_epoch,
+                replica_nodes: partition.replicas.clone(),
+                isr_nodes: partition.in_sync_replicas.clone(),
+                offline_replicas: partition.offline_replicas(node_id),
+                ..Default::default()
+            })
+            .collect(),
+        topic_authorized_operations: authorized_operations,
+        ..Default::default()
+    }
+}
@@ -1,40 +1,141 @@
+use std::path::Path;
+
 use tokio::fs;
 
 use crate::{modules::metadata_log_file::payloads::MetadataLogFile, serde_kafka};
 
-pub async fn load() {
-    // let metadata_topic_file_path =
-    //     "/tmp/kraft-combined-logs/__cluster_metadata-0/00000000000000000000.log";
-    let metadata_topic_file_path = "./log.log";
-    let content = fs::read(metadata_topic_file_path).await.unwrap();
-
-    let metadata: Vec<MetadataLogFile> = serde_kafka::from_bytes(&content).unwrap();
+pub async fn load(path: impl AsRef<Path>) -> serde_kafka::Result<Vec<MetadataLogFile>> {
+    let content = fs::read(path).await?;
 
+    let metadata = parse(&content)?;
     tracing::debug!("{metadata:?}");
+
+    Ok(metadata)
+}
+
+/// Parses the record batches of a log file, which follow each other without
+/// a count.
+pub fn parse(mut content: &[u8]) -> serde_kafka::Result<Vec<MetadataLogFile>> {
+    let mut batches = Vec::new();
+    while !content.is_empty() {
+        let (batch, rest) = serde_kafka::from_bytes_trail(content)?;
+        batches.push(batch);
+        content = rest;
+    }
+
+    Ok(batches)
 }
 
 #[cfg(test)]
 mod test {
     use uuid::Uuid;
 
-    use crate::modules::metadata_log_file::payloads::{Record, RecordValue};
+    use crate::{
+        modules::metadata_log_file::payloads::RecordValue,
+        records::Record,
+        serde_kafka::{ErrorKind, TaggedFields, VarInt},
+    };
 
     use super::*;
 
     #[tokio::test]
-    async fn test_parse() {
-        load().await;
+    async fn test_load() {
+        let path = std::env::temp_dir().join(format!("metadata-{}.log", std::process::id()));
+        fs::write(&path, LOG).await.unwrap();
+
+        let metadata = load(&path).await;
+        fs::remove_file(&path).await.unwrap();
+
+        assert_eq!(metadata.unwrap(), metadata_log());
+        assert!(load(&path).await.is_err());
     }
 
     #[test]
-    fn test_ser_de_empty() {
-        let map: (MetadataLogFile, MetadataLogFile) = (
+    fn test_ser_de() {
+        let metadata = metadata_log();
+
+        let bytes = serde_kafka::to_bytes_mut(&(&metadata[0], &metadata[1])).unwrap();
+        assert_eq!(LOG, bytes.to_vec());
+
+        assert_eq!(metadata, parse(LOG).unwrap());
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut log = LOG.to_vec();
+        // name of the first record.
+        log[72] = b'M';
+
+        let error = parse(&log).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::InvalidCrc { .. }));
+        assert_eq!(error.offset(), Some(17));
+    }
+
+    #[test]
+    fn test_unknown_value_type() {
+        let mut log = LOG.to_vec();
+        // value_type of the first record, with the batch CRC updated.
+        log[68] = 0x7f;
+        let crc = crc32c::crc32c(&log[21..91]);
+        log[17..21].copy_from_slice(&crc.to_be_bytes());
+
+        let metadata = parse(&log).unwrap();
+        let error = RecordValue::from_record(&metadata[0].records[0]).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::UnknownDiscriminant {
+                name: "RecordValue",
+                value: 0x7f
+            }
+        ));
+        assert_eq!(error.offset(), Some(0));
+    }
+
+    #[test]
+    fn test_record_values() {
+        let metadata = metadata_log();
+        let values: Vec<_> = metadata
+            .iter()
+            .flat_map(|batch| &batch.records)
+            .map(|record| RecordValue::from_record(record).unwrap())
+            .collect();
+
+        assert!(matches!(
+            &values[0],
+            RecordValue::FeatureLevelValue {
+                feature_level: 20,
+                ..
+            }
+        ));
+        assert!(matches!(
+            &values[1],
+            RecordValue::TopicRecordValue { topic_name, .. } if topic_name.as_str() == "saz"
+        ));
+        assert!(matches!(
+            &values[3],
+            RecordValue::PartitionRecordValue {
+                partition_id: 1,
+                ..
+            }
+        ));
+
+        let record = Record::default();
+        assert!(RecordValue::from_record(&record).is_err());
+    }
+
+    fn record(offset_delta: i32, value: RecordValue) -> Record {
+        Record {
+            offset_delta: VarInt(offset_delta),
+            value: Some(serde_kafka::to_bytes_mut(&value).unwrap().freeze()),
+            ..Record::default()
+        }
+    }
+
+    fn metadata_log() -> Vec<MetadataLogFile> {
+        vec![
             MetadataLogFile {
                 base_offset: 0,
-                batch_length: 79,
                 partition_leader_epoch: 1,
-                magic_byte: 2,
-                crc: -1335278212,
                 attributes: 0,
                 last_offset_delta: 0,
                 base_timestamp: 1726045943832,
@@ -42,29 +143,21 @@ mod test {
                 producer_id: -1,
                 producer_epoch: -1,
                 base_sequence: -1,
-                records: vec![Record {
-                    length: 29,
-                    attributes: 0,
-                    timestamp_delta: 0,
-                    offset_delta: 0,
-                    keys: vec![],
-                    value: RecordValue::FeatureLevelValue {
+                records: vec![record(
+                    0,
+                    RecordValue::FeatureLevelValue {
                         frame_version: 1,
                         value_type: 12,
-                        version: 1,
-                        name: "metadata-version".into(),
+                        version: 0,
+                        name: "metadata.version".into(),
                         feature_level: 20,
-                        tagged_fields_count: 0,
+                        tagged_fields: TaggedFields::default(),
                     },
-                    headers_array_count: 0,
-                }],
+                )],
             },
             MetadataLogFile {
                 base_offset: 1,
-                batch_length: 228,
                 partition_leader_epoch: 1,
-                magic_byte: 2,
-                crc: 618336989,
                 attributes: 0,
                 last_offset_delta: 2,
                 base_timestamp: 1726045957397,
@@ -73,13 +166,9 @@ mod test {
                 producer_epoch: -1,
                 base_sequence: -1,
                 records: vec![
-                    Record {
-                        length: 30,
-                        attributes: 0,
-                        timestamp_delta: 0,
-                        offset_delta: 0,
-                        keys: vec![],
-                        value: RecordValue::TopicRecordValue {
+                    record(
+                        0,
+                        RecordValue::TopicRecordValue {
                             frame_version: 1,
                             value_type: 2,
                             version: 0,
@@ -88,21 +177,16 @@ mod test {
                                 0x00u8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00,
                                 0x00, 0x00, 0x00, 0x00, 0x91,
                             ]),
-                            tagged_fields_count: 0,
+                            tagged_fields: TaggedFields::default(),
                         },
-                        headers_array_count: 0,
-                    },
-                    Record {
-                        length: 72,
-                        attributes: 0,
-                        timestamp_delta: 0,
-                        offset_delta: 1,
-                        keys: vec![],
-                        value: RecordValue::PartitionRecordValue {
+                    ),
+                    record(
+                        1,
+                        RecordValue::PartitionRecordValue {
                             frame_version: 1,
                             value_type: 3,
                             version: 1,
-                            partition_id: 1,
+                            partition_id: 0,
                             topic_uuid: Uuid::from_bytes([
                                 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00,
                                 0x00, 0x00, 0x00, 0x00, 0x91,
@@ -118,17 +202,14 @@ mod test {
                                 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00,
                                 0x00, 0x00, 0x00, 0x00, 0x01,
                             ])],
-                            tagged_fields_count: 0,
+                            tagged_fields: TaggedFields::default(),
+                            eligible_leader_replicas: None,
+                            last_known_elr: None,
                         },
-                        headers_array_count: 0,
-                    },
-                    Record {
-                        length: 72,
-                        attributes: 0,
-                        timestamp_delta: 0,
-                        offset_delta: 2,
-                        keys: vec![],
-                        value: RecordValue::PartitionRecordValue {
+                    ),
+                    record(
+                        2,
+                        RecordValue::PartitionRecordValue {
                             frame_version: 1,
                             value_type: 3,
                             version: 1,
@@ -148,42 +229,39 @@ mod test {
                                 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00,
                                 0x00, 0x00, 0x00, 0x00, 0x01,
                             ])],
-                            tagged_fields_count: 0,
+                            tagged_fields: TaggedFields::default(),
+                            eligible_leader_replicas: None,
+                            last_known_elr: None,
                         },
-                        headers_array_count: 0,
-                    },
+                    ),
                 ],
             },
-        );
-
-        let result: Vec<u8> = vec![
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4f, 0x00, 0x00,
-            0x00, 0x01, 0x02, 0xb0, 0x69, 0x45, 0x7c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x01, 0x91, 0xe0, 0x5a, 0xf8, 0x18, 0x00, 0x00, 0x01, 0x91, 0xe0, 0x5a, 0xf8,
-            0x18, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-            0xff, 0x00, 0x00, 0x00, 0x01, 0x3a, 0x00, 0x00, 0x00, 0x01, 0x2e, 0x01, 0x0c, 0x00,
-            0x11, 0x6d, 0x65, 0x74, 0x61, 0x64, 0x61, 0x74, 0x61, 0x2e, 0x76, 0x65, 0x72, 0x73,
-            0x69, 0x6f, 0x6e, 0x00, 0x14, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x01, 0x00, 0x00, 0x00, 0xe4, 0x00, 0x00, 0x00, 0x01, 0x02, 0x24, 0xdb, 0x12, 0xdd,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x01, 0x91, 0xe0, 0x5b, 0x2d, 0x15,
-            0x00, 0x00, 0x01, 0x91, 0xe0, 0x5b, 0x2d, 0x15, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
-            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x03, 0x3c, 0x00,
-            0x00, 0x00, 0x01, 0x30, 0x01, 0x02, 0x00, 0x04, 0x73, 0x61, 0x7a, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x91, 0x00,
-            0x00, 0x90, 0x01, 0x00, 0x00, 0x02, 0x01, 0x82, 0x01, 0x01, 0x03, 0x01, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x91, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x01,
-            0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
-            0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x01, 0x00, 0x00, 0x90, 0x01, 0x00, 0x00, 0x04, 0x01, 0x82, 0x01, 0x01,
-            0x03, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00,
-            0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x91, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02,
-            0x00, 0x00, 0x00, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00,
-            0x00, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80,
-            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
-        ];
-
-        let wat = serde_kafka::to_bytes_mut(&map).unwrap();
-        assert_eq!(result, wat.to_vec())
+        ]
     }
+
+    const LOG: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x4f, 0x00, 0x00, 0x00,
+        0x01, 0x02, 0xb0, 0x69, 0x45, 0x7c, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x91, 0xe0, 0x5a, 0xf8, 0x18, 0x00, 0x00, 0x01, 0x91, 0xe0, 0x5a, 0xf8, 0x18, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x01, 0x3a, 0x00, 0x00, 0x00, 0x01, 0x2e, 0x01, 0x0c, 0x00, 0x11, 0x6d, 0x65, 0x74, 0x61,
+        0x64, 0x61, 0x74, 0x61, 0x2e, 0x76, 0x65, 0x72, 0x73, 0x69, 0x6f, 0x6e, 0x00, 0x14, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xe4, 0x00, 0x00,
+        0x00, 0x01, 0x02, 0x24, 0xdb, 0x12, 0xdd, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+        0x01, 0x91, 0xe0, 0x5b, 0x2d, 0x15, 0x00, 0x00, 0x01, 0x91, 0xe0, 0x5b, 0x2d, 0x15, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00,
+        0x00, 0x03, 0x3c, 0x00, 0x00, 0x00, 0x01, 0x30, 0x01, 0x02, 0x00, 0x04, 0x73, 0x61, 0x7a,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x91, 0x00, 0x00, 0x90, 0x01, 0x00, 0x00, 0x02, 0x01, 0x82, 0x01, 0x01, 0x03, 0x01, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x91, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x01, 0x01,
+        0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x10,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        0x00, 0x00, 0x90, 0x01, 0x00, 0x00, 0x04, 0x01, 0x82, 0x01, 0x01, 0x03, 0x01, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x91, 0x02, 0x00, 0x00, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x10, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00,
+        0x00,
+    ];
 }
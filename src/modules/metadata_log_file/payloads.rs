@@ -1,59 +1,45 @@
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::serde_kafka::CompactString;
+use crate::{
+    records::{Record, RecordBatch},
+    serde_kafka::{self, nullable, uuid_as_bytes, CompactString, TaggedFields, Version},
+};
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct MetadataLogFile {
-    pub base_offset: i64,
-    pub batch_length: i32,
-    pub partition_leader_epoch: i32,
-    pub magic_byte: u8,
-    pub crc: i32,
-    pub attributes: i16,
-    pub last_offset_delta: i32,
-    pub base_timestamp: i64,
-    pub max_timestamp: i64,
-    pub producer_id: i64,
-    pub producer_epoch: i16,
-    pub base_sequence: i32,
-    pub records: Vec<Record>,
-}
-
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
-pub struct Record {
-    pub length: i8,
-    pub attributes: i8,
-    pub timestamp_delta: i8,
-    pub offset_delta: i8,
-    pub keys: Vec<i8>,
-    pub value: RecordValue,
-    pub headers_array_count: u8,
-}
+/// A record batch of the `__cluster_metadata` log, whose record values are
+/// `RecordValue`s.
+pub type MetadataLogFile = RecordBatch<Record>;
 
+/// A metadata record, selected by its `value_type`.
 #[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename = "RecordValue;discriminant=1")]
 pub enum RecordValue {
+    #[serde(rename = "FeatureLevelValue;value=12")]
     FeatureLevelValue {
         frame_version: i8,
         value_type: i8,
         version: i8,
         name: CompactString,
         feature_level: i16,
-        tagged_fields_count: u8,
+        tagged_fields: TaggedFields,
     },
+    #[serde(rename = "TopicRecordValue;value=2")]
     TopicRecordValue {
         frame_version: i8,
         value_type: i8,
         version: i8,
         topic_name: CompactString,
+        #[serde(with = "uuid_as_bytes")]
         topic_uuid: Uuid,
-        tagged_fields_count: u8,
+        tagged_fields: TaggedFields,
     },
+    #[serde(rename = "PartitionRecordValue;value=3")]
     PartitionRecordValue {
         frame_version: i8,
         value_type: i8,
         version: i8,
         partition_id: i32,
+        #[serde(with = "uuid_as_bytes")]
         topic_uuid: Uuid,
         replicas: Vec<i32>,
         in_sync_replicas: Vec<i32>,
@@ -62,7 +48,104 @@ pub enum RecordValue {
         leader: i32,
         leader_epoch: i32,
         partition_epoch: i32,
+        #[serde(
+            rename = "directories;versions=1+",
+            default,
+            with = "uuid_as_bytes::vec"
+        )]
         directories: Vec<Uuid>,
-        tagged_fields_count: u8,
+        tagged_fields: TaggedFields,
+        #[serde(rename = "eligible_leader_replicas;versions=2+;tag=1", default)]
+        #[serde(with = "nullable", skip_serializing_if = "Option::is_none")]
+        eligible_leader_replicas: Option<Vec<i32>>,
+        #[serde(rename = "last_known_elr;versions=2+;tag=2", default)]
+        #[serde(with = "nullable", skip_serializing_if = "Option::is_none")]
+        last_known_elr: Option<Vec<i32>>,
+    },
+    #[serde(rename = "ConfigRecordValue;value=4")]
+    ConfigRecordValue {
+        frame_version: i8,
+        value_type: i8,
+        version: i8,
+        resource_type: i8,
+        resource_name: CompactString,
+        name: CompactString,
+        #[serde(with = "nullable")]
+        value: Option<CompactString>,
+        tagged_fields: TaggedFields,
     },
+    /// The fields of a partition that changed, those left out are unchanged.
+    #[serde(rename = "PartitionChangeRecordValue;value=5")]
+    PartitionChangeRecordValue {
+        frame_version: i8,
+        value_type: i8,
+        version: i8,
+        partition_id: i32,
+        #[serde(with = "uuid_as_bytes")]
+        topic_uuid: Uuid,
+        tagged_fields: TaggedFields,
+        #[serde(rename = "in_sync_replicas;tag=0", default, with = "nullable")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        in_sync_replicas: Option<Vec<i32>>,
+        /// -1 when the partition has no leader, -2 when unchanged.
+        #[serde(rename = "leader;tag=1", default = "no_leader_change")]
+        #[serde(skip_serializing_if = "is_no_leader_change")]
+        leader: i32,
+        #[serde(rename = "replicas;tag=2", default, with = "nullable")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        replicas: Option<Vec<i32>>,
+        #[serde(rename = "eligible_leader_replicas;versions=2+;tag=6", default)]
+        #[serde(with = "nullable", skip_serializing_if = "Option::is_none")]
+        eligible_leader_replicas: Option<Vec<i32>>,
+        #[serde(rename = "last_known_elr;versions=2+;tag=7", default)]
+        #[serde(with = "nullable", skip_serializing_if = "Option::is_none")]
+        last_known_elr: Option<Vec<i32>>,
+        #[serde(rename = "directories;versions=1+;tag=8", default)]
+        #[serde(with = "uuid_as_bytes::nullable_vec")]
+        #[serde(skip_serializing_if = "Option::is_none")]
+        directories: Option<Vec<Uuid>>,
+    },
+    #[serde(rename = "RemoveTopicRecordValue;value=9")]
+    RemoveTopicRecordValue {
+        frame_version: i8,
+        value_type: i8,
+        version: i8,
+        #[serde(with = "uuid_as_bytes")]
+        topic_uuid: Uuid,
+        tagged_fields: TaggedFields,
+    },
+}
+
+/// The `leader` of a `PartitionChangeRecordValue` that leaves it unchanged.
+pub const NO_LEADER_CHANGE: i32 = -2;
+
+fn no_leader_change() -> i32 {
+    NO_LEADER_CHANGE
+}
+
+fn is_no_leader_change(leader: &i32) -> bool {
+    *leader == NO_LEADER_CHANGE
+}
+
+impl RecordValue {
+    /// Decodes the value of a metadata log record, at the version it was
+    /// written with.
+    pub fn from_record(record: &Record) -> serde_kafka::Result<Self> {
+        let value = record
+            .value
+            .as_deref()
+            .ok_or_else(|| serde_kafka::Error::message("Metadata record without a value"))?;
+
+        let version = *value
+            .get(2)
+            .ok_or_else(|| serde_kafka::Error::message("Metadata record without a version"))?;
+
+        serde_kafka::from_bytes_with_version(
+            value,
+            Version {
+                version: version.into(),
+                flexible: true,
+            },
+        )
+    }
 }
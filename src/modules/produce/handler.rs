@@ -0,0 +1,142 @@
+use bytes::Bytes;
+
+use crate::{
+    cluster_metadata::ClusterMetadata,
+    config::BrokerConfig,
+    constants::{ApiKey, ErrorCode},
+    headers::RequestHeader,
+    messages::{
+        produce_request::{PartitionProduceData, ProduceRequest},
+        produce_response::{
+            LeaderIdAndEpoch, PartitionProduceResponse, ProduceResponse, TopicProduceResponse,
+        },
+    },
+    partition_log::{AppendError, LogManager},
+    records::CompressionType,
+    serde_kafka,
+};
+
+/// Appends the record batch of each partition to its log. `None` when the
+/// producer asked for no acknowledgement, `acks=0`, so no response is sent.
+pub async fn handler(
+    header: &RequestHeader,
+    raw_body: Bytes,
+    config: &BrokerConfig,
+    metadata: &ClusterMetadata,
+    logs: &LogManager,
+) -> serde_kafka::Result<Option<ProduceResponse>> {
+    let version = ApiKey::Produce.version(header.request_api_version);
+    let body: ProduceRequest = serde_kafka::from_shared_bytes_with_version(&raw_body, version)?;
+
+    let acks = body.acks;
+    let mut responses = Vec::with_capacity(body.topic_data.len());
+    for topic in body.topic_data {
+        let mut partition_responses = Vec::with_capacity(topic.partition_data.len());
+        for partition in topic.partition_data {
+            partition_responses.push(match acks {
+                -1..=1 => produce(&topic.name, partition, acks, config, metadata, logs).await,
+                _ => error(partition.index, ErrorCode::InvalidRequiredAcks),
+            });
+        }
+
+        responses.push(TopicProduceResponse {
+            name: topic.name,
+            partition_responses,
+            ..Default::default()
+        });
+    }
+
+    if acks == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(ProduceResponse {
+        responses,
+        ..Default::default()
+    }))
+}
+
+/// Appends the records of `partition` of `topic`, which this broker must lead,
+/// compressed with the topic's `compression.type`, or the broker's.
+/// With `acks=-1` they are also written through to the disk, the only replica.
+async fn produce(
+    topic: &str,
+    partition: PartitionProduceData,
+    acks: i16,
+    config: &BrokerConfig,
+    metadata: &ClusterMetadata,
+    logs: &LogManager,
+) -> PartitionProduceResponse {
+    let index = partition.index;
+    let (Some(topic_metadata), Some(partition_metadata)) =
+        (metadata.topic(topic), metadata.partition(topic, index))
+    else {
+        return error(index, ErrorCode::UnknownTopic);
+    };
+    if partition_metadata.leader != config.node_id {
+        return PartitionProduceResponse {
+            current_leader: LeaderIdAndEpoch {
+                leader_id: partition_metadata.leader,
+                leader_epoch: partition_metadata.leader_epoch,
+                ..Default::default()
+            },
+            ..error(index, ErrorCode::NotLeaderOrFollower)
+        };
+    }
+    let Some(records) = partition.records else {
+        return error(index, ErrorCode::InvalidRecord);
+    };
+
+    let compression_type = match topic_metadata.config("compression.type") {
+        Some(value) => match value.parse::<CompressionType>() {
+            Ok(compression_type) => compression_type,
+            Err(e) => {
+                tracing::warn!("{topic}: invalid compression.type: {e}");
+                config.compression_type
+            }
+        },
+        None => config.compression_type,
+    };
+
+    let append = logs
+        .append(
+            topic,
+            index,
+            records,
+            partition_metadata.leader_epoch,
+            compression_type,
+            acks == -1,
+        )
+        .await;
+
+    match append {
+        Ok(info) => PartitionProduceResponse {
+            index,
+            base_offset: info.base_offset,
+            log_start_offset: info.log_start_offset,
+            ..Default::default()
+        },
+        Err(e) => {
+            tracing::debug!("produce to {topic}-{index} failed: {e}");
+            let error_code = match e {
+                AppendError::InvalidRecord(_) => ErrorCode::InvalidRecord,
+                AppendError::CorruptMessage(_) => ErrorCode::CorruptMessage,
+                AppendError::Io(_) => ErrorCode::KafkaStorageError,
+            };
+
+            PartitionProduceResponse {
+                error_message: Some(e.to_string().into()),
+                ..error(index, error_code)
+            }
+        }
+    }
+}
+
+fn error(index: i32, error_code: ErrorCode) -> PartitionProduceResponse {
+    PartitionProduceResponse {
+        index,
+        error_code: error_code as i16,
+        base_offset: -1,
+        ..Default::default()
+    }
+}
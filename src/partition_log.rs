@@ -0,0 +1,808 @@
+//! Partition logs: the record batches of each partition, appended to a
+//! segment file in `<log dir>/<topic>-<partition>/`, named after the offset of
+//! its first batch as Kafka names segments.
+//!
+//! Batches are stored as they were produced, only their base offset and
+//! partition leader epoch are set, which the CRC doesn't cover, unless the
+//! topic's `compression.type` names another codec than the producer's: those
+//! are recompressed first. Fetches read them back as they are, whole batches
+//! at a time.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::{
+    sync::{Notify, OnceCell},
+    task,
+};
+
+use crate::{
+    config::{FIRST_SEGMENT, METADATA_LOG_DIR},
+    records::{CompressionType, Record, RecordBatch},
+    serde_kafka,
+};
+
+// `base_offset` and `batch_length`, the part of a batch before its length.
+const LOG_OVERHEAD: usize = 12;
+const PARTITION_LEADER_EPOCH_OFFSET: usize = 12;
+const CRC_OFFSET: usize = 17;
+// Where the part of the batch the CRC covers starts.
+const ATTRIBUTES_OFFSET: usize = 21;
+const LAST_OFFSET_DELTA_OFFSET: usize = 23;
+// Up to `last_offset_delta`, the shortest batch recovery reads.
+const MIN_BATCH_SIZE: usize = 27;
+
+/// A partition, by topic name.
+type TopicPartition = (String, i32);
+
+/// A partition log, shared by the requests to its partition.
+pub type SharedLog = Arc<Mutex<PartitionLog>>;
+
+/// The fetches waiting for appends, by partition.
+type Waiting = Arc<Mutex<HashMap<TopicPartition, Vec<Arc<Notify>>>>>;
+
+/// The partition logs of a log directory: those already in it, opened when
+/// it's loaded, and those created by the first append to their partition.
+#[derive(Debug)]
+pub struct LogManager {
+    log_dir: PathBuf,
+    // Each log is opened once, by the first request to its partition, and
+    // outside of the lock so requests to other partitions aren't held up.
+    logs: Mutex<HashMap<TopicPartition, Arc<OnceCell<SharedLog>>>>,
+    // Shared with the subscriptions, which remove themselves when dropped.
+    waiting: Waiting,
+    max_records_size: usize,
+}
+
+/// A subscription to the appends to some partitions, see
+/// `LogManager::subscribe`. It's removed when dropped.
+#[derive(Debug)]
+pub struct Appends {
+    appended: Arc<Notify>,
+    partitions: Vec<TopicPartition>,
+    waiting: Waiting,
+}
+
+#[derive(Debug)]
+pub struct PartitionLog {
+    // Shared with appends syncing it, which don't hold the log meanwhile.
+    file: Arc<File>,
+    size: u64,
+    next_offset: i64,
+    batches: Vec<BatchPosition>,
+}
+
+/// Where a batch is in the segment, so fetches find the batch of an offset
+/// without reading the ones before it.
+#[derive(Debug, Clone, Copy)]
+struct BatchPosition {
+    last_offset: i64,
+    position: u64,
+    size: u64,
+}
+
+/// Batches of a log, read without holding it: appends only write after them.
+#[derive(Debug, Clone)]
+pub struct LogSlice {
+    file: Arc<File>,
+    position: u64,
+    size: usize,
+}
+
+/// Where a batch was appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogAppendInfo {
+    pub base_offset: i64,
+    pub log_start_offset: i64,
+}
+
+#[derive(Debug)]
+pub enum AppendError {
+    /// The records aren't a single valid batch.
+    InvalidRecord(String),
+    /// The batch doesn't decode or its CRC doesn't match.
+    CorruptMessage(serde_kafka::Error),
+    Io(io::Error),
+}
+
+impl LogManager {
+    /// A manager of the logs in `log_dir`, appending batches whose records
+    /// decompress to at most `max_records_size` bytes.
+    pub fn new(log_dir: impl Into<PathBuf>, max_records_size: usize) -> Self {
+        LogManager {
+            log_dir: log_dir.into(),
+            logs: Mutex::default(),
+            waiting: Waiting::default(),
+            max_records_size,
+        }
+    }
+
+    /// Loads the partition logs in `log_dir`, which are recovered, as
+    /// `LogManager::new` with `max_records_size`. The metadata log isn't one
+    /// of them.
+    pub fn load(log_dir: impl Into<PathBuf>, max_records_size: usize) -> io::Result<Self> {
+        let manager = LogManager::new(log_dir, max_records_size);
+
+        let entries = match fs::read_dir(&manager.log_dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(manager),
+            Err(e) => return Err(e),
+        };
+        let mut logs = manager.logs.lock().unwrap();
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_dir() || entry.file_name() == METADATA_LOG_DIR {
+                continue;
+            }
+            let name = entry.file_name();
+            let Some((topic, partition)) = name
+                .to_str()
+                .and_then(|name| name.rsplit_once('-'))
+                .and_then(|(topic, partition)| Some((topic, partition.parse().ok()?)))
+            else {
+                continue;
+            };
+
+            let log = Arc::new(Mutex::new(PartitionLog::open(&entry.path())?));
+            logs.insert(
+                (topic.to_string(), partition),
+                Arc::new(OnceCell::new_with(Some(log))),
+            );
+        }
+        drop(logs);
+
+        Ok(manager)
+    }
+
+    /// The log of `partition` of `topic`, `None` if nothing was appended to
+    /// it yet.
+    pub fn existing_log(&self, topic: &str, partition: i32) -> Option<SharedLog> {
+        let logs = self.logs.lock().unwrap();
+        logs.get(&(topic.to_string(), partition))?.get().cloned()
+    }
+
+    /// The log of `partition` of `topic`, created if it doesn't exist yet.
+    pub async fn log(&self, topic: &str, partition: i32) -> io::Result<SharedLog> {
+        let cell = self
+            .logs
+            .lock()
+            .unwrap()
+            .entry((topic.to_string(), partition))
+            .or_default()
+            .clone();
+
+        let log = cell
+            .get_or_try_init(|| async {
+                let dir = self.log_dir.join(format!("{topic}-{partition}"));
+                let log = task::spawn_blocking(move || PartitionLog::open(&dir))
+                    .await
+                    .map_err(io::Error::other)??;
+                Ok::<_, io::Error>(Arc::new(Mutex::new(log)))
+            })
+            .await?;
+        Ok(log.clone())
+    }
+
+    /// Appends `records` to the log of `partition` of `topic`, compressed as
+    /// `compression_type` says, writing them through to the disk when `sync`
+    /// is set, and wakes the fetches waiting for the partition's records.
+    /// The batch is checked, written and synced on a blocking thread.
+    pub async fn append(
+        &self,
+        topic: &str,
+        partition: i32,
+        records: Bytes,
+        leader_epoch: i32,
+        compression_type: CompressionType,
+        sync: bool,
+    ) -> Result<LogAppendInfo, AppendError> {
+        let log = self.log(topic, partition).await?;
+        let max_records_size = self.max_records_size;
+
+        let info = task::spawn_blocking(move || {
+            let mut log = log.lock().unwrap();
+            let info = log.append(&records, leader_epoch, compression_type, max_records_size)?;
+            // Synced once the log is released, so it can be read meanwhile.
+            let file = log.file.clone();
+            drop(log);
+            if sync {
+                file.sync_data()?;
+            }
+
+            Ok::<_, AppendError>(info)
+        })
+        .await
+        .map_err(|e| AppendError::Io(io::Error::other(e)))??;
+
+        self.notify_appended(topic, partition);
+        Ok(info)
+    }
+
+    /// Notified of the appends to any of `partitions`, by topic name, after
+    /// this call, until it's dropped.
+    pub fn subscribe<'a, I>(&self, partitions: I) -> Appends
+    where
+        I: IntoIterator<Item = (&'a str, i32)>,
+    {
+        let appended = Arc::new(Notify::new());
+        let partitions: Vec<_> = partitions
+            .into_iter()
+            .map(|(topic, partition)| (topic.to_string(), partition))
+            .collect();
+        let mut waiting = self.waiting.lock().unwrap();
+        for partition in &partitions {
+            waiting
+                .entry(partition.clone())
+                .or_default()
+                .push(appended.clone());
+        }
+        drop(waiting);
+
+        Appends {
+            appended,
+            partitions,
+            waiting: self.waiting.clone(),
+        }
+    }
+
+    fn notify_appended(&self, topic: &str, partition: i32) {
+        let waiting = self.waiting.lock().unwrap();
+        for fetch in waiting
+            .get(&(topic.to_string(), partition))
+            .into_iter()
+            .flatten()
+        {
+            fetch.notify_one();
+        }
+    }
+}
+
+impl Appends {
+    /// Waits for an append. Appends while no one waits still wake the next
+    /// wait.
+    pub async fn notified(&self) {
+        self.appended.notified().await
+    }
+}
+
+impl Drop for Appends {
+    fn drop(&mut self) {
+        let mut waiting = self.waiting.lock().unwrap();
+        for partition in &self.partitions {
+            let Some(fetches) = waiting.get_mut(partition) else {
+                continue;
+            };
+            fetches.retain(|fetch| !Arc::ptr_eq(fetch, &self.appended));
+            if fetches.is_empty() {
+                waiting.remove(partition);
+            }
+        }
+    }
+}
+
+impl PartitionLog {
+    /// Opens the log in `dir`, recovering the next offset from the batches
+    /// already in it. The log is truncated from the first batch cut short by
+    /// a crash or whose CRC doesn't match.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(dir.join(FIRST_SEGMENT))?;
+
+        let file_size = file.metadata()?.len();
+        let mut size = 0;
+        let mut next_offset = 0;
+        let mut batches = Vec::new();
+        let mut batch = Vec::new();
+        while size + MIN_BATCH_SIZE as u64 <= file_size {
+            batch.resize(LOG_OVERHEAD, 0);
+            file.seek(SeekFrom::Start(size))?;
+            file.read_exact(&mut batch)?;
+
+            let base_offset = i64::from_be_bytes(batch[..8].try_into().unwrap());
+            let batch_length = i32::from_be_bytes(batch[8..LOG_OVERHEAD].try_into().unwrap());
+            let batch_size = LOG_OVERHEAD as u64 + batch_length.max(0) as u64;
+            if batch_size < MIN_BATCH_SIZE as u64 || size + batch_size > file_size {
+                break;
+            }
+            batch.resize(batch_size as usize, 0);
+            file.read_exact(&mut batch[LOG_OVERHEAD..])?;
+
+            let crc = u32::from_be_bytes(batch[CRC_OFFSET..ATTRIBUTES_OFFSET].try_into().unwrap());
+            if crc32c::crc32c(&batch[ATTRIBUTES_OFFSET..]) != crc {
+                break;
+            }
+            let last_offset_delta = i32::from_be_bytes(
+                batch[LAST_OFFSET_DELTA_OFFSET..MIN_BATCH_SIZE]
+                    .try_into()
+                    .unwrap(),
+            );
+
+            next_offset = base_offset + i64::from(last_offset_delta) + 1;
+            batches.push(BatchPosition {
+                last_offset: next_offset - 1,
+                position: size,
+                size: batch_size,
+            });
+            size += batch_size;
+        }
+
+        if size < file_size {
+            tracing::warn!(
+                "{}: truncated {} bytes from an incomplete or corrupt batch",
+                dir.display(),
+                file_size - size
+            );
+            file.set_len(size)?;
+        }
+
+        Ok(PartitionLog {
+            file: Arc::new(file),
+            size,
+            next_offset,
+            batches,
+        })
+    }
+
+    pub fn next_offset(&self) -> i64 {
+        self.next_offset
+    }
+
+    pub fn log_start_offset(&self) -> i64 {
+        0
+    }
+
+    /// Appends the produced `records`, a single record batch whose records
+    /// decompress to at most `max_records_size` bytes, at the next offset.
+    /// They're recompressed if `compression_type` names another codec.
+    pub fn append(
+        &mut self,
+        records: &[u8],
+        leader_epoch: i32,
+        compression_type: CompressionType,
+        max_records_size: usize,
+    ) -> Result<LogAppendInfo, AppendError> {
+        let mut batch = validate(records, max_records_size)?;
+
+        let mut records = match compression_type {
+            CompressionType::Codec(compression) if batch.compression() != Some(compression) => {
+                batch.set_compression(compression);
+                serde_kafka::to_bytes_mut(&batch).map_err(AppendError::CorruptMessage)?
+            }
+            _ => BytesMut::from(records),
+        };
+        let base_offset = self.next_offset;
+        records[..8].copy_from_slice(&base_offset.to_be_bytes());
+        records[PARTITION_LEADER_EPOCH_OFFSET..PARTITION_LEADER_EPOCH_OFFSET + 4]
+            .copy_from_slice(&leader_epoch.to_be_bytes());
+
+        // Written at the end of the batches rather than of the file, so a
+        // failed write is overwritten by the next one even if it can't be
+        // truncated.
+        if let Err(e) = self.file.write_all_at(&records, self.size) {
+            if let Err(e) = self.file.set_len(self.size) {
+                tracing::warn!("failed to truncate a partly written batch: {e}");
+            }
+            return Err(e.into());
+        }
+        self.next_offset = base_offset + i64::from(batch.last_offset_delta) + 1;
+        self.batches.push(BatchPosition {
+            last_offset: self.next_offset - 1,
+            position: self.size,
+            size: records.len() as u64,
+        });
+        self.size += records.len() as u64;
+
+        Ok(LogAppendInfo {
+            base_offset,
+            log_start_offset: self.log_start_offset(),
+        })
+    }
+
+    /// Writes the appended batches through to the disk.
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    /// The batches from the one holding `offset` on, up to `max_bytes` of
+    /// them. With `min_one`, the first batch is read even if it's larger, so
+    /// a consumer can't get stuck on it.
+    pub fn read(&self, offset: i64, max_bytes: usize, min_one: bool) -> io::Result<Bytes> {
+        self.slice(offset, max_bytes, min_one).read()
+    }
+
+    /// Where the batches `read` reads are, to read them once the log is
+    /// released.
+    pub fn slice(&self, offset: i64, max_bytes: usize, min_one: bool) -> LogSlice {
+        let first = self
+            .batches
+            .partition_point(|batch| batch.last_offset < offset);
+        let position = self.batches.get(first).map_or(0, |batch| batch.position);
+
+        let mut size = 0;
+        for batch in &self.batches[first..] {
+            if size + batch.size > max_bytes as u64 && !(min_one && size == 0) {
+                break;
+            }
+            size += batch.size;
+        }
+
+        LogSlice {
+            file: self.file.clone(),
+            position,
+            size: size as usize,
+        }
+    }
+}
+
+impl LogSlice {
+    pub fn read(&self) -> io::Result<Bytes> {
+        if self.size == 0 {
+            return Ok(Bytes::new());
+        }
+
+        let mut records = vec![0; self.size];
+        self.file.read_exact_at(&mut records, self.position)?;
+        Ok(records.into())
+    }
+}
+
+/// Checks that `records` are exactly one batch, as produce requests since v3
+/// carry, whose records match its `last_offset_delta`.
+fn validate(records: &[u8], max_records_size: usize) -> Result<RecordBatch<Record>, AppendError> {
+    let (batch, rest): (RecordBatch<Record>, _) =
+        RecordBatch::decode(records, max_records_size).map_err(AppendError::CorruptMessage)?;
+
+    if !rest.is_empty() {
+        return Err(AppendError::InvalidRecord(
+            "produce requests must have exactly one record batch".to_string(),
+        ));
+    }
+    if batch.records.is_empty() {
+        return Err(AppendError::InvalidRecord(
+            "record batch without records".to_string(),
+        ));
+    }
+    let offset_deltas = batch.records.iter().map(|record| record.offset_delta.0);
+    if !offset_deltas.eq(0..=batch.last_offset_delta) {
+        return Err(AppendError::InvalidRecord(
+            "record offsets don't match the last offset delta".to_string(),
+        ));
+    }
+
+    Ok(batch)
+}
+
+impl From<io::Error> for AppendError {
+    fn from(error: io::Error) -> Self {
+        AppendError::Io(error)
+    }
+}
+
+impl fmt::Display for AppendError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AppendError::InvalidRecord(reason) => write!(formatter, "invalid record: {reason}"),
+            AppendError::CorruptMessage(e) => write!(formatter, "corrupt message: {e}"),
+            AppendError::Io(e) => write!(formatter, "storage error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AppendError {}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use std::{io::Write, time::Duration};
+
+    use bytes::Bytes;
+    use tokio::time;
+
+    use crate::{records::Compression, serde_kafka::VarInt};
+
+    use super::*;
+
+    fn batch(values: &[&'static [u8]]) -> BytesMut {
+        let batch = RecordBatch {
+            last_offset_delta: values.len() as i32 - 1,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: values
+                .iter()
+                .enumerate()
+                .map(|(offset_delta, &value)| Record {
+                    offset_delta: VarInt(offset_delta as i32),
+                    value: Some(Bytes::from_static(value)),
+                    ..Record::default()
+                })
+                .collect(),
+            ..RecordBatch::default()
+        };
+
+        serde_kafka::to_bytes_mut(&batch).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_append() {
+        let dir = temp_dir("partition-log-append");
+
+        let mut log = PartitionLog::open(&dir).unwrap();
+        let info = log
+            .append(
+                &batch(&[b"a", b"b"]),
+                3,
+                CompressionType::Producer,
+                usize::MAX,
+            )
+            .unwrap();
+        assert_eq!(info.base_offset, 0);
+        let info = log
+            .append(&batch(&[b"c"]), 3, CompressionType::Producer, usize::MAX)
+            .unwrap();
+        assert_eq!(info.base_offset, 2);
+        assert_eq!(log.next_offset(), 3);
+        log.flush().unwrap();
+
+        // Offsets are assigned in the stored batches.
+        let content = fs::read(dir.join(FIRST_SEGMENT)).unwrap();
+        let (first, rest): (RecordBatch<Record>, _) =
+            serde_kafka::from_bytes_trail(&content).unwrap();
+        let second: RecordBatch<Record> = serde_kafka::from_bytes(rest).unwrap();
+        assert_eq!(first.base_offset, 0);
+        assert_eq!(first.partition_leader_epoch, 3);
+        assert_eq!(second.base_offset, 2);
+        assert_eq!(second.records[0].value.as_deref(), Some(&b"c"[..]));
+
+        // Read back whole batches, from the one holding the offset.
+        assert_eq!(log.read(1, usize::MAX, false).unwrap(), content);
+        assert_eq!(log.read(2, usize::MAX, false).unwrap(), rest);
+        assert_eq!(log.read(3, usize::MAX, false).unwrap(), Bytes::new());
+        assert_eq!(log.read(0, 1, false).unwrap(), Bytes::new());
+        assert_eq!(
+            log.read(0, 1, true).unwrap(),
+            &content[..content.len() - rest.len()]
+        );
+
+        // Recovered when the log is opened again, without a partial batch.
+        drop(log);
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(dir.join(FIRST_SEGMENT))
+            .unwrap();
+        file.write_all(&batch(&[b"d"])[..20]).unwrap();
+        let log = PartitionLog::open(&dir).unwrap();
+        assert_eq!(log.next_offset(), 3);
+        assert_eq!(log.size, content.len() as u64);
+        assert_eq!(log.read(2, usize::MAX, false).unwrap(), rest);
+
+        // Or from a batch whose CRC doesn't match, with the ones after it.
+        drop(log);
+        let mut corrupt = batch(&[b"d"]);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        file.write_all(&corrupt).unwrap();
+        file.write_all(&batch(&[b"e"])).unwrap();
+        let log = PartitionLog::open(&dir).unwrap();
+        assert_eq!(log.next_offset(), 3);
+        assert_eq!(log.size, content.len() as u64);
+        assert_eq!(
+            fs::metadata(dir.join(FIRST_SEGMENT)).unwrap().len(),
+            content.len() as u64
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failed_append() {
+        let dir = temp_dir("partition-log-failed-append");
+        let path = dir.join(FIRST_SEGMENT);
+        let mut log = PartitionLog::open(&dir).unwrap();
+        log.append(&batch(&[b"a"]), 0, CompressionType::Producer, usize::MAX)
+            .unwrap();
+        let first = log.read(0, usize::MAX, false).unwrap();
+
+        // Part of a batch left behind by a failed write, which the read-only
+        // file can't truncate.
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&batch(&[b"b"])[..20]).unwrap();
+        log.file = Arc::new(File::open(&path).unwrap());
+        assert!(matches!(
+            log.append(&batch(&[b"b"]), 0, CompressionType::Producer, usize::MAX),
+            Err(AppendError::Io(_))
+        ));
+        assert_eq!(log.next_offset(), 1);
+        assert_eq!(log.size, first.len() as u64);
+
+        // The next append is written over it.
+        log.file = Arc::new(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(&path)
+                .unwrap(),
+        );
+        log.append(&batch(&[b"c"]), 0, CompressionType::Producer, usize::MAX)
+            .unwrap();
+        let second: RecordBatch<Record> =
+            serde_kafka::from_bytes(&log.read(1, usize::MAX, false).unwrap()).unwrap();
+        assert_eq!(second.base_offset, 1);
+        assert_eq!(second.records[0].value.as_deref(), Some(&b"c"[..]));
+
+        drop(log);
+        let log = PartitionLog::open(&dir).unwrap();
+        assert_eq!(log.next_offset(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = temp_dir("partition-log-load");
+        let mut log = PartitionLog::open(&dir.join("foo-1")).unwrap();
+        log.append(&batch(&[b"a"]), 0, CompressionType::Producer, usize::MAX)
+            .unwrap();
+        drop(log);
+        fs::create_dir_all(dir.join(METADATA_LOG_DIR)).unwrap();
+        fs::create_dir_all(dir.join("not-a-partition")).unwrap();
+
+        let logs = LogManager::load(&dir, usize::MAX).unwrap();
+        let log = logs.existing_log("foo", 1).unwrap();
+        assert_eq!(log.lock().unwrap().next_offset(), 1);
+        assert!(logs.existing_log("foo", 0).is_none());
+        assert!(logs.existing_log("__cluster_metadata", 0).is_none());
+        assert!(!dir.join("foo-0").exists());
+
+        // Without a log directory yet.
+        let logs = LogManager::load(dir.join("missing"), usize::MAX).unwrap();
+        assert!(logs.existing_log("foo", 1).is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_subscribe() {
+        let dir = temp_dir("partition-log-subscribe");
+        let logs = LogManager::new(&dir, usize::MAX);
+        let append = |partition| {
+            logs.append(
+                "foo",
+                partition,
+                batch(&[b"a"]).freeze(),
+                0,
+                CompressionType::Producer,
+                false,
+            )
+        };
+        let wait = |appended: &Appends| {
+            let appended = appended.appended.clone();
+            async move {
+                time::timeout(Duration::from_millis(20), appended.notified())
+                    .await
+                    .is_ok()
+            }
+        };
+
+        // Opened once when first used by concurrent requests.
+        let (first, second) = tokio::join!(logs.log("foo", 0), logs.log("foo", 0));
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+
+        // Only woken by appends to the partitions subscribed to, even made
+        // before waiting.
+        let appended = logs.subscribe([("foo", 0), ("bar", 0)]);
+        append(1).await.unwrap();
+        assert!(!wait(&appended).await);
+        append(0).await.unwrap();
+        assert!(wait(&appended).await);
+        assert!(!wait(&appended).await);
+
+        // Removed when dropped, even if nothing is appended.
+        let other = logs.subscribe([("foo", 0)]);
+        drop(appended);
+        assert_eq!(
+            logs.waiting.lock().unwrap()[&("foo".to_string(), 0)].len(),
+            1
+        );
+        assert!(!logs
+            .waiting
+            .lock()
+            .unwrap()
+            .contains_key(&("bar".to_string(), 0)));
+        drop(other);
+        assert!(logs.waiting.lock().unwrap().is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_batches() {
+        let dir = temp_dir("partition-log-invalid");
+        let mut log = PartitionLog::open(&dir).unwrap();
+
+        let mut corrupt = batch(&[b"a"]);
+        let last = corrupt.len() - 1;
+        corrupt[last] ^= 0xff;
+        assert!(matches!(
+            log.append(&corrupt, 0, CompressionType::Producer, usize::MAX),
+            Err(AppendError::CorruptMessage(_))
+        ));
+
+        let mut two_batches = batch(&[b"a"]);
+        two_batches.unsplit(batch(&[b"b"]));
+        assert!(matches!(
+            log.append(&two_batches, 0, CompressionType::Producer, usize::MAX),
+            Err(AppendError::InvalidRecord(_))
+        ));
+        assert!(matches!(
+            log.append(&batch(&[]), 0, CompressionType::Producer, usize::MAX),
+            Err(AppendError::InvalidRecord(_))
+        ));
+
+        // Records that decompress to more than the limit.
+        let mut compressed: RecordBatch<Record> =
+            serde_kafka::from_bytes(&batch(&[&[0; 4096]])).unwrap();
+        compressed.set_compression(Compression::Gzip);
+        let compressed = serde_kafka::to_bytes_mut(&compressed).unwrap();
+        assert!(compressed.len() < 1024);
+        assert!(matches!(
+            log.append(&compressed, 0, CompressionType::Producer, 1024),
+            Err(AppendError::CorruptMessage(_))
+        ));
+
+        assert_eq!(log.next_offset(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_recompress() {
+        let dir = temp_dir("partition-log-recompress");
+        let mut log = PartitionLog::open(&dir).unwrap();
+
+        let mut snappy: RecordBatch<Record> =
+            serde_kafka::from_bytes(&batch(&[b"a", b"b"])).unwrap();
+        snappy.set_compression(Compression::Snappy);
+        let snappy = serde_kafka::to_bytes_mut(&snappy).unwrap();
+
+        let zstd = CompressionType::Codec(Compression::Zstd);
+        log.append(&snappy, 1, zstd, usize::MAX).unwrap();
+        log.append(&batch(&[b"c"]), 1, zstd, usize::MAX).unwrap();
+        log.append(&snappy, 1, CompressionType::Producer, usize::MAX)
+            .unwrap();
+
+        let content = log.read(0, usize::MAX, false).unwrap();
+        let (first, rest): (RecordBatch<Record>, _) =
+            serde_kafka::from_bytes_trail(&content).unwrap();
+        let (second, rest): (RecordBatch<Record>, _) = serde_kafka::from_bytes_trail(rest).unwrap();
+        let third: RecordBatch<Record> = serde_kafka::from_bytes(rest).unwrap();
+        assert_eq!(first.compression(), Some(Compression::Zstd));
+        assert_eq!(first.records[1].value.as_deref(), Some(&b"b"[..]));
+        assert_eq!(second.compression(), Some(Compression::Zstd));
+        assert_eq!(second.base_offset, 2);
+        assert_eq!(second.partition_leader_epoch, 1);
+        assert_eq!(third.compression(), Some(Compression::Snappy));
+        assert_eq!(third.base_offset, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,688 @@
+//! Record batches (magic v2), the format records are produced in, stored in
+//! partition logs and the metadata log, and fetched in.
+//!
+//! `batch_length`, the CRC-32C and the length of each record are not fields:
+//! they are computed when a batch is serialized, and checked when it is
+//! deserialized. Records are compressed with the codec in `attributes`, see
+//! `Compression`.
+
+use std::{fmt, marker::PhantomData};
+
+use bytes::{Bytes, BytesMut};
+use serde::{
+    de::{self, DeserializeOwned, DeserializeSeed, SeqAccess, Unexpected, Visitor},
+    ser::{self, SerializeStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::serde_kafka::{
+    self, int32_array, varint_length, Crc32c, ErrorKind, Remaining, SizePrefixed, VarInt,
+    VarIntSizePrefixed, VarLong,
+};
+
+mod compression;
+
+pub use compression::{Compression, CompressionType, UnknownCompression};
+
+pub const MAGIC: i8 = 2;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RecordBatch<R> {
+    pub base_offset: i64,
+    pub partition_leader_epoch: i32,
+    pub attributes: i16,
+    pub last_offset_delta: i32,
+    pub base_timestamp: i64,
+    pub max_timestamp: i64,
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    pub records: Vec<R>,
+}
+
+/// A record of a batch, with its offset and timestamp relative to the base
+/// ones of the batch.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub attributes: i8,
+    pub timestamp_delta: VarLong,
+    pub offset_delta: VarInt,
+    #[serde(with = "varint_length::nullable_bytes")]
+    pub key: Option<Bytes>,
+    #[serde(with = "varint_length::nullable_bytes")]
+    pub value: Option<Bytes>,
+    #[serde(with = "varint_length")]
+    pub headers: Vec<Header>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+    #[serde(with = "varint_length")]
+    pub key: String,
+    #[serde(with = "varint_length::nullable_bytes")]
+    pub value: Option<Bytes>,
+}
+
+// The batch on the wire: `batch_length` is the size of `batch`, and `crc`
+// covers everything after it.
+#[derive(Serialize, Deserialize)]
+struct Frame<B> {
+    base_offset: i64,
+    batch: SizePrefixed<Batch<B>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Batch<B> {
+    partition_leader_epoch: i32,
+    magic: Magic,
+    body: Crc32c<B>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct BatchHeader {
+    attributes: i16,
+    last_offset_delta: i32,
+    base_timestamp: i64,
+    max_timestamp: i64,
+    producer_id: i64,
+    producer_epoch: i16,
+    base_sequence: i32,
+}
+
+const BODY_FIELDS: &[&str] = &["header", "records"];
+
+struct BodyRef<'a, R> {
+    header: BatchHeader,
+    records: &'a [R],
+}
+
+struct Body<R> {
+    header: BatchHeader,
+    records: Vec<R>,
+}
+
+// The body with its records still encoded, see `RecordBatch::decode`.
+#[derive(Deserialize)]
+struct EncodedBody {
+    header: BatchHeader,
+    records: Remaining,
+}
+
+// Each record is preceded by its `VARINT` size. The records of a compressed
+// batch are an `INT32` count followed by the compressed records, extending to
+// the end of the batch.
+struct Records<'a, R> {
+    compression: Compression,
+    records: &'a [R],
+}
+
+struct RecordsSeed<R> {
+    compression: Compression,
+    records: PhantomData<R>,
+}
+
+/// The magic byte, checked before the rest of the batch is decoded since
+/// older formats are laid out differently.
+struct Magic;
+
+impl Serialize for Magic {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_i8(MAGIC)
+    }
+}
+
+impl<'de> Deserialize<'de> for Magic {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match i8::deserialize(deserializer)? {
+            MAGIC => Ok(Magic),
+            magic => Err(de::Error::invalid_value(
+                Unexpected::Signed(magic.into()),
+                &"magic 2",
+            )),
+        }
+    }
+}
+
+fn compression<E: de::Error>(attributes: i16) -> Result<Compression, E> {
+    Compression::from_attributes(attributes).ok_or_else(|| {
+        de::Error::invalid_value(
+            Unexpected::Signed(attributes.into()),
+            &"attributes with a known compression codec",
+        )
+    })
+}
+
+impl<R> Serialize for BodyRef<'_, R>
+where
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let compression = Compression::from_attributes(self.header.attributes)
+            .ok_or_else(|| ser::Error::custom("Unknown compression codec"))?;
+
+        let mut body = serializer.serialize_struct("Body", BODY_FIELDS.len())?;
+        body.serialize_field("header", &self.header)?;
+        body.serialize_field(
+            "records",
+            &Records {
+                compression,
+                records: self.records,
+            },
+        )?;
+        body.end()
+    }
+}
+
+impl<R> Serialize for Records<'_, R>
+where
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.compression == Compression::None {
+            let records: Vec<_> = self.records.iter().map(VarIntSizePrefixed).collect();
+            return int32_array::serialize(&records, serializer);
+        }
+
+        let count = i32::try_from(self.records.len()).map_err(ser::Error::custom)?;
+        let mut records = BytesMut::new();
+        for record in self.records {
+            let record = serde_kafka::to_bytes_mut(&VarIntSizePrefixed(record))
+                .map_err(ser::Error::custom)?;
+            records.extend_from_slice(&record);
+        }
+        let compressed = self
+            .compression
+            .compress(&records)
+            .map_err(ser::Error::custom)?;
+
+        (count, Remaining(compressed.into())).serialize(serializer)
+    }
+}
+
+impl<'de, R> Deserialize<'de> for Body<R>
+where
+    R: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct BodyVisitor<R>(PhantomData<R>);
+
+        impl<'de, R> Visitor<'de> for BodyVisitor<R>
+        where
+            R: DeserializeOwned,
+        {
+            type Value = Body<R>;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a record batch body")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                let header: BatchHeader = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let records = seq
+                    .next_element_seed(RecordsSeed {
+                        compression: compression(header.attributes)?,
+                        records: PhantomData,
+                    })?
+                    .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+
+                Ok(Body { header, records })
+            }
+        }
+
+        deserializer.deserialize_struct("Body", BODY_FIELDS, BodyVisitor(PhantomData))
+    }
+}
+
+impl<'de, R> DeserializeSeed<'de> for RecordsSeed<R>
+where
+    R: DeserializeOwned,
+{
+    type Value = Vec<R>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let Remaining(records) = Remaining::deserialize(deserializer)?;
+
+        decode_records(self.compression, &records, usize::MAX).map_err(de::Error::custom)
+    }
+}
+
+/// Decodes the records section of a batch, decompressing it to at most
+/// `max_size` bytes.
+fn decode_records<R>(
+    compression: Compression,
+    records: &[u8],
+    max_size: usize,
+) -> serde_kafka::Result<Vec<R>>
+where
+    R: DeserializeOwned,
+{
+    let (count, records): (i32, _) = serde_kafka::from_bytes_trail(records)?;
+    let count = usize::try_from(count).map_err(|_| ErrorKind::NegativeLength(count.into()))?;
+
+    let decompressed;
+    let mut input = match compression {
+        Compression::None => records,
+        _ => {
+            decompressed = compression.decompress(records, max_size)?;
+            &decompressed[..]
+        }
+    };
+
+    let mut records = Vec::with_capacity(count.min(1024));
+    for _ in 0..count {
+        let (VarIntSizePrefixed(record), rest) = serde_kafka::from_bytes_trail(input)?;
+        records.push(record);
+        input = rest;
+    }
+    if !input.is_empty() {
+        return Err(ErrorKind::TrailingBytes(input.len()).into());
+    }
+    Ok(records)
+}
+
+impl<R> RecordBatch<R>
+where
+    R: DeserializeOwned,
+{
+    /// Decodes a batch from the start of `bytes`, returning the bytes after
+    /// it, like `serde_kafka::from_bytes_trail`, but failing when its records
+    /// decompress to more than `max_records_size` bytes: batches from clients
+    /// are decoded this way, as a small compressed batch can otherwise expand
+    /// without bound.
+    pub fn decode(bytes: &[u8], max_records_size: usize) -> serde_kafka::Result<(Self, &[u8])> {
+        let (frame, rest): (Frame<EncodedBody>, _) = serde_kafka::from_bytes_trail(bytes)?;
+        let Frame {
+            base_offset,
+            batch: SizePrefixed(batch),
+        } = frame;
+        let Crc32c(EncodedBody { header, records }) = batch.body;
+        let compression = compression::<serde_kafka::Error>(header.attributes)?;
+        let records = decode_records(compression, &records.0, max_records_size)?;
+
+        Ok((
+            RecordBatch::from_parts(base_offset, batch.partition_leader_epoch, header, records),
+            rest,
+        ))
+    }
+}
+
+impl<R> RecordBatch<R> {
+    fn from_parts(
+        base_offset: i64,
+        partition_leader_epoch: i32,
+        header: BatchHeader,
+        records: Vec<R>,
+    ) -> Self {
+        RecordBatch {
+            base_offset,
+            partition_leader_epoch,
+            attributes: header.attributes,
+            last_offset_delta: header.last_offset_delta,
+            base_timestamp: header.base_timestamp,
+            max_timestamp: header.max_timestamp,
+            producer_id: header.producer_id,
+            producer_epoch: header.producer_epoch,
+            base_sequence: header.base_sequence,
+            records,
+        }
+    }
+
+    /// The codec of the records, `None` if `attributes` names an unknown one.
+    pub fn compression(&self) -> Option<Compression> {
+        Compression::from_attributes(self.attributes)
+    }
+
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.attributes = (self.attributes & !Compression::ATTRIBUTES_MASK) | compression.id();
+    }
+}
+
+impl<R> Serialize for RecordBatch<R>
+where
+    R: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        Frame {
+            base_offset: self.base_offset,
+            batch: SizePrefixed(Batch {
+                partition_leader_epoch: self.partition_leader_epoch,
+                magic: Magic,
+                body: Crc32c(BodyRef {
+                    header: BatchHeader {
+                        attributes: self.attributes,
+                        last_offset_delta: self.last_offset_delta,
+                        base_timestamp: self.base_timestamp,
+                        max_timestamp: self.max_timestamp,
+                        producer_id: self.producer_id,
+                        producer_epoch: self.producer_epoch,
+                        base_sequence: self.base_sequence,
+                    },
+                    records: &self.records,
+                }),
+            }),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, R> Deserialize<'de> for RecordBatch<R>
+where
+    R: DeserializeOwned,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let Frame {
+            base_offset,
+            batch: SizePrefixed(batch),
+        } = Frame::<Body<R>>::deserialize(deserializer)?;
+        let Crc32c(Body { header, records }) = batch.body;
+
+        Ok(RecordBatch::from_parts(
+            base_offset,
+            batch.partition_leader_epoch,
+            header,
+            records,
+        ))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use crate::serde_kafka::{self, ErrorKind};
+
+    use super::*;
+
+    fn batch() -> RecordBatch<i8> {
+        RecordBatch {
+            base_offset: 1,
+            partition_leader_epoch: 2,
+            last_offset_delta: 1,
+            producer_id: -1,
+            producer_epoch: -1,
+            base_sequence: -1,
+            records: vec![0x7f, 0x00],
+            ..RecordBatch::default()
+        }
+    }
+
+    const BATCH: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // base_offset: 1
+        0x00, 0x00, 0x00, 0x35, // batch_length: 53
+        0x00, 0x00, 0x00, 0x02, // partition_leader_epoch: 2
+        0x02, // magic: 2
+        0xb6, 0x10, 0xdd, 0x8f, // crc
+        0x00, 0x00, // attributes: 0
+        0x00, 0x00, 0x00, 0x01, // last_offset_delta: 1
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // base_timestamp: 0
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // max_timestamp: 0
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // producer_id: -1
+        0xff, 0xff, // producer_epoch: -1
+        0xff, 0xff, 0xff, 0xff, // base_sequence: -1
+        0x00, 0x00, 0x00, 0x02, // records: len 2
+        0x02, 0x7f, // length: 1, record
+        0x02, 0x00, // length: 1, record
+    ];
+
+    #[test]
+    fn test_ser_de() {
+        let bytes = serde_kafka::to_bytes_mut(&batch()).unwrap();
+        assert_eq!(BATCH, bytes.to_vec());
+
+        assert_eq!(batch(), serde_kafka::from_bytes(BATCH).unwrap());
+    }
+
+    const RECORD: &[u8] = &[
+        0x1e, // length: 15
+        0x00, // attributes: 0
+        0x00, // timestamp_delta: 0
+        0x02, // offset_delta: 1
+        0x02, b'k', // key
+        0x02, b'v', // value
+        0x04, // headers: len 2
+        0x02, b'h', 0x02, b'x', // key, value
+        0x02, b'n', 0x01, // key, value: null
+    ];
+
+    fn record() -> Record {
+        Record {
+            offset_delta: VarInt(1),
+            key: Some(Bytes::from_static(b"k")),
+            value: Some(Bytes::from_static(b"v")),
+            headers: vec![
+                Header {
+                    key: "h".to_string(),
+                    value: Some(Bytes::from_static(b"x")),
+                },
+                Header {
+                    key: "n".to_string(),
+                    value: None,
+                },
+            ],
+            ..Record::default()
+        }
+    }
+
+    #[test]
+    fn test_record() {
+        let bytes = serde_kafka::to_bytes_mut(&VarIntSizePrefixed(record())).unwrap();
+        assert_eq!(RECORD, bytes.to_vec());
+
+        let VarIntSizePrefixed(decoded) = serde_kafka::from_bytes(RECORD).unwrap();
+        assert_eq!(record(), decoded);
+
+        // Null key and value, -1 lengths.
+        let null = Record::default();
+        let bytes = serde_kafka::to_bytes_mut(&VarIntSizePrefixed(&null)).unwrap();
+        assert_eq!(
+            vec![0x0c, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00],
+            bytes.to_vec()
+        );
+
+        let mut batch = RecordBatch {
+            records: vec![record(), null],
+            ..RecordBatch::default()
+        };
+        for compression in [Compression::None, Compression::Zstd] {
+            batch.set_compression(compression);
+            let bytes = serde_kafka::to_bytes_mut(&batch).unwrap();
+            assert_eq!(batch, serde_kafka::from_bytes(&bytes).unwrap());
+        }
+
+        // A record shorter than its length.
+        let mut bytes = RECORD.to_vec();
+        bytes[0] = 0x20;
+        assert!(serde_kafka::from_bytes::<VarIntSizePrefixed<Record>>(&bytes).is_err());
+        // Or longer.
+        bytes[0] = 0x1c;
+        assert!(serde_kafka::from_bytes::<VarIntSizePrefixed<Record>>(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_compressed() {
+        for compression in [
+            Compression::Gzip,
+            Compression::Snappy,
+            Compression::Lz4,
+            Compression::Zstd,
+        ] {
+            let mut batch = batch();
+            batch.set_compression(compression);
+            assert_eq!(batch.compression(), Some(compression));
+
+            let bytes = serde_kafka::to_bytes_mut(&batch).unwrap();
+            assert_eq!(batch, serde_kafka::from_bytes(&bytes).unwrap());
+
+            // Its two records decompress to 4 bytes.
+            let (decoded, rest) = RecordBatch::decode(&bytes, 4).unwrap();
+            assert_eq!(batch, decoded);
+            assert!(rest.is_empty());
+            assert!(RecordBatch::<i8>::decode(&bytes, 3).is_err());
+        }
+    }
+
+    // Batches of two records, `hello` and `kafka` 7ms later, framed as the
+    // Java producer frames them with each `compression.type`. They aren't
+    // captured from a client: the gzip stream is the JDK `GZIPOutputStream`'s,
+    // the others were built to the framing of snappy-java (its xerial
+    // header), `KafkaLZ4BlockOutputStream` and zstd-jni around literal
+    // blocks, so the codecs' own matches aren't covered. To be replaced with
+    // the batches written to `foo-0/00000000000000000000.log` by
+    // `kafka-console-producer.sh --bootstrap-server localhost:9092 --topic foo
+    // --compression-codec <codec>`, with the client version noted here.
+    const GZIP_BATCH: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5d, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0xbc, 0x8d, 0x76, 0x1b, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01,
+        0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x07, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0x13, 0x63, 0x60, 0x60,
+        0x60, 0xe4, 0xca, 0x48, 0xcd, 0xc9, 0xc9, 0x67, 0x10, 0x63, 0xe0, 0x63, 0x62, 0xe4, 0xca,
+        0x4e, 0x4c, 0xcb, 0x4e, 0x64, 0x00, 0x00, 0x53, 0xeb, 0x5d, 0x1b, 0x18, 0x00, 0x00, 0x00,
+    ];
+
+    const SNAPPY_BATCH: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5f, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0x19, 0x6d, 0xc4, 0xc4, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01,
+        0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x07, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x82, 0x53, 0x4e, 0x41, 0x50, 0x50, 0x59, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x00, 0x00, 0x1a, 0x18, 0x5c, 0x16, 0x00, 0x00, 0x00, 0x01, 0x0a, 0x68,
+        0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x16, 0x00, 0x0e, 0x02, 0x01, 0x0a, 0x6b, 0x61, 0x66, 0x6b,
+        0x61, 0x00,
+    ];
+
+    const LZ4_BATCH: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x58, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0x75, 0xe6, 0xb4, 0xda, 0x00, 0x03, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01,
+        0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x07, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x04, 0x22, 0x4d, 0x18, 0x60, 0x40, 0x82, 0x18, 0x00, 0x00, 0x80, 0x16, 0x00, 0x00,
+        0x00, 0x01, 0x0a, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x16, 0x00, 0x0e, 0x02, 0x01, 0x0a,
+        0x6b, 0x61, 0x66, 0x6b, 0x61, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    const ZSTD_BATCH: &[u8] = &[
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x52, 0xff, 0xff, 0xff,
+        0xff, 0x02, 0x22, 0x3a, 0xd7, 0x11, 0x00, 0x04, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x01,
+        0x8b, 0xcf, 0xe5, 0x68, 0x00, 0x00, 0x00, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x07, 0xff, 0xff,
+        0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00,
+        0x02, 0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x58, 0xc1, 0x00, 0x00, 0x16, 0x00, 0x00, 0x00, 0x01,
+        0x0a, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x00, 0x16, 0x00, 0x0e, 0x02, 0x01, 0x0a, 0x6b, 0x61,
+        0x66, 0x6b, 0x61, 0x00,
+    ];
+
+    #[test]
+    fn test_java_client_batches() {
+        for (compression, bytes) in [
+            (Compression::Gzip, GZIP_BATCH),
+            (Compression::Snappy, SNAPPY_BATCH),
+            (Compression::Lz4, LZ4_BATCH),
+            (Compression::Zstd, ZSTD_BATCH),
+        ] {
+            let mut expected = RecordBatch {
+                last_offset_delta: 1,
+                partition_leader_epoch: -1,
+                base_timestamp: 1_700_000_000_000,
+                max_timestamp: 1_700_000_000_007,
+                producer_id: -1,
+                producer_epoch: -1,
+                base_sequence: -1,
+                records: vec![
+                    Record {
+                        value: Some(Bytes::from_static(b"hello")),
+                        ..Record::default()
+                    },
+                    Record {
+                        timestamp_delta: VarLong(7),
+                        offset_delta: VarInt(1),
+                        value: Some(Bytes::from_static(b"kafka")),
+                        ..Record::default()
+                    },
+                ],
+                ..RecordBatch::default()
+            };
+            expected.set_compression(compression);
+
+            // Its records decompress to 24 bytes.
+            let (decoded, rest) = RecordBatch::decode(bytes, 24).unwrap();
+            assert_eq!(expected, decoded);
+            assert!(rest.is_empty());
+            assert!(RecordBatch::<Record>::decode(bytes, 23).is_err());
+        }
+    }
+
+    #[test]
+    fn test_unknown_compression() {
+        let mut batch = batch();
+        batch.attributes = 0x07;
+        assert!(serde_kafka::to_bytes_mut(&batch).is_err());
+
+        let mut bytes = BATCH.to_vec();
+        bytes[22] = 0x07;
+        let crc = crc32c::crc32c(&bytes[21..]);
+        bytes[17..21].copy_from_slice(&crc.to_be_bytes());
+        let error = serde_kafka::from_bytes::<RecordBatch<i8>>(&bytes).unwrap_err();
+        assert_eq!(error.path(), Some("batch.body"));
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let mut bytes = BATCH.to_vec();
+        bytes[60] = 0x01;
+        let error = serde_kafka::from_bytes::<RecordBatch<i8>>(&bytes).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::InvalidCrc {
+                expected: 0xb610dd8f,
+                ..
+            }
+        ));
+        assert_eq!(error.offset(), Some(17));
+        assert_eq!(error.path(), Some("batch.body"));
+
+        let mut bytes = BATCH.to_vec();
+        bytes[16] = 0x01;
+        let error = serde_kafka::from_bytes::<RecordBatch<i8>>(&bytes).unwrap_err();
+        assert_eq!(error.path(), Some("batch.magic"));
+
+        let mut bytes = BATCH.to_vec();
+        bytes[11] = 0x36;
+        let error = serde_kafka::from_bytes::<RecordBatch<i8>>(&bytes).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Eof {
+                needed: 54,
+                available: 53
+            }
+        ));
+    }
+}
@@ -0,0 +1,334 @@
+//! Compression codecs of record batches, selected by the low 3 bits of the
+//! batch attributes. The whole records section is compressed at once, in the
+//! stream formats the Java client uses.
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use flate2::{read::MultiGzDecoder, write::GzEncoder};
+use lz4_flex::frame::{BlockMode, BlockSize, FrameDecoder, FrameEncoder, FrameInfo};
+
+/// Header of the framing snappy-java writes, which the Java client uses.
+const XERIAL_HEADER: &[u8] = &[0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0];
+const XERIAL_BLOCK_SIZE: usize = 32 * 1024;
+
+/// Level the Java client uses by default.
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Snappy,
+    Lz4,
+    Zstd,
+}
+
+impl Compression {
+    pub const ATTRIBUTES_MASK: i16 = 0x07;
+
+    /// The codec of a batch with `attributes`, `None` for unknown codecs.
+    pub fn from_attributes(attributes: i16) -> Option<Self> {
+        match attributes & Self::ATTRIBUTES_MASK {
+            0 => Some(Compression::None),
+            1 => Some(Compression::Gzip),
+            2 => Some(Compression::Snappy),
+            3 => Some(Compression::Lz4),
+            4 => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn id(self) -> i16 {
+        self as i16
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Compression::None => Ok(data.to_vec()),
+            Compression::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Compression::Snappy => {
+                let mut output = XERIAL_HEADER.to_vec();
+                // Format version and minimum compatible version.
+                output.extend_from_slice(&1i32.to_be_bytes());
+                output.extend_from_slice(&1i32.to_be_bytes());
+
+                let mut encoder = snap::raw::Encoder::new();
+                for block in data.chunks(XERIAL_BLOCK_SIZE) {
+                    let compressed = encoder.compress_vec(block)?;
+                    output.extend_from_slice(&(compressed.len() as i32).to_be_bytes());
+                    output.extend_from_slice(&compressed);
+                }
+                Ok(output)
+            }
+            Compression::Lz4 => {
+                let frame_info = FrameInfo::new()
+                    .block_size(BlockSize::Max64KB)
+                    .block_mode(BlockMode::Independent);
+                let mut encoder = FrameEncoder::with_frame_info(frame_info, Vec::new());
+                encoder.write_all(data)?;
+                encoder.finish().map_err(io::Error::other)
+            }
+            Compression::Zstd => zstd::encode_all(data, ZSTD_LEVEL),
+        }
+    }
+
+    /// Decompresses `data`, failing once it decompresses to more than
+    /// `max_size` bytes, so a small batch can't expand without bound.
+    pub fn decompress(self, data: &[u8], max_size: usize) -> io::Result<Vec<u8>> {
+        let mut output = Vec::new();
+        match self {
+            Compression::None => {
+                check_size(data.len(), max_size)?;
+                output.extend_from_slice(data);
+            }
+            Compression::Gzip => read_to_end(MultiGzDecoder::new(data), max_size, &mut output)?,
+            Compression::Snappy => match data.strip_prefix(XERIAL_HEADER) {
+                Some(framed) => decompress_xerial(framed, max_size, &mut output)?,
+                None => {
+                    check_size(snap::raw::decompress_len(data)?, max_size)?;
+                    output = snap::raw::Decoder::new().decompress_vec(data)?;
+                }
+            },
+            Compression::Lz4 => read_to_end(FrameDecoder::new(data), max_size, &mut output)?,
+            Compression::Zstd => read_to_end(zstd::Decoder::new(data)?, max_size, &mut output)?,
+        }
+        Ok(output)
+    }
+}
+
+/// Reads `reader` to the end into `output`, reading at most one byte past
+/// `max_size` to tell whether it is exceeded.
+fn read_to_end(reader: impl Read, max_size: usize, output: &mut Vec<u8>) -> io::Result<()> {
+    let limit = u64::try_from(max_size)
+        .unwrap_or(u64::MAX)
+        .saturating_add(1);
+    reader.take(limit).read_to_end(output)?;
+    check_size(output.len(), max_size)
+}
+
+fn check_size(size: usize, max_size: usize) -> io::Result<()> {
+    if size > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("records decompress to more than {max_size} bytes"),
+        ));
+    }
+    Ok(())
+}
+
+/// Decompresses the blocks following the xerial header, each preceded by its
+/// `INT32` size.
+fn decompress_xerial(data: &[u8], max_size: usize, output: &mut Vec<u8>) -> io::Result<()> {
+    let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+    // Skip the format version and minimum compatible version.
+    let mut blocks = data
+        .get(8..)
+        .ok_or_else(|| invalid("truncated xerial snappy header"))?;
+
+    let mut decoder = snap::raw::Decoder::new();
+    while !blocks.is_empty() {
+        let (size, rest) = blocks
+            .split_first_chunk::<4>()
+            .ok_or_else(|| invalid("truncated xerial snappy block size"))?;
+        let size = usize::try_from(i32::from_be_bytes(*size))
+            .map_err(|_| invalid("negative xerial snappy block size"))?;
+        let block = rest
+            .get(..size)
+            .ok_or_else(|| invalid("truncated xerial snappy block"))?;
+
+        check_size(output.len() + snap::raw::decompress_len(block)?, max_size)?;
+        output.extend_from_slice(&decoder.decompress_vec(block)?);
+        blocks = &rest[size..];
+    }
+    Ok(())
+}
+
+/// The `compression.type` of a topic, or the broker default for topics
+/// without one: the codec batches are stored with, recompressed if they were
+/// produced with another, or `Producer` to keep the producer's.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionType {
+    #[default]
+    Producer,
+    Codec(Compression),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct UnknownCompression(pub String);
+
+impl fmt::Display for UnknownCompression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "unknown compression type `{}`", self.0)
+    }
+}
+
+impl std::error::Error for UnknownCompression {}
+
+/// Parses the `compression.type` values naming a codec.
+impl FromStr for Compression {
+    type Err = UnknownCompression;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" | "uncompressed" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "snappy" => Ok(Compression::Snappy),
+            "lz4" => Ok(Compression::Lz4),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(UnknownCompression(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Snappy => "snappy",
+            Compression::Lz4 => "lz4",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+impl FromStr for CompressionType {
+    type Err = UnknownCompression;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "producer" => Ok(CompressionType::Producer),
+            codec => codec.parse().map(CompressionType::Codec),
+        }
+    }
+}
+
+impl fmt::Display for CompressionType {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionType::Producer => formatter.write_str("producer"),
+            CompressionType::Codec(compression) => compression.fmt(formatter),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DATA: &[u8] = &[0x7f, 0x00];
+
+    #[test]
+    fn test_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_be_bytes())
+            .collect();
+
+        for compression in [
+            Compression::None,
+            Compression::Gzip,
+            Compression::Snappy,
+            Compression::Lz4,
+            Compression::Zstd,
+        ] {
+            let compressed = compression.compress(&data).unwrap();
+            assert_eq!(
+                data,
+                compression.decompress(&compressed, data.len()).unwrap()
+            );
+            assert!(compression.decompress(&compressed, data.len() - 1).is_err());
+            assert_eq!(
+                Some(compression),
+                Compression::from_attributes(compression.id())
+            );
+            assert_eq!(Ok(compression), compression.to_string().parse());
+
+            let compression_type = CompressionType::Codec(compression);
+            assert_eq!(Ok(compression_type), compression_type.to_string().parse());
+        }
+        assert_eq!(Ok(CompressionType::Producer), "producer".parse());
+    }
+
+    #[test]
+    fn test_reference_streams() {
+        // JDK `GZIPOutputStream`, as the Java client writes it.
+        let gzip = [
+            0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xab, 0x67, 0x00, 0x00,
+            0xc6, 0x77, 0x7e, 0xe9, 0x02, 0x00, 0x00, 0x00,
+        ];
+        assert_eq!(
+            DATA,
+            Compression::Gzip.decompress(&gzip, DATA.len()).unwrap()
+        );
+
+        // snappy-java framing: header, versions, then sized raw blocks.
+        let xerial = [
+            0x82, b'S', b'N', b'A', b'P', b'P', b'Y', 0x00, // header
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, // versions
+            0x00, 0x00, 0x00, 0x04, 0x02, 0x04, 0x7f, 0x00, // block
+        ];
+        assert_eq!(
+            DATA,
+            Compression::Snappy.decompress(&xerial, DATA.len()).unwrap()
+        );
+        assert_eq!(xerial.to_vec(), Compression::Snappy.compress(DATA).unwrap());
+        // Unframed, as older clients wrote it.
+        assert_eq!(
+            DATA,
+            Compression::Snappy
+                .decompress(&xerial[20..], DATA.len())
+                .unwrap()
+        );
+
+        // lz4 frame with 64KB independent blocks and no content checksum.
+        let lz4 = [
+            0x04, 0x22, 0x4d, 0x18, 0x60, 0x40, 0x82, 0x02, 0x00, 0x00, 0x80, 0x7f, 0x00, 0x00,
+            0x00, 0x00, 0x00,
+        ];
+        assert_eq!(DATA, Compression::Lz4.decompress(&lz4, DATA.len()).unwrap());
+
+        // zstd level 3 without checksum.
+        let zstd = [
+            0x28, 0xb5, 0x2f, 0xfd, 0x00, 0x58, 0x11, 0x00, 0x00, 0x7f, 0x00,
+        ];
+        assert_eq!(
+            DATA,
+            Compression::Zstd.decompress(&zstd, DATA.len()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert_eq!(None, Compression::from_attributes(0x05));
+        assert!("brotli".parse::<Compression>().is_err());
+        assert!("producer".parse::<Compression>().is_err());
+        assert!("brotli".parse::<CompressionType>().is_err());
+
+        for compression in [
+            Compression::Gzip,
+            Compression::Snappy,
+            Compression::Lz4,
+            Compression::Zstd,
+        ] {
+            assert!(compression.decompress(&[0xff; 8], usize::MAX).is_err());
+        }
+        assert!(Compression::Snappy
+            .decompress(
+                &[XERIAL_HEADER, &[0x00; 8], &[0x00, 0x00, 0x00, 0x09]].concat(),
+                usize::MAX,
+            )
+            .is_err());
+    }
+}
@@ -0,0 +1,150 @@
+//! The requests of every connection go through one bounded queue to a pool of
+//! handler workers, like Kafka's request channel and `num.io.threads`. While
+//! the queue is full, connections stop reading requests.
+
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use bytes::Bytes;
+use tokio::{
+    sync::{mpsc, oneshot, Mutex},
+    task::JoinSet,
+    time::Instant,
+};
+
+use crate::{
+    handle_request, headers::RequestHeader, serde_kafka, Broker, Reply, RequestContext, Response,
+};
+
+pub(crate) type ResponseReceiver = oneshot::Receiver<serde_kafka::Result<Response>>;
+
+struct Request {
+    context: RequestContext,
+    header: RequestHeader,
+    raw_body: Bytes,
+    enqueue_time: Instant,
+    response: oneshot::Sender<serde_kafka::Result<Response>>,
+}
+
+#[derive(Clone)]
+pub(crate) struct RequestChannel {
+    sender: mpsc::Sender<Request>,
+}
+
+/// Totals over the requests handled so far. Queue time is spent waiting for a
+/// worker, processing time in the handler.
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    requests: AtomicU64,
+    queue_time_micros: AtomicU64,
+    processing_time_micros: AtomicU64,
+}
+
+impl RequestChannel {
+    /// Starts `workers` handling the requests sent to the channel, which end
+    /// once every `RequestChannel` is dropped.
+    pub(crate) fn start(
+        queue_depth: usize,
+        workers: usize,
+        metrics: Arc<RequestMetrics>,
+        broker: Arc<Broker>,
+    ) -> (Self, JoinSet<()>) {
+        let (sender, receiver) = mpsc::channel(queue_depth);
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let mut worker_set = JoinSet::new();
+        for _ in 0..workers {
+            worker_set.spawn(work(receiver.clone(), metrics.clone(), broker.clone()));
+        }
+
+        (RequestChannel { sender }, worker_set)
+    }
+
+    /// Queues a request, waiting while the queue is full. `None` once the
+    /// workers are gone.
+    pub(crate) async fn send(
+        &self,
+        context: RequestContext,
+        header: RequestHeader,
+        raw_body: Bytes,
+    ) -> Option<ResponseReceiver> {
+        let (response, receiver) = oneshot::channel();
+        let request = Request {
+            context,
+            header,
+            raw_body,
+            enqueue_time: Instant::now(),
+            response,
+        };
+
+        self.sender.send(request).await.ok()?;
+        Some(receiver)
+    }
+}
+
+async fn work(
+    requests: Arc<Mutex<mpsc::Receiver<Request>>>,
+    metrics: Arc<RequestMetrics>,
+    broker: Arc<Broker>,
+) {
+    loop {
+        // The lock is only held while waiting for a request, so the other
+        // workers can take the next one while this one is handled.
+        let Some(request) = requests.lock().await.recv().await else {
+            break;
+        };
+
+        let dequeue_time = Instant::now();
+        let reply =
+            handle_request(&broker, &request.context, &request.header, request.raw_body).await;
+        let queue_time = dequeue_time - request.enqueue_time;
+        let processing_time = dequeue_time.elapsed();
+
+        tracing::debug!(
+            "{:?} queue time: {queue_time:?} processing time: {processing_time:?}",
+            request.header.api_key()
+        );
+        metrics.record(queue_time, processing_time);
+
+        // The connection may have closed meanwhile.
+        match reply {
+            Reply::Now(response) => {
+                let _ = request.response.send(response);
+            }
+            // Frees the worker while the operation waits, its processing time
+            // only counts until then.
+            Reply::Delayed(response) => {
+                tokio::spawn(async move {
+                    let _ = request.response.send(response.await);
+                });
+            }
+        }
+    }
+}
+
+impl RequestMetrics {
+    fn record(&self, queue_time: Duration, processing_time: Duration) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.queue_time_micros
+            .fetch_add(queue_time.as_micros() as u64, Ordering::Relaxed);
+        self.processing_time_micros
+            .fetch_add(processing_time.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn requests(&self) -> u64 {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    pub fn queue_time(&self) -> Duration {
+        Duration::from_micros(self.queue_time_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn processing_time(&self) -> Duration {
+        Duration::from_micros(self.processing_time_micros.load(Ordering::Relaxed))
+    }
+}
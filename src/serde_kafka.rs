@@ -1,16 +1,35 @@
 mod compact_string;
 mod de;
 mod error;
+mod field_attributes;
+mod frame;
+pub mod int32_array;
+pub mod nullable;
 mod ser;
+pub mod shared_bytes;
 mod simple_seq;
+mod tagged_fields;
 pub mod uuid_as_bytes;
+mod varint;
+pub mod varint_length;
+mod version;
 
 pub use de::{
     from_async_reader_trail_with_message_size, from_async_reader_with_message_size, from_bytes,
-    Deserializer,
+    from_bytes_trail, from_bytes_trail_with_version, from_bytes_with_version,
+    from_shared_bytes_with_version, read_message, Deserializer,
+};
+pub use error::{Error, ErrorKind, Result};
+pub use ser::{
+    to_async_writer_with_message_size, to_bytes_mut, to_bytes_mut_with_message_size,
+    to_bytes_mut_with_version, with_message_size, Serializer,
 };
-pub use error::{Error, Result};
-pub use ser::{to_async_writer_with_message_size, to_bytes_mut, Serializer};
 
 pub use compact_string::*;
-pub use simple_seq::*;
+pub use frame::{
+    Crc32c, Remaining, SizePrefixed, VarIntSizePrefixed, CRC32C_NAME, REMAINING_NAME,
+    SIZE_PREFIXED_NAME, VARINT_SIZE_PREFIXED_NAME,
+};
+pub use tagged_fields::{is_default, TaggedFields, TAGGED_FIELDS_NAME};
+pub use varint::*;
+pub use version::Version;
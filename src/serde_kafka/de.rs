@@ -1,71 +1,354 @@
-use std::{num::TryFromIntError, string::FromUtf8Error};
+use std::{collections::BTreeMap, fmt::Write, mem, str};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes, BytesMut};
 use serde::{
-    de::{self, DeserializeOwned, IntoDeserializer, Visitor},
+    de::{
+        self,
+        value::{BorrowedBytesDeserializer, BorrowedStrDeserializer, MapDeserializer},
+        DeserializeOwned, IntoDeserializer, Visitor,
+    },
     Deserialize,
 };
 use tokio::io::AsyncReadExt;
 
 use super::COMPACT_STRING_NAME;
 
-use super::error::{Error, Result};
+use super::{
+    error::{Error, ErrorKind, Result},
+    field_attributes::FieldAttributes,
+    frame::{CRC32C_NAME, REMAINING_NAME, SIZE_PREFIXED_NAME, VARINT_SIZE_PREFIXED_NAME},
+    int32_array::INT32_ARRAY_NAME,
+    nullable::{
+        NULLABLE_ARRAY_NAME, NULLABLE_COMPACT_STRING_NAME, NULLABLE_STRING_NAME,
+        NULLABLE_STRUCT_NAME,
+    },
+    shared_bytes,
+    tagged_fields::TAGGED_FIELDS_NAME,
+    varint::{
+        get_unsigned_varint, get_varint, get_varlong, UNSIGNED_VARINT_NAME, VARINT_NAME,
+        VARLONG_NAME,
+    },
+    varint_length::VARINT_LENGTH_NAME,
+    version::Version,
+};
 
 pub struct Deserializer<'de> {
     input: &'de [u8],
+    // The whole message, to report error offsets.
+    message: &'de [u8],
+    // Fields and array elements being deserialized, to report error paths.
+    path: Vec<PathSegment>,
+    version: Version,
+    // Wire type of the `Option` being deserialized, see `serde_kafka::nullable`.
+    nullable: Option<&'static str>,
+    // Whether that `Option` may be null at this version, false outside the
+    // `nullable` attribute of its field.
+    null_allowed: bool,
+    // Wire type of the length of the string, array or bytes being
+    // deserialized, when overridden by `serde_kafka::varint_length`.
+    length: Option<&'static str>,
+    // Known tagged fields of the struct being deserialized.
+    frame: StructFrame<'de>,
+}
+
+#[derive(Clone)]
+enum PathSegment {
+    Field(&'static str),
+    Index(usize),
+}
+
+#[derive(Default)]
+struct StructFrame<'de> {
+    known_tags: Vec<u32>,
+    tagged_fields: BTreeMap<u32, &'de [u8]>,
+}
+
+impl StructFrame<'_> {
+    fn new(fields: &[&str]) -> Result<Self> {
+        let mut known_tags = Vec::new();
+        for field in fields {
+            known_tags.extend(FieldAttributes::parse(field)?.tag);
+        }
+
+        Ok(StructFrame {
+            known_tags,
+            ..StructFrame::default()
+        })
+    }
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_bytes(input: &'de [u8]) -> Self {
-        Deserializer { input }
+        Self::from_bytes_with_version(input, Version::LATEST)
+    }
+
+    pub fn from_bytes_with_version(input: &'de [u8], version: Version) -> Self {
+        Deserializer {
+            input,
+            message: input,
+            path: Vec::new(),
+            version,
+            nullable: None,
+            null_allowed: true,
+            length: None,
+            frame: StructFrame::default(),
+        }
+    }
+
+    /// A deserializer for `data`, a part of the message that was set aside.
+    fn nested(&self, data: &'de [u8]) -> Self {
+        Deserializer {
+            input: data,
+            message: self.message,
+            path: self.path.clone(),
+            version: self.version,
+            nullable: None,
+            null_allowed: self.null_allowed,
+            length: None,
+            frame: StructFrame::default(),
+        }
+    }
+
+    /// Adds the current offset and field path to `error`, unless it was
+    /// already located by a deeper value.
+    fn locate(&self, error: Error) -> Error {
+        if error.is_located() {
+            return error;
+        }
+
+        let offset = self.input.as_ptr() as usize - self.message.as_ptr() as usize;
+
+        let mut path = String::new();
+        for segment in &self.path {
+            match segment {
+                PathSegment::Field(name) if path.is_empty() => path.push_str(name),
+                PathSegment::Field(name) => write!(path, ".{name}").unwrap(),
+                PathSegment::Index(index) => write!(path, "[{index}]").unwrap(),
+            }
+        }
+
+        error.located(offset, path)
+    }
+
+    /// Checks that `needed` more bytes can be read.
+    fn ensure(&self, needed: usize) -> Result<()> {
+        if self.input.remaining() < needed {
+            return Err(Error::eof(needed, self.input.remaining()));
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'de [u8]> {
+        self.ensure(length)?;
+
+        let (bytes, rest) = self.input.split_at(length);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn take_str(&mut self, length: usize) -> Result<&'de str> {
+        self.ensure(length)?;
+
+        // Checked before advancing, so the error points at the string.
+        let string = str::from_utf8(&self.input[..length])?;
+        self.input.advance(length);
+        Ok(string)
+    }
+
+    /// Reads the `INT16` length prefix of a string.
+    fn get_string_length(&mut self) -> Result<usize> {
+        if self.length.take() == Some(VARINT_LENGTH_NAME) {
+            return self.get_varint_length();
+        }
+
+        self.ensure(2)?;
+
+        non_negative(self.input.get_i16().into())
+    }
+
+    /// Reads the length prefix of an array, `INT32` in non-flexible versions.
+    fn get_array_length(&mut self) -> Result<usize> {
+        if self.length.take() == Some(VARINT_LENGTH_NAME) {
+            return self.get_varint_length();
+        }
+        if self.version.flexible {
+            return self.get_compact_length();
+        }
+
+        self.get_int32_length()
+    }
+
+    fn get_int32_length(&mut self) -> Result<usize> {
+        self.ensure(4)?;
+
+        non_negative(self.input.get_i32().into())
+    }
+
+    /// Reads the `VARINT` length prefix used inside records.
+    fn get_varint_length(&mut self) -> Result<usize> {
+        non_negative(get_varint(&mut self.input)?.into())
+    }
+
+    /// Whether the next value is the null marker of the `Option` being
+    /// deserialized, consuming it if so.
+    fn take_null(&mut self) -> Result<bool> {
+        let nullable = self.nullable.take();
+        let null_allowed = mem::replace(&mut self.null_allowed, true);
+
+        if self.length == Some(VARINT_LENGTH_NAME) {
+            let mut peek = self.input;
+            let is_null = get_varint(&mut peek)? == -1;
+            if is_null {
+                self.input = peek;
+                self.length = None;
+            }
+            return Ok(is_null);
+        }
+
+        let marker: &[u8] = match nullable {
+            // Values of fields that aren't nullable at this version, structs
+            // included, have no null marker.
+            Some(_) if !null_allowed => return Ok(false),
+            Some(NULLABLE_COMPACT_STRING_NAME | NULLABLE_ARRAY_NAME) if self.version.flexible => {
+                let mut peek = self.input;
+                let is_null = get_unsigned_varint(&mut peek)? == 0;
+                if is_null {
+                    self.input = peek;
+                }
+                return Ok(is_null);
+            }
+            Some(NULLABLE_STRING_NAME | NULLABLE_COMPACT_STRING_NAME) => &(-1i16).to_be_bytes(),
+            Some(NULLABLE_ARRAY_NAME) => &(-1i32).to_be_bytes(),
+            // Structs are always preceded by a marker, 1 when present.
+            Some(NULLABLE_STRUCT_NAME) => {
+                self.ensure(1)?;
+                return Ok(self.input.get_i8() < 0);
+            }
+            _ => {
+                return Err(Error::message(
+                    "Option fields must be deserialized with serde_kafka::nullable",
+                ))
+            }
+        };
+
+        let is_null = self.input.starts_with(marker);
+        if is_null {
+            self.input.advance(marker.len());
+        }
+        Ok(is_null)
     }
+
+    /// Reads the `UNSIGNED_VARINT` length prefix used by compact strings,
+    /// arrays and bytes, where 0 is the null marker.
+    fn get_compact_length(&mut self) -> Result<usize> {
+        let stored_length = get_unsigned_varint(&mut self.input)?;
+
+        non_negative(i64::from(stored_length) - 1)
+    }
+}
+
+fn non_negative(length: i64) -> Result<usize> {
+    usize::try_from(length).map_err(|_| ErrorKind::NegativeLength(length).into())
+}
+
+/// Deserializes a value from the start of `s`, returning the bytes after it.
+pub fn from_bytes_trail<'a, T>(s: &'a [u8]) -> Result<(T, &'a [u8])>
+where
+    T: Deserialize<'a>,
+{
+    from_bytes_trail_with_version(s, Version::LATEST)
 }
 
-pub fn from_bytes_trail<'a, T>(s: &'a [u8]) -> Result<(T, Vec<u8>)>
+pub fn from_bytes_trail_with_version<'a, T>(s: &'a [u8], version: Version) -> Result<(T, &'a [u8])>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_bytes(s);
-    let t = T::deserialize(&mut deserializer)?;
+    let mut deserializer = Deserializer::from_bytes_with_version(s, version);
+    let t = T::deserialize(&mut deserializer).map_err(|e| deserializer.locate(e))?;
 
-    Ok((t, deserializer.input.to_owned()))
+    Ok((t, deserializer.input))
 }
 
 pub fn from_bytes<'a, T>(s: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_bytes(s);
-    let t = T::deserialize(&mut deserializer)?;
+    from_bytes_with_version(s, Version::LATEST)
+}
+
+pub fn from_bytes_with_version<'a, T>(s: &'a [u8], version: Version) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes_with_version(s, version);
+    let t = T::deserialize(&mut deserializer).map_err(|e| deserializer.locate(e))?;
     if deserializer.input.is_empty() {
         Ok(t)
     } else {
-        Err(Error::TrailingCharacters)
+        let trailing = ErrorKind::TrailingBytes(deserializer.input.len());
+        Err(deserializer.locate(trailing.into()))
     }
 }
 
-pub async fn from_async_reader_with_message_size<R, D>(reader: &mut R) -> Result<D>
+/// Like `from_bytes_with_version`, with `serde_kafka::shared_bytes` fields
+/// sliced from `bytes` instead of copied.
+pub fn from_shared_bytes_with_version<T>(bytes: &Bytes, version: Version) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    shared_bytes::with_shared(bytes, || from_bytes_with_version(bytes, version))
+}
+
+/// Reads a message preceded by its `INT32` size, which is checked against
+/// `max_size` before the message is allocated.
+pub async fn read_message<R>(reader: &mut R, max_size: usize) -> Result<Bytes>
+where
+    R: AsyncReadExt + Unpin,
+{
+    let message_size = non_negative(reader.read_i32().await?.into())?;
+    if message_size > max_size {
+        return Err(ErrorKind::MessageTooLarge {
+            size: message_size,
+            max: max_size,
+        }
+        .into());
+    }
+    let mut message_bytes = BytesMut::zeroed(message_size);
+    reader.read_exact(&mut message_bytes).await?;
+
+    Ok(message_bytes.freeze())
+}
+
+/// Reads a message of at most `max_message_size` bytes and deserializes it
+/// whole at `version`.
+pub async fn from_async_reader_with_message_size<R, D>(
+    reader: &mut R,
+    max_message_size: usize,
+    version: Version,
+) -> Result<D>
 where
     R: AsyncReadExt + Unpin,
     D: DeserializeOwned,
 {
-    let message_size: i32 = reader.read_i32().await.unwrap();
-    let mut message_bytes = vec![0u8; message_size.try_into().unwrap()];
-    reader.read_exact(&mut message_bytes).await.unwrap();
+    let message_bytes = read_message(reader, max_message_size).await?;
 
-    from_bytes(&message_bytes)
+    from_shared_bytes_with_version(&message_bytes, version)
 }
 
-pub async fn from_async_reader_trail_with_message_size<R, D>(reader: &mut R) -> Result<(D, Vec<u8>)>
+/// Reads a message and deserializes a value from its start, returning the
+/// rest of the message without copying it.
+pub async fn from_async_reader_trail_with_message_size<R, D>(
+    reader: &mut R,
+    max_message_size: usize,
+) -> Result<(D, Bytes)>
 where
     R: AsyncReadExt + Unpin,
     D: DeserializeOwned,
 {
-    let message_size: i32 = reader.read_i32().await.unwrap();
-    let mut message_bytes = vec![0u8; message_size.try_into().unwrap()];
-    reader.read_exact(&mut message_bytes).await.unwrap();
+    let message_bytes = read_message(reader, max_message_size).await?;
+    let (value, trail) = from_bytes_trail(&message_bytes)?;
 
-    from_bytes_trail(&message_bytes)
+    Ok((value, message_bytes.slice_ref(trail)))
 }
 
 impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
@@ -82,6 +365,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(1)?;
         let value = self.input.get_u8();
         visitor.visit_bool(value != 0)
     }
@@ -90,6 +374,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(1)?;
         let value = self.input.get_i8();
         visitor.visit_i8(value)
     }
@@ -98,6 +383,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(2)?;
         let value = self.input.get_i16();
         visitor.visit_i16(value)
     }
@@ -106,6 +392,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(4)?;
         let value = self.input.get_i32();
         visitor.visit_i32(value)
     }
@@ -114,6 +401,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(8)?;
         let value = self.input.get_i64();
         visitor.visit_i64(value)
     }
@@ -122,21 +410,25 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
+        self.ensure(1)?;
         let value = self.input.get_u8();
         visitor.visit_u8(value)
     }
 
-    fn deserialize_u16<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        self.ensure(2)?;
+        let value = self.input.get_u16();
+        visitor.visit_u16(value)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
+        self.ensure(4)?;
         let value = self.input.get_u32();
         visitor.visit_u32(value)
     }
@@ -155,11 +447,13 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         unimplemented!()
     }
 
-    fn deserialize_f64<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        self.ensure(8)?;
+        let value = self.input.get_f64();
+        visitor.visit_f64(value)
     }
 
     fn deserialize_char<V>(self, _visitor: V) -> Result<V::Value>
@@ -169,56 +463,32 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         unimplemented!()
     }
 
-    fn deserialize_str<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_str<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        let length = self.get_string_length()?;
+        let string = self.take_str(length)?;
+
+        visitor.visit_borrowed_str(string)
     }
 
     fn deserialize_string<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let stored_length = self.input.get_i16();
-
-        let length = stored_length as usize;
-
-        if length == 0 {
-            return visitor.visit_string("".into());
-        }
-
-        if self.input.remaining() < length {
-            return Err(Error::Message("Unexpected EOF".to_string()));
-        }
-
-        let string_bytes = &self.input[..length];
-
-        let string = String::from_utf8(string_bytes.to_vec())
-            .map_err(|e: FromUtf8Error| Error::Message(e.to_string()))?;
-        self.input.advance(length);
-
-        visitor.visit_string(string)
+        self.deserialize_str(visitor)
     }
 
+    // `BYTES`, or `COMPACT_BYTES` in flexible versions.
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let length = self
-            .input
-            .get_i32()
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
+        let length = self.get_array_length()?;
+        let bytes = self.take(length)?;
 
-        if self.input.remaining() < length {
-            return Err(Error::Message("Unexpected EOF".to_string()));
-        }
-
-        let bytes = &self.input[..length];
-        self.input.advance(length);
-
-        visitor.visit_bytes(bytes)
+        visitor.visit_borrowed_bytes(bytes)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
@@ -228,11 +498,15 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         self.deserialize_bytes(visitor)
     }
 
-    fn deserialize_option<V>(self, _visitor: V) -> Result<V::Value>
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        if self.take_null()? {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
     }
 
     fn deserialize_unit<V>(self, _visitor: V) -> Result<V::Value>
@@ -253,33 +527,106 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        if name == COMPACT_STRING_NAME {
-            let stored_length = self.input.get_i8();
-
-            if stored_length <= 0 {
-                return Err(Error::Message(format!(
-                    "Invalid length for non-optional compact string: {}",
-                    stored_length
-                )));
+        match name {
+            COMPACT_STRING_NAME => {
+                let length = if self.version.flexible {
+                    self.get_compact_length()?
+                } else {
+                    self.get_string_length()?
+                };
+                let s = self.take_str(length)?;
+
+                visitor.visit_newtype_struct(BorrowedStrDeserializer::new(s))
             }
-
-            let length = (stored_length as usize) - 1;
-
-            if self.input.remaining() < length {
-                return Err(Error::Message("Unexpected EOF".to_string()));
+            UNSIGNED_VARINT_NAME => {
+                let value = get_unsigned_varint(&mut self.input)?;
+                visitor.visit_newtype_struct(value.into_deserializer())
             }
-
-            let string_bytes = &self.input[..length];
-
-            let s = String::from_utf8(string_bytes.to_vec())
-                .map_err(|e| Error::Message(e.to_string()))?;
-
-            self.input.advance(length);
-
-            let de = s.into_deserializer();
-            visitor.visit_newtype_struct(de)
-        } else {
-            visitor.visit_newtype_struct(self)
+            VARINT_NAME => {
+                let value = get_varint(&mut self.input)?;
+                visitor.visit_newtype_struct(value.into_deserializer())
+            }
+            VARLONG_NAME => {
+                let value = get_varlong(&mut self.input)?;
+                visitor.visit_newtype_struct(value.into_deserializer())
+            }
+            NULLABLE_STRING_NAME
+            | NULLABLE_COMPACT_STRING_NAME
+            | NULLABLE_ARRAY_NAME
+            | NULLABLE_STRUCT_NAME => {
+                self.nullable = Some(name);
+                visitor.visit_newtype_struct(self)
+            }
+            VARINT_LENGTH_NAME => {
+                self.length = Some(name);
+                visitor.visit_newtype_struct(self)
+            }
+            SIZE_PREFIXED_NAME | VARINT_SIZE_PREFIXED_NAME => {
+                let size = if name == SIZE_PREFIXED_NAME {
+                    self.get_int32_length()?
+                } else {
+                    self.get_varint_length()?
+                };
+                let data = self.take(size)?;
+
+                let mut nested = self.nested(data);
+                let value = visitor
+                    .visit_newtype_struct(&mut nested)
+                    .map_err(|e| nested.locate(e))?;
+                if !nested.input.is_empty() {
+                    let trailing = ErrorKind::TrailingBytes(nested.input.len());
+                    return Err(nested.locate(trailing.into()));
+                }
+                Ok(value)
+            }
+            CRC32C_NAME => {
+                self.ensure(4)?;
+                let (crc, data) = self.input.split_at(4);
+                let expected = u32::from_be_bytes(crc.try_into().unwrap());
+                let actual = crc32c::crc32c(data);
+                // Checked before advancing, so the error points at the CRC.
+                if expected != actual {
+                    return Err(ErrorKind::InvalidCrc { expected, actual }.into());
+                }
+                self.input = data;
+
+                visitor.visit_newtype_struct(self)
+            }
+            REMAINING_NAME => {
+                let data = self.take(self.input.len())?;
+                visitor.visit_newtype_struct(BorrowedBytesDeserializer::new(data))
+            }
+            INT32_ARRAY_NAME => {
+                let length = self.get_int32_length()?;
+
+                visitor.visit_seq(SeqAccess {
+                    deserializer: self,
+                    len: length,
+                    index: 0,
+                })
+            }
+            TAGGED_FIELDS_NAME if !self.version.flexible => {
+                visitor.visit_map(MapDeserializer::new(std::iter::empty::<(u32, &[u8])>()))
+            }
+            TAGGED_FIELDS_NAME => {
+                let count = get_unsigned_varint(&mut self.input)?;
+
+                let mut unknown = Vec::new();
+                for _ in 0..count {
+                    let tag = get_unsigned_varint(&mut self.input)?;
+                    let size = get_unsigned_varint(&mut self.input)? as usize;
+                    let data = self.take(size)?;
+
+                    if self.frame.known_tags.contains(&tag) {
+                        self.frame.tagged_fields.insert(tag, data);
+                    } else {
+                        unknown.push((tag, data));
+                    }
+                }
+
+                visitor.visit_map(MapDeserializer::new(unknown.into_iter()))
+            }
+            _ => visitor.visit_newtype_struct(self),
         }
     }
 
@@ -287,15 +634,12 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        let length: usize = self
-            .input
-            .get_i8()
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
+        let length = self.get_array_length()?;
 
         visitor.visit_seq(SeqAccess {
             deserializer: self,
-            len: length - 1,
+            len: length,
+            index: 0,
         })
     }
 
@@ -306,6 +650,7 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
         visitor.visit_seq(SeqAccess {
             deserializer: self,
             len,
+            index: 0,
         })
     }
 
@@ -337,22 +682,48 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_seq(SeqAccess {
+        let frame = mem::replace(&mut self.frame, StructFrame::new(fields)?);
+
+        let result = visitor.visit_seq(StructAccess {
             deserializer: self,
-            len: fields.len(),
-        })
+            fields: fields.iter(),
+        });
+
+        self.frame = frame;
+        result
     }
 
     fn deserialize_enum<V>(
         self,
-        _name: &'static str,
-        _variants: &'static [&'static str],
-        _visitor: V,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        unimplemented!()
+        // The discriminant is peeked, it is also a field of the variant.
+        let offset = FieldAttributes::parse(name)?.discriminant.ok_or_else(|| {
+            Error::message(format!("Enum `{name}` must have a discriminant attribute"))
+        })?;
+        self.ensure(offset + 1)?;
+        let value = i64::from(self.input[offset] as i8);
+
+        let mut variant = None;
+        for candidate in variants {
+            if FieldAttributes::parse(candidate)?.value == Some(value) {
+                variant = Some(*candidate);
+            }
+        }
+        let variant = variant.ok_or(ErrorKind::UnknownDiscriminant {
+            name: name.split(';').next().unwrap_or(name),
+            value,
+        })?;
+
+        visitor.visit_enum(EnumAccess {
+            deserializer: self,
+            variant,
+        })
     }
 
     fn deserialize_identifier<V>(self, _visitor: V) -> Result<V::Value>
@@ -373,29 +744,143 @@ impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
 struct SeqAccess<'a, 'de: 'a> {
     deserializer: &'a mut Deserializer<'de>,
     len: usize,
+    index: usize,
 }
 
 impl<'de, 'a> de::SeqAccess<'de> for SeqAccess<'a, 'de> {
     type Error = Error;
 
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.len)
+    }
+
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
     where
         T: de::DeserializeSeed<'de>,
     {
-        if self.len > 0 {
-            self.len -= 1;
-            let value = de::DeserializeSeed::deserialize(seed, &mut *self.deserializer)?;
-            Ok(Some(value))
-        } else {
-            Ok(None)
+        if self.len == 0 {
+            return Ok(None);
         }
+        self.len -= 1;
+
+        let deserializer = &mut *self.deserializer;
+        deserializer.path.push(PathSegment::Index(self.index));
+        self.index += 1;
+
+        let result = seed
+            .deserialize(&mut *deserializer)
+            .map_err(|e| deserializer.locate(e));
+        deserializer.path.pop();
+
+        result.map(Some)
+    }
+}
+
+struct StructAccess<'a, 'de: 'a> {
+    deserializer: &'a mut Deserializer<'de>,
+    fields: std::slice::Iter<'static, &'static str>,
+}
+
+impl<'de, 'a> de::SeqAccess<'de> for StructAccess<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        let Some(field) = self.fields.next() else {
+            return Ok(None);
+        };
+
+        // Fields missing from this version are left to their
+        // `#[serde(default)]`.
+        let attributes = FieldAttributes::parse(field)?;
+        if !attributes.is_present(self.deserializer.version) {
+            return Ok(None);
+        }
+
+        let deserializer = &mut *self.deserializer;
+        deserializer.null_allowed = attributes.is_nullable(deserializer.version);
+        let name = field.split(';').next().unwrap_or(field);
+        deserializer.path.push(PathSegment::Field(name));
+
+        let result = match attributes.tag {
+            // Tagged fields missing from the section are left to their
+            // `#[serde(default)]`.
+            Some(tag) => match deserializer.frame.tagged_fields.remove(&tag) {
+                Some(data) => {
+                    let mut nested = deserializer.nested(data);
+                    seed.deserialize(&mut nested)
+                        .map(Some)
+                        .map_err(|e| nested.locate(e))
+                }
+                None => Ok(None),
+            },
+            None => seed
+                .deserialize(&mut *deserializer)
+                .map(Some)
+                .map_err(|e| deserializer.locate(e)),
+        };
+        deserializer.path.pop();
+
+        result
+    }
+}
+
+struct EnumAccess<'a, 'de: 'a> {
+    deserializer: &'a mut Deserializer<'de>,
+    variant: &'static str,
+}
+
+impl<'de, 'a> de::EnumAccess<'de> for EnumAccess<'a, 'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self::Variant)>
+    where
+        V: de::DeserializeSeed<'de>,
+    {
+        let variant =
+            seed.deserialize(IntoDeserializer::<Error>::into_deserializer(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a> de::VariantAccess<'de> for EnumAccess<'a, 'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+    where
+        T: de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.deserializer)
+    }
+
+    fn tuple_variant<V>(self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_tuple(self.deserializer, len, visitor)
+    }
+
+    fn struct_variant<V>(self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_struct(self.deserializer, self.variant, fields, visitor)
     }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
 #[cfg(test)]
 mod test {
-    use crate::serde_kafka::CompactString;
+    use crate::serde_kafka::{
+        nullable, CompactString, TaggedFields, UnsignedVarInt, VarInt, VarLong,
+    };
 
     use super::*;
 
@@ -491,4 +976,443 @@ mod test {
         };
         assert_eq!(expected, from_bytes(bytes).unwrap());
     }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Varints {
+        unsigned_varint: UnsignedVarInt,
+        varint: VarInt,
+        varlong: VarLong,
+    }
+
+    #[test]
+    fn test_varints() {
+        let bytes: &[u8] = &[
+            0xac, 0x02, // unsigned_varint: 300
+            0x81, 0x01, // varint: -65
+            0xb0, 0xe0, 0xd7, 0x85, 0xbc, 0x64, // varlong: 1726045943832
+        ];
+
+        let expected = Varints {
+            unsigned_varint: UnsignedVarInt(300),
+            varint: VarInt(-65),
+            varlong: VarLong(1_726_045_943_832),
+        };
+        assert_eq!(expected, from_bytes(bytes).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct LongCompact {
+        compact_string: CompactString,
+        ca_i8: Vec<i8>,
+    }
+
+    #[test]
+    fn test_long_compact_lengths() {
+        let mut bytes = vec![0xc9, 0x01]; // compact_string len: 201 (200+1)
+        bytes.extend_from_slice(&[b'k'; 200]);
+        bytes.extend_from_slice(&[0xad, 0x02]); // ca_i8 len: 301 (300+1)
+        bytes.extend_from_slice(&[0x7f; 300]);
+
+        let expected = LongCompact {
+            compact_string: "k".repeat(200).into(),
+            ca_i8: vec![i8::MAX; 300],
+        };
+        assert_eq!(expected, from_bytes(&bytes).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Nullables {
+        #[serde(with = "nullable")]
+        string: Option<String>,
+        #[serde(with = "nullable")]
+        compact_string: Option<CompactString>,
+        #[serde(with = "nullable")]
+        ca_i8: Option<Vec<i8>>,
+    }
+
+    #[test]
+    fn test_nullables() {
+        let bytes: &[u8] = &[
+            0xff, 0xff, // string: null (len -1 as i16)
+            0x00, // compact_string: null (len 0 as uvarint)
+            0x00, // ca_i8: null (len 0 as uvarint)
+        ];
+        let expected = Nullables {
+            string: None,
+            compact_string: None,
+            ca_i8: None,
+        };
+        assert_eq!(expected, from_bytes(bytes).unwrap());
+
+        let bytes: &[u8] = &[
+            0x00, 0x01, b'a', // string: "a"
+            0x02, b'b', // compact_string: "b"
+            0x01, // ca_i8: empty
+        ];
+        let expected = Nullables {
+            string: Some("a".into()),
+            compact_string: Some("b".into()),
+            ca_i8: Some(vec![]),
+        };
+        assert_eq!(expected, from_bytes(bytes).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct NullableVersions {
+        #[serde(rename = "string;nullable=1+", with = "nullable")]
+        string: Option<String>,
+    }
+
+    #[test]
+    fn test_nullable_versions() {
+        let v0 = Version {
+            version: 0,
+            flexible: false,
+        };
+        let v1 = Version { version: 1, ..v0 };
+
+        let null: &[u8] = &[0xff, 0xff]; // string: null (len -1 as i16)
+        assert!(from_bytes_with_version::<NullableVersions>(null, v0).is_err());
+        assert_eq!(
+            from_bytes_with_version::<NullableVersions>(null, v1).unwrap(),
+            NullableVersions { string: None }
+        );
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Borrowed<'a> {
+        string: &'a str,
+        compact_string: CompactString,
+        bytes: &'a [u8],
+        #[serde(with = "nullable", borrow)]
+        nullable_bytes: Option<&'a [u8]>,
+    }
+
+    #[test]
+    fn test_borrowed() {
+        let bytes: &[u8] = &[
+            0x00, 0x01, b'a', // string: "a"
+            0x02, b'b', // compact_string: "b"
+            0x03, 0xaa, 0xbb, // bytes: compact len 2
+            0x00, // nullable_bytes: null
+        ];
+        let expected = Borrowed {
+            string: "a",
+            compact_string: "b".into(),
+            bytes: &[0xaa, 0xbb],
+            nullable_bytes: None,
+        };
+        let borrowed: Borrowed = from_bytes(bytes).unwrap();
+        assert_eq!(expected, borrowed);
+        assert!(bytes.as_ptr_range().contains(&borrowed.string.as_ptr()));
+        assert!(bytes.as_ptr_range().contains(&borrowed.bytes.as_ptr()));
+
+        let bytes: &[u8] = &[
+            0x00, 0x01, b'a', // string: "a"
+            0x00, 0x01, b'b', // compact_string: "b"
+            0x00, 0x00, 0x00, 0x02, 0xaa, 0xbb, // bytes: len 2
+            0xff, 0xff, 0xff, 0xff, // nullable_bytes: null (len -1 as i32)
+        ];
+        let version = Version {
+            version: 0,
+            flexible: false,
+        };
+        assert_eq!(expected, from_bytes_with_version(bytes, version).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Shared {
+        #[serde(with = "shared_bytes")]
+        bytes: Bytes,
+        #[serde(with = "shared_bytes::nullable")]
+        nullable_bytes: Option<Bytes>,
+    }
+
+    #[test]
+    fn test_shared_bytes() {
+        let bytes = Bytes::from_static(&[
+            0x03, 0xaa, 0xbb, // bytes: compact len 2
+            0x02, 0xcc, // nullable_bytes: compact len 1
+        ]);
+        let expected = Shared {
+            bytes: Bytes::from_static(&[0xaa, 0xbb]),
+            nullable_bytes: Some(Bytes::from_static(&[0xcc])),
+        };
+
+        let shared: Shared = from_shared_bytes_with_version(&bytes, Version::LATEST).unwrap();
+        assert_eq!(expected, shared);
+        assert_eq!(shared.bytes.as_ptr(), bytes[1..].as_ptr());
+        assert_eq!(shared.nullable_bytes.unwrap().as_ptr(), bytes[4..].as_ptr());
+
+        // Copied when not deserialized from shared bytes.
+        let copied: Shared = from_bytes(&bytes).unwrap();
+        assert_eq!(expected, copied);
+        assert!(!bytes.as_ptr_range().contains(&copied.bytes.as_ptr()));
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_trail() {
+        let mut message: &[u8] = &[
+            0x00, 0x00, 0x00, 0x03, // size: 3
+            0x7f, // i8: 127
+            0xaa, 0xbb, // trail
+        ];
+        let (value, trail): (i8, Bytes) =
+            from_async_reader_trail_with_message_size(&mut message, 3)
+                .await
+                .unwrap();
+        assert_eq!(value, i8::MAX);
+        assert_eq!(trail, &[0xaa, 0xbb][..]);
+
+        // Rejected before the message is read.
+        let mut message: &[u8] = &[0x7f, 0xff, 0xff, 0xff];
+        let error = from_async_reader_trail_with_message_size::<_, i8>(&mut message, 1024)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::MessageTooLarge {
+                size: 0x7fffffff,
+                max: 1024
+            }
+        ));
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Tagged {
+        i8: i8,
+        tagged_fields: TaggedFields,
+        #[serde(rename = "known;tag=1", default)]
+        known: i16,
+        sub_struct: SubStruct,
+    }
+
+    #[test]
+    fn test_tagged_fields() {
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x03, // tagged fields: 3
+            0x00, 0x01, 0xaa, // tag 0, size 1
+            0x01, 0x02, 0x01, 0x02, // tag 1 (known), size 2
+            0x05, 0x02, 0xbb, 0xcc, // tag 5, size 2
+            0x7f, // i8: 127
+            0x7f, 0xff, //i16: 32767
+            0x7f, 0xff, 0xff, 0xff, // i32: 2147483647
+        ];
+        let expected = Tagged {
+            i8: i8::MAX,
+            tagged_fields: TaggedFields([(0, vec![0xaa]), (5, vec![0xbb, 0xcc])].into()),
+            known: 0x0102,
+            sub_struct: SubStruct {
+                i8: i8::MAX,
+                i16: i16::MAX,
+                i32: i32::MAX,
+            },
+        };
+        assert_eq!(expected, from_bytes(bytes).unwrap());
+
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x00, // tagged fields: 0
+            0x7f, // i8: 127
+            0x7f, 0xff, //i16: 32767
+            0x7f, 0xff, 0xff, 0xff, // i32: 2147483647
+        ];
+        let expected = Tagged {
+            i8: i8::MAX,
+            tagged_fields: TaggedFields::default(),
+            known: 0,
+            sub_struct: SubStruct {
+                i8: i8::MAX,
+                i16: i16::MAX,
+                i32: i32::MAX,
+            },
+        };
+        assert_eq!(expected, from_bytes(bytes).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Versioned {
+        i8: i8,
+        #[serde(rename = "i16;versions=1+", default)]
+        i16: i16,
+        compact_string: CompactString,
+        array: Vec<i8>,
+        #[serde(with = "nullable")]
+        nullable_array: Option<Vec<i8>>,
+        tagged_fields: TaggedFields,
+    }
+
+    #[test]
+    fn test_versioned() {
+        let v0 = Version {
+            version: 0,
+            flexible: false,
+        };
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            // i16 is missing from v0, and so are tagged fields
+            0x00, 0x02, 0x68, 0x69, // compact_string as STRING: "hi"
+            0x00, 0x00, 0x00, 0x01, 0x7f, // array: INT32 len 1
+            0xff, 0xff, 0xff, 0xff, // nullable_array: INT32 -1
+        ];
+        let expected = Versioned {
+            i8: i8::MAX,
+            i16: 0,
+            compact_string: "hi".into(),
+            array: vec![i8::MAX],
+            nullable_array: None,
+            tagged_fields: TaggedFields::default(),
+        };
+        assert_eq!(expected, from_bytes_with_version(bytes, v0).unwrap());
+
+        let v1 = Version {
+            version: 1,
+            flexible: true,
+        };
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x7f, 0xff, // i16: 32767
+            0x03, 0x68, 0x69, // compact_string: "hi"
+            0x02, 0x7f, // array: compact len 1
+            0x00, // nullable_array: null
+            0x01, 0x00, 0x01, 0xaa, // tagged fields: tag 0, size 1
+        ];
+        let expected = Versioned {
+            i8: i8::MAX,
+            i16: i16::MAX,
+            compact_string: "hi".into(),
+            array: vec![i8::MAX],
+            nullable_array: None,
+            tagged_fields: TaggedFields([(0, vec![0xaa])].into()),
+        };
+        assert_eq!(expected, from_bytes_with_version(bytes, v1).unwrap());
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Named {
+        i8: i8,
+        items: Vec<Item>,
+    }
+
+    #[derive(Deserialize, PartialEq, Debug)]
+    struct Item {
+        name: CompactString,
+    }
+
+    #[test]
+    fn test_errors() {
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x03, // items: compact len 2
+            0x02, 0x61, // name: "a"
+            0x05, 0x61, 0x62, // name: len 4, but only "ab"
+        ];
+        let error = from_bytes::<Named>(bytes).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Eof {
+                needed: 4,
+                available: 2
+            }
+        ));
+        assert_eq!(error.offset(), Some(5));
+        assert_eq!(error.path(), Some("items[1].name"));
+        assert_eq!(
+            error.to_string(),
+            "unexpected end of input, needed 4 bytes but 2 are available at byte 5 in `items[1].name`"
+        );
+
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x02, // items: compact len 1
+            0x02, 0xff, // name: invalid UTF-8
+        ];
+        let error = from_bytes::<Named>(bytes).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::InvalidUtf8(_)));
+        assert_eq!(error.offset(), Some(3));
+        assert_eq!(error.path(), Some("items[0].name"));
+
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0xff, 0xff, 0xff, 0xff, // items: INT32 len -1
+        ];
+        let v0 = Version {
+            version: 0,
+            flexible: false,
+        };
+        let error = from_bytes_with_version::<Named>(bytes, v0).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::NegativeLength(-1)));
+        assert_eq!(error.offset(), Some(5));
+        assert_eq!(error.path(), Some("items"));
+
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x01, // items: compact len 0
+            0x00, // trailing
+        ];
+        let error = from_bytes::<Named>(bytes).unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::TrailingBytes(1)));
+        assert_eq!(error.offset(), Some(2));
+        assert_eq!(error.path(), None);
+    }
+
+    #[test]
+    fn test_short_input() {
+        let bytes: &[u8] = &[
+            0x7f, // i8: 127
+            0x7f, // i16: 1 of 2 bytes
+        ];
+        let error = from_bytes::<SubStruct>(bytes).unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::Eof {
+                needed: 2,
+                available: 1
+            }
+        ));
+        assert_eq!(error.offset(), Some(1));
+        assert_eq!(error.path(), Some("i16"));
+
+        for length in 0..7 {
+            let bytes = [0x7f, 0x7f, 0xff, 0x7f, 0xff, 0xff, 0xff];
+            assert!(from_bytes::<SubStruct>(&bytes[..length]).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_errors() {
+        let mut negative_size: &[u8] = &[0xff, 0xff, 0xff, 0xff];
+        let error = from_async_reader_with_message_size::<_, SubStruct>(
+            &mut negative_size,
+            64,
+            Version::LATEST,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::NegativeLength(-1)));
+
+        let mut truncated: &[u8] = &[0x00, 0x00, 0x00, 0x07, 0x7f];
+        let error = from_async_reader_with_message_size::<_, SubStruct>(
+            &mut truncated,
+            64,
+            Version::LATEST,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(error.kind(), ErrorKind::Io(_)));
+
+        let mut too_large: &[u8] = &[0x00, 0x00, 0x00, 0x41, 0x7f];
+        let error = from_async_reader_with_message_size::<_, SubStruct>(
+            &mut too_large,
+            64,
+            Version::LATEST,
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            error.kind(),
+            ErrorKind::MessageTooLarge { size: 65, max: 64 }
+        ));
+    }
 }
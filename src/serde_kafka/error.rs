@@ -2,74 +2,159 @@ use std::{
     self,
     fmt::{self, Display},
     io,
+    str::Utf8Error,
 };
 
 use serde::{de, ser};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-// This is a bare-bones implementation. A real library would provide additional
-// information in its error type, for example the line and column at which the
-// error occurred, the byte offset into the input, or the current key being
-// processed.
+/// An error from `serde_kafka`. Decoding errors carry the byte offset into the
+/// message and the field path (e.g. `topics[0].name`) where decoding failed.
 #[derive(Debug)]
-pub enum Error {
-    // One or more variants that can be created by data structures through the
-    // `ser::Error` and `de::Error` traits. For example the Serialize impl for
-    // Mutex<T> might return an error because the mutex is poisoned, or the
-    // Deserialize impl for a struct may return an error because a required
-    // field is missing.
+pub struct Error {
+    kind: ErrorKind,
+    offset: Option<usize>,
+    path: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    // Errors created by data structures through the `ser::Error` and
+    // `de::Error` traits, e.g. a missing field, and by the format for
+    // unsupported shapes of data.
     Message(String),
     Io(io::Error),
 
-    // Zero or more variants that can be created directly by the Serializer and
-    // Deserializer without going through `ser::Error` and `de::Error`. These
-    // are specific to the format, in this case JSON.
-    Eof,
-    Syntax,
-    ExpectedBoolean,
-    ExpectedInteger,
-    ExpectedString,
-    ExpectedNull,
-    ExpectedArray,
-    ExpectedArrayComma,
-    ExpectedArrayEnd,
-    ExpectedMap,
-    ExpectedMapColon,
-    ExpectedMapComma,
-    ExpectedMapEnd,
-    ExpectedEnum,
-    TrailingCharacters,
+    Eof { needed: usize, available: usize },
+    InvalidVarint,
+    InvalidUtf8(Utf8Error),
+    NegativeLength(i64),
+    UnknownDiscriminant { name: &'static str, value: i64 },
+    TrailingBytes(usize),
+    InvalidCrc { expected: u32, actual: u32 },
+    MessageTooLarge { size: usize, max: usize },
+}
+
+impl Error {
+    pub(crate) fn message<T: Display>(msg: T) -> Self {
+        ErrorKind::Message(msg.to_string()).into()
+    }
+
+    pub(crate) fn eof(needed: usize, available: usize) -> Self {
+        ErrorKind::Eof { needed, available }.into()
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Byte offset into the message where decoding failed.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Path of the field being decoded when decoding failed.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub(crate) fn is_located(&self) -> bool {
+        self.offset.is_some()
+    }
+
+    pub(crate) fn located(mut self, offset: usize, path: String) -> Self {
+        self.offset = Some(offset);
+        self.path = Some(path).filter(|path| !path.is_empty());
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error {
+            kind,
+            offset: None,
+            path: None,
+        }
+    }
 }
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error::Io(err)
+        ErrorKind::Io(err).into()
+    }
+}
+
+impl From<Utf8Error> for Error {
+    fn from(err: Utf8Error) -> Self {
+        ErrorKind::InvalidUtf8(err).into()
     }
 }
 
 impl ser::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+        Error::message(msg)
     }
 }
 
 impl de::Error for Error {
     fn custom<T: Display>(msg: T) -> Self {
-        Error::Message(msg.to_string())
+        Error::message(msg)
     }
 }
 
-impl Display for Error {
+impl Display for ErrorKind {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Error::Message(msg) => formatter.write_str(msg),
-            Error::Io(err) => write!(formatter, "IO error: {}", err),
-            Error::Eof => formatter.write_str("unexpected end of input"),
-            _ => formatter.write_str("Error"),
-            /* and so forth */
+            ErrorKind::Message(msg) => formatter.write_str(msg),
+            ErrorKind::Io(err) => write!(formatter, "IO error: {err}"),
+            ErrorKind::Eof { needed, available } => write!(
+                formatter,
+                "unexpected end of input, needed {needed} bytes but {available} are available"
+            ),
+            ErrorKind::InvalidVarint => formatter.write_str("invalid varint"),
+            ErrorKind::InvalidUtf8(err) => write!(formatter, "invalid UTF-8: {err}"),
+            ErrorKind::NegativeLength(length) => {
+                write!(formatter, "invalid negative length {length}")
+            }
+            ErrorKind::UnknownDiscriminant { name, value } => {
+                write!(formatter, "unknown {name} discriminant {value}")
+            }
+            ErrorKind::TrailingBytes(count) => write!(formatter, "{count} trailing bytes"),
+            ErrorKind::InvalidCrc { expected, actual } => write!(
+                formatter,
+                "CRC mismatch, expected {expected:#010x} but computed {actual:#010x}"
+            ),
+            ErrorKind::MessageTooLarge { size, max } => {
+                write!(
+                    formatter,
+                    "message of {size} bytes exceeds the maximum of {max}"
+                )
+            }
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.kind.fmt(formatter)?;
+        if let Some(offset) = self.offset {
+            write!(formatter, " at byte {offset}")?;
         }
+        if let Some(path) = &self.path {
+            write!(formatter, " in `{path}`")?;
+        }
+        Ok(())
     }
 }
 
-impl std::error::Error for Error {}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(err) => Some(err),
+            ErrorKind::InvalidUtf8(err) => Some(err),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Protocol attributes of struct fields and enums.
+//!
+//! The wire format doesn't carry field names, so fields are renamed to carry
+//! `;`-separated attributes after the name, e.g. `"cluster_id;tag=0"`:
+//!
+//! - `tag=N`: a known tagged field, see `serde_kafka::TaggedFields`.
+//! - `versions=V`: the versions the field is present in, see
+//!   `serde_kafka::Version`.
+//! - `nullable=V`: the versions an `Option` field may be null in, when not all
+//!   of those it is present in, see `serde_kafka::nullable`.
+//!
+//! Enums are discriminated by a field of their variants that is already on
+//! the wire, e.g. the `value_type` of metadata records. The enum is renamed
+//! with the byte offset of that `INT8` field, `"RecordValue;discriminant=1"`,
+//! and each variant with its value, `"TopicRecordValue;value=2"`. Variants
+//! are serialized as their fields alone.
+
+use super::{
+    error::{Error, Result},
+    version::{Version, Versions},
+};
+
+pub(crate) struct FieldAttributes {
+    pub tag: Option<u32>,
+    pub versions: Option<Versions>,
+    pub nullable: Option<Versions>,
+    pub discriminant: Option<usize>,
+    pub value: Option<i64>,
+}
+
+impl FieldAttributes {
+    pub(crate) fn parse(key: &str) -> Result<Self> {
+        let mut attributes = FieldAttributes {
+            tag: None,
+            versions: None,
+            nullable: None,
+            discriminant: None,
+            value: None,
+        };
+
+        for attribute in key.split(';').skip(1) {
+            match attribute.split_once('=') {
+                Some(("tag", tag)) => {
+                    attributes.tag = Some(tag.parse().map_err(|_| {
+                        Error::message(format!("Invalid tag `{tag}` on field `{key}`"))
+                    })?)
+                }
+                Some(("versions", versions)) => {
+                    attributes.versions = Some(Versions::parse(versions)?)
+                }
+                Some(("nullable", versions)) => {
+                    attributes.nullable = Some(Versions::parse(versions)?)
+                }
+                Some(("discriminant", offset)) => {
+                    attributes.discriminant = Some(offset.parse().map_err(|_| {
+                        Error::message(format!("Invalid discriminant `{offset}` on `{key}`"))
+                    })?)
+                }
+                Some(("value", value)) => {
+                    attributes.value = Some(value.parse().map_err(|_| {
+                        Error::message(format!("Invalid value `{value}` on variant `{key}`"))
+                    })?)
+                }
+                _ => {
+                    return Err(Error::message(format!(
+                        "Unknown attribute `{attribute}` on field `{key}`"
+                    )))
+                }
+            }
+        }
+
+        Ok(attributes)
+    }
+
+    /// Whether the field is on the wire at `version`. Tagged fields only exist
+    /// in flexible versions.
+    pub(crate) fn is_present(&self, version: Version) -> bool {
+        (self.tag.is_none() || version.flexible)
+            && self
+                .versions
+                .is_none_or(|versions| versions.contains(version))
+    }
+
+    /// Whether the field may be null at `version`.
+    pub(crate) fn is_nullable(&self, version: Version) -> bool {
+        self.nullable
+            .is_none_or(|versions| versions.contains(version))
+    }
+}
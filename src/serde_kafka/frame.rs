@@ -0,0 +1,33 @@
+//! Sections of a message framed by their size or checksum, like the body of a
+//! record batch.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+use super::shared_bytes;
+
+pub const SIZE_PREFIXED_NAME: &str = "SizePrefixed";
+pub const VARINT_SIZE_PREFIXED_NAME: &str = "VarIntSizePrefixed";
+pub const CRC32C_NAME: &str = "Crc32c";
+pub const REMAINING_NAME: &str = "Remaining";
+
+/// A value preceded by its `INT32` size in bytes. Decoding fails unless the
+/// value takes exactly that size.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct SizePrefixed<T>(pub T);
+
+/// A value preceded by its `VARINT` size in bytes, like the records of a
+/// record batch. Decoding fails unless the value takes exactly that size.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct VarIntSizePrefixed<T>(pub T);
+
+/// A value preceded by the `UINT32` CRC-32C of its bytes. The value must
+/// extend to the end of the enclosing `SizePrefixed` or message, which is
+/// checksummed before it is decoded.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Crc32c<T>(pub T);
+
+/// Bytes without a length, extending to the end of the enclosing
+/// `SizePrefixed` or message, like the compressed records of a record batch.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Remaining(#[serde(with = "shared_bytes")] pub Bytes);
@@ -0,0 +1,62 @@
+//! Arrays with an `INT32` length in every version, used as
+//! `#[serde(with = "int32_array")]`, like the records of a record batch, which
+//! aren't compact even inside flexible messages.
+//!
+//! Like `serde_kafka::nullable`, the array is wrapped in a newtype named after
+//! its wire type.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+pub const INT32_ARRAY_NAME: &str = "Int32Array";
+
+pub fn serialize<T, S>(value: &[T], serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Serialize,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(INT32_ARRAY_NAME, value)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct Int32ArrayVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for Int32ArrayVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = Vec<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("an INT32 array")
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Vec::deserialize(deserializer)
+        }
+
+        fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut elements = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1024));
+            while let Some(element) = seq.next_element()? {
+                elements.push(element);
+            }
+            Ok(elements)
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(INT32_ARRAY_NAME, Int32ArrayVisitor(PhantomData))
+}
@@ -0,0 +1,98 @@
+//! Nullable protocol types, used as `#[serde(with = "nullable")]` on `Option`
+//! fields.
+//!
+//! The null marker depends on the wire type of the value (`-1` length for
+//! `NULLABLE_STRING`, `0` length for compact types, `-1` marker for structs,
+//! which are preceded by `1` when present), which serde does not pass
+//! to `serialize_none`/`deserialize_option`. This module wraps the `Option` in a
+//! newtype named after the wire type so the format knows which marker to use.
+//!
+//! Fields only nullable in some versions carry a `nullable` attribute, see
+//! `serde_kafka::field_attributes`: in other versions null is an error and
+//! structs have no marker.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{
+    de::{self, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::CompactString;
+
+pub const NULLABLE_STRING_NAME: &str = "NullableString";
+pub const NULLABLE_COMPACT_STRING_NAME: &str = "NullableCompactString";
+pub const NULLABLE_ARRAY_NAME: &str = "NullableArray";
+pub const NULLABLE_STRUCT_NAME: &str = "NullableStruct";
+
+/// Types that have a nullable variant on the wire. Structs implement it with
+/// `NULLABLE_STRUCT_NAME`.
+pub trait Nullable {
+    const NULLABLE_NAME: &'static str;
+}
+
+impl Nullable for String {
+    const NULLABLE_NAME: &'static str = NULLABLE_STRING_NAME;
+}
+
+impl Nullable for CompactString {
+    const NULLABLE_NAME: &'static str = NULLABLE_COMPACT_STRING_NAME;
+}
+
+impl<T> Nullable for Vec<T> {
+    const NULLABLE_NAME: &'static str = NULLABLE_ARRAY_NAME;
+}
+
+impl Nullable for &[u8] {
+    const NULLABLE_NAME: &'static str = NULLABLE_ARRAY_NAME;
+}
+
+pub fn serialize<T, S>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Nullable + Serialize,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(T::NULLABLE_NAME, value)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Nullable + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct NullableVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for NullableVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = Option<T>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a nullable value")
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            Option::deserialize(deserializer)
+        }
+
+        fn visit_none<E>(self) -> Result<Self::Value, E>
+        where
+            E: de::Error,
+        {
+            Ok(None)
+        }
+
+        fn visit_some<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            T::deserialize(deserializer).map(Some)
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(T::NULLABLE_NAME, NullableVisitor(PhantomData))
+}
@@ -1,4 +1,4 @@
-use std::num::TryFromIntError;
+use std::{collections::BTreeMap, mem, num::TryFromIntError};
 
 use bytes::{BufMut, BytesMut};
 use serde::{ser, Serialize};
@@ -6,38 +6,224 @@ use tokio::io::AsyncWriteExt;
 
 use crate::serde_kafka::COMPACT_STRING_NAME;
 
-use super::error::{Error, Result};
+use super::{
+    error::{Error, Result},
+    field_attributes::FieldAttributes,
+    frame::{CRC32C_NAME, REMAINING_NAME, SIZE_PREFIXED_NAME, VARINT_SIZE_PREFIXED_NAME},
+    int32_array::INT32_ARRAY_NAME,
+    nullable::{
+        NULLABLE_ARRAY_NAME, NULLABLE_COMPACT_STRING_NAME, NULLABLE_STRING_NAME,
+        NULLABLE_STRUCT_NAME,
+    },
+    tagged_fields::TAGGED_FIELDS_NAME,
+    varint::{
+        put_unsigned_varint, put_varint, put_varlong, UNSIGNED_VARINT_NAME, VARINT_NAME,
+        VARLONG_NAME,
+    },
+    varint_length::VARINT_LENGTH_NAME,
+    version::Version,
+};
 
 pub struct Serializer {
     output: BytesMut,
+    version: Version,
+    // Wire type of the `Option` being serialized, see `serde_kafka::nullable`.
+    nullable: Option<&'static str>,
+    // Whether that `Option` may be null at this version, false outside the
+    // `nullable` attribute of its field.
+    null_allowed: bool,
+    // Wire type of the length of the array or bytes being serialized, when
+    // overridden by `serde_kafka::int32_array`, `serde_kafka::varint_length` or
+    // `Remaining`.
+    length: Option<&'static str>,
+    // Tagged field section of each struct being serialized, written when the
+    // struct ends. `None` for structs without a `TaggedFields` field.
+    tagged_fields: Vec<Option<BTreeMap<u32, BytesMut>>>,
 }
 
 pub fn to_bytes_mut<T>(value: &T) -> Result<BytesMut>
+where
+    T: Serialize,
+{
+    to_bytes_mut_with_version(value, Version::LATEST)
+}
+
+pub fn to_bytes_mut_with_version<T>(value: &T, version: Version) -> Result<BytesMut>
 where
     T: Serialize,
 {
     let mut serializer = Serializer {
         output: BytesMut::new(),
+        version,
+        nullable: None,
+        null_allowed: true,
+        length: None,
+        tagged_fields: Vec::new(),
     };
     value.serialize(&mut serializer)?;
     Ok(serializer.output)
 }
 
-pub async fn to_async_writer_with_message_size<W, S>(writer: &mut W, value: &S) -> Result<()>
+/// Serializes `value` preceded by its `INT32` size, as messages are sent.
+pub fn to_bytes_mut_with_message_size<T>(value: &T, version: Version) -> Result<BytesMut>
+where
+    T: Serialize,
+{
+    let message = to_bytes_mut_with_version(value, version)?;
+
+    with_message_size(&message)
+}
+
+/// Prefixes an already serialized message with its `INT32` size.
+pub fn with_message_size(message: &[u8]) -> Result<BytesMut> {
+    let size: i32 = message
+        .len()
+        .try_into()
+        .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+    let mut result = BytesMut::with_capacity(4 + message.len());
+    result.put_i32(size);
+    result.extend_from_slice(message);
+    Ok(result)
+}
+
+pub async fn to_async_writer_with_message_size<W, S>(
+    writer: &mut W,
+    value: &S,
+    version: Version,
+) -> Result<()>
 where
     W: AsyncWriteExt + Unpin,
     S: Serialize,
 {
-    let response_bytes = to_bytes_mut(value)?;
-    let mut result = BytesMut::new();
-    result.extend_from_slice(&(response_bytes.len() as i32).to_be_bytes());
-    result.extend_from_slice(&response_bytes);
+    let mut message = to_bytes_mut_with_message_size(value, version)?;
 
-    writer.write_all_buf(&mut result).await.unwrap();
-    writer.flush().await.unwrap();
+    writer.write_all_buf(&mut message).await?;
+    writer.flush().await?;
     Ok(())
 }
 
+impl Serializer {
+    /// Writes the length prefix of a string, `INT16` in every version.
+    fn put_string_length(&mut self, length: usize) -> Result<()> {
+        if self.length.take() == Some(VARINT_LENGTH_NAME) {
+            return self.put_varint_length(length);
+        }
+
+        let length: i16 = length
+            .try_into()
+            .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+        self.output.put_i16(length);
+        Ok(())
+    }
+
+    /// Writes the length prefix of an array, `INT32` in non-flexible versions.
+    fn put_array_length(&mut self, length: usize) -> Result<()> {
+        match self.length.take() {
+            Some(INT32_ARRAY_NAME) => {}
+            Some(REMAINING_NAME) => return Ok(()),
+            Some(VARINT_LENGTH_NAME) => return self.put_varint_length(length),
+            _ if self.version.flexible => return self.put_compact_length(length),
+            _ => {}
+        }
+
+        let length: i32 = length
+            .try_into()
+            .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+        self.output.put_i32(length);
+        Ok(())
+    }
+
+    /// Writes the `VARINT` length prefix used inside records.
+    fn put_varint_length(&mut self, length: usize) -> Result<()> {
+        let length: i32 = length
+            .try_into()
+            .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+        put_varint(&mut self.output, length);
+        Ok(())
+    }
+
+    /// Writes the `UNSIGNED_VARINT` length prefix used by compact strings,
+    /// arrays and bytes, where 0 is reserved for null.
+    fn put_compact_length(&mut self, length: usize) -> Result<()> {
+        let length: u32 = length
+            .checked_add(1)
+            .and_then(|length| length.try_into().ok())
+            .ok_or(Error::message(format!(
+                "Compact length too large: {length}"
+            )))?;
+
+        put_unsigned_varint(&mut self.output, length);
+        Ok(())
+    }
+
+    fn tagged_fields(&mut self) -> Result<&mut BTreeMap<u32, BytesMut>> {
+        self.tagged_fields
+            .last_mut()
+            .map(|fields| fields.get_or_insert_with(BTreeMap::new))
+            .ok_or(Error::message("Tagged fields must be inside a struct"))
+    }
+
+    /// Serializes `value` into a separate buffer.
+    fn serialize_aside<T>(&mut self, value: &T) -> Result<BytesMut>
+    where
+        T: ?Sized + Serialize,
+    {
+        let output = mem::take(&mut self.output);
+        let result = value.serialize(&mut *self);
+        let data = mem::replace(&mut self.output, output);
+        result?;
+
+        Ok(data)
+    }
+
+    /// Serializes `value` aside, to be written in the tagged field section of
+    /// the enclosing struct.
+    fn serialize_tagged_field<T>(&mut self, tag: u32, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let data = self.serialize_aside(value)?;
+
+        self.tagged_fields()?.insert(tag, data);
+        Ok(())
+    }
+
+    fn serialize_struct_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
+    where
+        T: ?Sized + Serialize,
+    {
+        let attributes = FieldAttributes::parse(key)?;
+        if !attributes.is_present(self.version) {
+            return Ok(());
+        }
+        self.null_allowed = attributes.is_nullable(self.version);
+
+        match attributes.tag {
+            Some(tag) => self.serialize_tagged_field(tag, value),
+            None => value.serialize(self),
+        }
+    }
+
+    fn end_struct(&mut self) -> Result<()> {
+        let Some(fields) = self.tagged_fields.pop().flatten() else {
+            return Ok(());
+        };
+
+        put_unsigned_varint(&mut self.output, fields.len() as u32);
+        for (tag, data) in fields {
+            put_unsigned_varint(&mut self.output, tag);
+            put_unsigned_varint(&mut self.output, data.len() as u32);
+            self.output.put_slice(&data);
+        }
+
+        Ok(())
+    }
+}
+
 impl ser::Serializer for &mut Serializer {
     type Ok = ();
     type Error = Error;
@@ -80,8 +266,9 @@ impl ser::Serializer for &mut Serializer {
         Ok(())
     }
 
-    fn serialize_u16(self, _v: u16) -> Result<()> {
-        unimplemented!()
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.output.put_u16(v);
+        Ok(())
     }
 
     fn serialize_u32(self, v: u32) -> Result<()> {
@@ -97,8 +284,9 @@ impl ser::Serializer for &mut Serializer {
         unimplemented!()
     }
 
-    fn serialize_f64(self, _v: f64) -> Result<()> {
-        unimplemented!()
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.output.put_f64(v);
+        Ok(())
     }
 
     fn serialize_char(self, _v: char) -> Result<()> {
@@ -106,38 +294,65 @@ impl ser::Serializer for &mut Serializer {
     }
 
     fn serialize_str(self, v: &str) -> Result<()> {
-        let length: i16 = v
-            .len()
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
-
-        self.output.put_i16(length);
+        self.put_string_length(v.len())?;
         self.output.put_slice(v.as_bytes());
 
         Ok(())
     }
 
+    // `BYTES`, or `COMPACT_BYTES` in flexible versions.
     fn serialize_bytes(self, v: &[u8]) -> Result<()> {
-        let length: i32 = v
-            .len()
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
-
-        self.output.put_i32(length);
+        self.put_array_length(v.len())?;
         self.output.put_slice(v);
 
         Ok(())
     }
 
     fn serialize_none(self) -> Result<()> {
-        unimplemented!()
+        let nullable = self.nullable.take();
+        let null_allowed = mem::replace(&mut self.null_allowed, true);
+
+        if self.length == Some(VARINT_LENGTH_NAME) {
+            self.length = None;
+            put_varint(&mut self.output, -1);
+            return Ok(());
+        }
+
+        match nullable {
+            Some(_) if !null_allowed => {
+                return Err(Error::message(format!(
+                    "Null value in version {}, where the field isn't nullable",
+                    self.version.version
+                )))
+            }
+            Some(NULLABLE_COMPACT_STRING_NAME | NULLABLE_ARRAY_NAME) if self.version.flexible => {
+                put_unsigned_varint(&mut self.output, 0)
+            }
+            Some(NULLABLE_STRING_NAME | NULLABLE_COMPACT_STRING_NAME) => self.output.put_i16(-1),
+            Some(NULLABLE_ARRAY_NAME) => self.output.put_i32(-1),
+            Some(NULLABLE_STRUCT_NAME) => self.output.put_i8(-1),
+            _ => {
+                return Err(Error::message(
+                    "Option fields must be serialized with serde_kafka::nullable",
+                ))
+            }
+        }
+
+        Ok(())
     }
 
-    fn serialize_some<T>(self, _value: &T) -> Result<()>
+    fn serialize_some<T>(self, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        let nullable = self.nullable.take();
+        let null_allowed = mem::replace(&mut self.null_allowed, true);
+
+        // Structs only have a marker in versions they are nullable in.
+        if nullable == Some(NULLABLE_STRUCT_NAME) && null_allowed {
+            self.output.put_i8(1);
+        }
+        value.serialize(self)
     }
 
     fn serialize_unit(self) -> Result<()> {
@@ -161,43 +376,106 @@ impl ser::Serializer for &mut Serializer {
     where
         T: ?Sized + Serialize,
     {
-        if name == COMPACT_STRING_NAME {
-            let s = value.serialize(StringCapture)?;
-
-            let length: i8 = s
-                .len()
-                .try_into()
-                .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
-
-            self.output.put_i8(length + 1);
-            self.output.put_slice(s.as_bytes());
-
-            Ok(())
-        } else {
-            value.serialize(self)
+        match name {
+            COMPACT_STRING_NAME => {
+                let s = value.serialize(StringCapture)?;
+
+                if self.version.flexible {
+                    self.put_compact_length(s.len())?;
+                } else {
+                    self.put_string_length(s.len())?;
+                }
+                self.output.put_slice(s.as_bytes());
+
+                Ok(())
+            }
+            UNSIGNED_VARINT_NAME => {
+                let v = value
+                    .serialize(IntegerCapture)?
+                    .try_into()
+                    .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+                put_unsigned_varint(&mut self.output, v);
+                Ok(())
+            }
+            VARINT_NAME => {
+                let v = value
+                    .serialize(IntegerCapture)?
+                    .try_into()
+                    .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+                put_varint(&mut self.output, v);
+                Ok(())
+            }
+            VARLONG_NAME => {
+                put_varlong(&mut self.output, value.serialize(IntegerCapture)?);
+                Ok(())
+            }
+            NULLABLE_STRING_NAME
+            | NULLABLE_COMPACT_STRING_NAME
+            | NULLABLE_ARRAY_NAME
+            | NULLABLE_STRUCT_NAME => {
+                self.nullable = Some(name);
+                value.serialize(self)
+            }
+            INT32_ARRAY_NAME | VARINT_LENGTH_NAME | REMAINING_NAME => {
+                self.length = Some(name);
+                value.serialize(self)
+            }
+            SIZE_PREFIXED_NAME => {
+                let data = self.serialize_aside(value)?;
+                let size: i32 = data
+                    .len()
+                    .try_into()
+                    .map_err(|e: TryFromIntError| Error::message(e.to_string()))?;
+
+                self.output.put_i32(size);
+                self.output.put_slice(&data);
+                Ok(())
+            }
+            VARINT_SIZE_PREFIXED_NAME => {
+                let data = self.serialize_aside(value)?;
+
+                self.put_varint_length(data.len())?;
+                self.output.put_slice(&data);
+                Ok(())
+            }
+            CRC32C_NAME => {
+                let data = self.serialize_aside(value)?;
+
+                self.output.put_u32(crc32c::crc32c(&data));
+                self.output.put_slice(&data);
+                Ok(())
+            }
+            _ => value.serialize(self),
         }
     }
 
     fn serialize_newtype_variant<T>(
         self,
-        _name: &'static str,
-        _variant_index: u32,
+        name: &'static str,
+        variant_index: u32,
         _variant: &'static str,
-        _value: &T,
+        value: &T,
     ) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        unimplemented!()
+        if name == TAGGED_FIELDS_NAME {
+            // An unknown tagged field, the variant index is its tag.
+            if !self.version.flexible {
+                return Ok(());
+            }
+            self.serialize_tagged_field(variant_index, value)
+        } else {
+            unimplemented!()
+        }
     }
 
     fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq> {
-        let length: i8 = len
-            .ok_or(Error::Message("Size must be known".into()))?
-            .try_into()
-            .map_err(|e: TryFromIntError| Error::Message(e.to_string()))?;
+        let length = len.ok_or(Error::message("Size must be known"))?;
 
-        self.output.put_i8(length + 1);
+        self.put_array_length(length)?;
         Ok(self)
     }
 
@@ -207,9 +485,12 @@ impl ser::Serializer for &mut Serializer {
 
     fn serialize_tuple_struct(
         self,
-        _name: &'static str,
+        name: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeTupleStruct> {
+        if name == TAGGED_FIELDS_NAME && self.version.flexible {
+            self.tagged_fields()?;
+        }
         Ok(self)
     }
 
@@ -228,6 +509,7 @@ impl ser::Serializer for &mut Serializer {
     }
 
     fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        self.tagged_fields.push(None);
         Ok(self)
     }
 
@@ -235,10 +517,12 @@ impl ser::Serializer for &mut Serializer {
         self,
         _name: &'static str,
         _variant_index: u32,
-        variant: &'static str,
+        _variant: &'static str,
         _len: usize,
     ) -> Result<Self::SerializeStructVariant> {
-        variant.serialize(&mut *self)?;
+        // The variant is identified by one of its fields, see
+        // `field_attributes`.
+        self.tagged_fields.push(None);
         Ok(self)
     }
 }
@@ -334,15 +618,15 @@ impl ser::SerializeStruct for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.serialize_struct_field(key, value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_struct()
     }
 }
 
@@ -350,28 +634,34 @@ impl ser::SerializeStructVariant for &mut Serializer {
     type Ok = ();
     type Error = Error;
 
-    fn serialize_field<T>(&mut self, _key: &'static str, value: &T) -> Result<()>
+    fn serialize_field<T>(&mut self, key: &'static str, value: &T) -> Result<()>
     where
         T: ?Sized + Serialize,
     {
-        value.serialize(&mut **self)
+        self.serialize_struct_field(key, value)
     }
 
     fn end(self) -> Result<()> {
-        Ok(())
+        self.end_struct()
     }
 }
 struct StringCapture;
 
 macro_rules! reject_other_types {
     ($self:ident, $ty:expr) => {
-        Err(Error::Message(format!(
-            "CompactString can only contain a string, not {}",
-            $ty
-        )))
+        Err($self.reject($ty))
     };
 }
 
+impl StringCapture {
+    fn reject(&self, ty: &str) -> Error {
+        Error::message(format!(
+            "CompactString can only contain a string, not {}",
+            ty
+        ))
+    }
+}
+
 impl ser::Serializer for StringCapture {
     type Ok = String;
 
@@ -534,11 +824,185 @@ impl ser::Serializer for StringCapture {
         reject_other_types!(self, "struct_variant")
     }
 }
+
+struct IntegerCapture;
+
+impl IntegerCapture {
+    fn reject(&self, ty: &str) -> Error {
+        Error::message(format!("Varint can only contain an integer, not {}", ty))
+    }
+}
+
+impl ser::Serializer for IntegerCapture {
+    type Ok = i64;
+
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<i64, Error>;
+
+    type SerializeTuple = ser::Impossible<i64, Error>;
+
+    type SerializeTupleStruct = ser::Impossible<i64, Error>;
+
+    type SerializeTupleVariant = ser::Impossible<i64, Error>;
+
+    type SerializeMap = ser::Impossible<i64, Error>;
+
+    type SerializeStruct = ser::Impossible<i64, Error>;
+
+    type SerializeStructVariant = ser::Impossible<i64, Error>;
+
+    fn serialize_i8(self, v: i8) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Self::Ok> {
+        Ok(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Self::Ok> {
+        Ok(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Self::Ok> {
+        v.try_into()
+            .map_err(|e: TryFromIntError| Error::message(e.to_string()))
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<Self::Ok> {
+        reject_other_types!(self, "bool")
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<Self::Ok> {
+        reject_other_types!(self, "f32")
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<Self::Ok> {
+        reject_other_types!(self, "f64")
+    }
+
+    fn serialize_char(self, _v: char) -> Result<Self::Ok> {
+        reject_other_types!(self, "char")
+    }
+
+    fn serialize_str(self, _v: &str) -> Result<Self::Ok> {
+        reject_other_types!(self, "str")
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Self::Ok> {
+        reject_other_types!(self, "bytes")
+    }
+
+    fn serialize_none(self) -> Result<Self::Ok> {
+        reject_other_types!(self, "none")
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _v: &T) -> Result<Self::Ok> {
+        reject_other_types!(self, "some")
+    }
+
+    fn serialize_unit(self) -> Result<Self::Ok> {
+        reject_other_types!(self, "unit")
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Self::Ok> {
+        reject_other_types!(self, "unit_struct")
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _n: &'static str,
+        _vi: u32,
+        _var: &'static str,
+    ) -> Result<Self::Ok> {
+        reject_other_types!(self, "unit_variant")
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _n: &'static str,
+        v: &T,
+    ) -> Result<Self::Ok> {
+        v.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _n: &'static str,
+        _vi: u32,
+        _var: &'static str,
+        _v: &T,
+    ) -> Result<Self::Ok> {
+        reject_other_types!(self, "newtype_variant")
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        reject_other_types!(self, "seq")
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        reject_other_types!(self, "tuple")
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _n: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        reject_other_types!(self, "tuple_struct")
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _n: &'static str,
+        _vi: u32,
+        _var: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        reject_other_types!(self, "tuple_variant")
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        reject_other_types!(self, "map")
+    }
+
+    fn serialize_struct(self, _n: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        reject_other_types!(self, "struct")
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _n: &'static str,
+        _vi: u32,
+        _var: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        reject_other_types!(self, "struct_variant")
+    }
+}
 ////////////////////////////////////////////////////////////////////////////////
 
 #[cfg(test)]
 mod tests {
-    use crate::serde_kafka::CompactString;
+    use crate::serde_kafka::{
+        is_default, nullable, CompactString, TaggedFields, UnsignedVarInt, VarInt, VarLong,
+    };
 
     use super::*;
 
@@ -600,25 +1064,25 @@ mod tests {
         // string: "kafka-cli" (len 9)
         expected.put_i16(9);
         expected.put_slice(b"kafka-cli");
-        // compact_string: "hello" (len 5, so writes 6 as i8)
+        // compact_string: "hello" (len 5, so writes 6 as uvarint)
         expected.put_i8(6);
         expected.put_slice(b"hello");
-        // ca_i8: vec![1, 2, 3] (len 3 as i8)
+        // ca_i8: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         expected.put_i8(i8::MAX);
         expected.put_i8(i8::MAX);
         expected.put_i8(i8::MAX);
-        // ca_i16: vec![1, 2, 3] (len 3 as i8)
+        // ca_i16: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         expected.put_i16(i16::MAX);
         expected.put_i16(i16::MAX);
         expected.put_i16(i16::MAX);
-        // ca_i32: vec![1, 2, 3] (len 3 as i8)
+        // ca_i32: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         expected.put_i32(i32::MAX);
         expected.put_i32(i32::MAX);
         expected.put_i32(i32::MAX);
-        // ca_string: vec![1, 2, 3] (len 3 as i8)
+        // ca_string: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         expected.put_i16(9);
         expected.put_slice(b"kafka-cli");
@@ -626,7 +1090,7 @@ mod tests {
         expected.put_slice(b"kafka-cli");
         expected.put_i16(9);
         expected.put_slice(b"kafka-cli");
-        // ca_compact_string: vec![1, 2, 3] (len 3 as i8)
+        // ca_compact_string: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         expected.put_i8(10);
         expected.put_slice(b"kafka-cli");
@@ -634,7 +1098,7 @@ mod tests {
         expected.put_slice(b"kafka-cli");
         expected.put_i8(10);
         expected.put_slice(b"kafka-cli");
-        // ca_struct: vec![1, 2, 3] (len 3 as i8)
+        // ca_struct: vec![1, 2, 3] (len 3, so writes 4 as uvarint)
         expected.put_i8(4);
         // i8: 127
         expected.put_i8(i8::MAX);
@@ -659,4 +1123,206 @@ mod tests {
 
         assert_eq!(result, expected);
     }
+
+    #[derive(Serialize)]
+    struct Varints {
+        unsigned_varint: UnsignedVarInt,
+        varint: VarInt,
+        varlong: VarLong,
+    }
+
+    #[test]
+    fn test_varint_serialization() {
+        let value = Varints {
+            unsigned_varint: UnsignedVarInt(300),
+            varint: VarInt(-65),
+            varlong: VarLong(1_726_045_943_832),
+        };
+
+        let expected: &[u8] = &[
+            0xac, 0x02, // unsigned_varint: 300
+            0x81, 0x01, // varint: -65
+            0xb0, 0xe0, 0xd7, 0x85, 0xbc, 0x64, // varlong: 1726045943832
+        ];
+
+        assert_eq!(to_bytes_mut(&value).unwrap(), expected);
+    }
+
+    #[derive(Serialize)]
+    struct LongCompact {
+        compact_string: CompactString,
+        ca_i8: Vec<i8>,
+    }
+
+    #[test]
+    fn test_long_compact_lengths() {
+        let value = LongCompact {
+            compact_string: "k".repeat(200).into(),
+            ca_i8: vec![i8::MAX; 300],
+        };
+
+        let mut expected = BytesMut::new();
+        // compact_string: len 200, so writes 201 as uvarint
+        expected.put_slice(&[0xc9, 0x01]);
+        expected.put_slice(&[b'k'; 200]);
+        // ca_i8: len 300, so writes 301 as uvarint
+        expected.put_slice(&[0xad, 0x02]);
+        expected.put_slice(&[0x7f; 300]);
+
+        assert_eq!(to_bytes_mut(&value).unwrap(), expected);
+    }
+
+    #[derive(Serialize)]
+    struct Nullables {
+        #[serde(with = "nullable")]
+        string: Option<String>,
+        #[serde(with = "nullable")]
+        compact_string: Option<CompactString>,
+        #[serde(with = "nullable")]
+        ca_i8: Option<Vec<i8>>,
+    }
+
+    #[test]
+    fn test_nullable_serialization() {
+        let null = Nullables {
+            string: None,
+            compact_string: None,
+            ca_i8: None,
+        };
+        let expected: &[u8] = &[
+            0xff, 0xff, // string: null (len -1 as i16)
+            0x00, // compact_string: null (len 0 as uvarint)
+            0x00, // ca_i8: null (len 0 as uvarint)
+        ];
+        assert_eq!(to_bytes_mut(&null).unwrap(), expected);
+
+        let present = Nullables {
+            string: Some("a".into()),
+            compact_string: Some("b".into()),
+            ca_i8: Some(vec![]),
+        };
+        let expected: &[u8] = &[
+            0x00, 0x01, b'a', // string: "a"
+            0x02, b'b', // compact_string: "b"
+            0x01, // ca_i8: empty
+        ];
+        assert_eq!(to_bytes_mut(&present).unwrap(), expected);
+    }
+
+    #[derive(Serialize)]
+    struct NullableVersions {
+        #[serde(rename = "string;nullable=1+", with = "nullable")]
+        string: Option<String>,
+    }
+
+    #[test]
+    fn test_nullable_versions_serialization() {
+        let v0 = Version {
+            version: 0,
+            flexible: false,
+        };
+        let v1 = Version { version: 1, ..v0 };
+
+        let null = NullableVersions { string: None };
+        assert!(to_bytes_mut_with_version(&null, v0).is_err());
+        assert_eq!(
+            to_bytes_mut_with_version(&null, v1).unwrap(),
+            &[0xff, 0xff][..] // string: null (len -1 as i16)
+        );
+
+        let present = NullableVersions {
+            string: Some("a".into()),
+        };
+        assert_eq!(
+            to_bytes_mut_with_version(&present, v0).unwrap(),
+            &[0x00, 0x01, b'a'][..] // string: "a"
+        );
+    }
+
+    #[derive(Serialize)]
+    struct Tagged {
+        i8: i8,
+        tagged_fields: TaggedFields,
+        #[serde(rename = "known;tag=1", skip_serializing_if = "is_default")]
+        known: i16,
+    }
+
+    #[test]
+    fn test_tagged_fields_serialization() {
+        let value = Tagged {
+            i8: i8::MAX,
+            tagged_fields: TaggedFields([(0, vec![0xaa]), (5, vec![0xbb, 0xcc])].into()),
+            known: 0x0102,
+        };
+        let expected: &[u8] = &[
+            0x7f, // i8: 127
+            0x03, // tagged fields: 3
+            0x00, 0x01, 0xaa, // tag 0, size 1
+            0x01, 0x02, 0x01, 0x02, // tag 1 (known), size 2
+            0x05, 0x02, 0xbb, 0xcc, // tag 5, size 2
+        ];
+        assert_eq!(to_bytes_mut(&value).unwrap(), expected);
+
+        let value = Tagged {
+            i8: i8::MAX,
+            tagged_fields: TaggedFields::default(),
+            known: 0,
+        };
+        let expected: &[u8] = &[
+            0x7f, // i8: 127
+            0x00, // tagged fields: 0, known is left out when default
+        ];
+        assert_eq!(to_bytes_mut(&value).unwrap(), expected);
+    }
+
+    #[derive(Serialize)]
+    struct Versioned {
+        i8: i8,
+        #[serde(rename = "i16;versions=1+")]
+        i16: i16,
+        compact_string: CompactString,
+        array: Vec<i8>,
+        #[serde(with = "nullable")]
+        nullable_array: Option<Vec<i8>>,
+        tagged_fields: TaggedFields,
+    }
+
+    #[test]
+    fn test_versioned_serialization() {
+        let value = Versioned {
+            i8: i8::MAX,
+            i16: i16::MAX,
+            compact_string: "hi".into(),
+            array: vec![i8::MAX],
+            nullable_array: None,
+            tagged_fields: TaggedFields([(0, vec![0xaa])].into()),
+        };
+
+        let v0 = Version {
+            version: 0,
+            flexible: false,
+        };
+        let expected: &[u8] = &[
+            0x7f, // i8: 127
+            // i16 is missing from v0, and so are tagged fields
+            0x00, 0x02, 0x68, 0x69, // compact_string as STRING: "hi"
+            0x00, 0x00, 0x00, 0x01, 0x7f, // array: INT32 len 1
+            0xff, 0xff, 0xff, 0xff, // nullable_array: INT32 -1
+        ];
+        assert_eq!(to_bytes_mut_with_version(&value, v0).unwrap(), expected);
+
+        let v1 = Version {
+            version: 1,
+            flexible: true,
+        };
+        let expected: &[u8] = &[
+            0x7f, // i8: 127
+            0x7f, 0xff, // i16: 32767
+            0x03, 0x68, 0x69, // compact_string: "hi"
+            0x02, 0x7f, // array: compact len 1
+            0x00, // nullable_array: null
+            0x01, 0x00, 0x01, 0xaa, // tagged fields: tag 0, size 1
+        ];
+        assert_eq!(to_bytes_mut_with_version(&value, v1).unwrap(), expected);
+    }
 }
@@ -0,0 +1,114 @@
+//! `BYTES` fields held as `bytes::Bytes`, used as
+//! `#[serde(with = "shared_bytes")]`, or `#[serde(with = "shared_bytes::nullable")]`
+//! on `Option<Bytes>`.
+//!
+//! When the message is deserialized with `from_shared_bytes_with_version`, the
+//! fields are slices of the message buffer instead of copies, so large
+//! payloads like record batches are never copied.
+
+use std::cell::RefCell;
+
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serializer};
+
+thread_local! {
+    // The buffer being deserialized by `from_shared_bytes_with_version`.
+    static SHARED: RefCell<Option<Bytes>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` with `bytes` as the buffer `shared_bytes` fields are sliced from.
+pub(crate) fn with_shared<T>(bytes: &Bytes, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(SHARED.with(|shared| shared.replace(Some(bytes.clone()))));
+    f()
+}
+
+/// Restores the buffer of an enclosing `with_shared` when dropped, so it is
+/// restored even when `f` panics.
+struct Restore(Option<Bytes>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        SHARED.with(|shared| *shared.borrow_mut() = previous);
+    }
+}
+
+/// `slice` as a `Bytes`, sharing the buffer being deserialized when `slice`
+/// is part of it.
+pub(crate) fn share(slice: &[u8]) -> Bytes {
+    SHARED.with(|shared| match &*shared.borrow() {
+        Some(bytes) if contains(bytes, slice) => bytes.slice_ref(slice),
+        _ => Bytes::copy_from_slice(slice),
+    })
+}
+
+fn contains(bytes: &Bytes, slice: &[u8]) -> bool {
+    let range = bytes.as_ptr_range();
+    let slice_range = slice.as_ptr_range();
+    range.start <= slice_range.start && slice_range.end <= range.end
+}
+
+pub fn serialize<S>(value: &Bytes, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_bytes(value)
+}
+
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Bytes, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    <&'de [u8]>::deserialize(deserializer).map(share)
+}
+
+pub mod nullable {
+    use bytes::Bytes;
+    use serde::{Deserializer, Serializer};
+
+    use super::share;
+    use crate::serde_kafka::nullable;
+
+    pub fn serialize<S>(value: &Option<Bytes>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        nullable::serialize(&value.as_deref(), serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Bytes>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        nullable::deserialize::<&'de [u8], D>(deserializer).map(|value| value.map(share))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod test {
+    use std::panic::{self, AssertUnwindSafe};
+
+    use super::*;
+
+    #[test]
+    fn test_with_shared_restores_on_panic() {
+        let outer = Bytes::from_static(&[0x01, 0x02]);
+        let inner = Bytes::from_static(&[0x03]);
+
+        with_shared(&outer, || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                with_shared(&inner, || panic!("deserialization panicked"))
+            }));
+            assert!(result.is_err());
+
+            let shared = share(&outer[1..]);
+            assert_eq!(shared.as_ptr(), outer[1..].as_ptr());
+        });
+
+        // Nothing is shared outside `with_shared`, slices are copied.
+        let copied = share(&outer[1..]);
+        assert_ne!(copied.as_ptr(), outer[1..].as_ptr());
+    }
+}
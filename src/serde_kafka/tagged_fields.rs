@@ -0,0 +1,137 @@
+//! Tagged fields (KIP-482), the trailing section of every flexible-version
+//! struct:
+//!
+//! ```text
+//! UNSIGNED_VARINT count, then count * (UNSIGNED_VARINT tag, UNSIGNED_VARINT size, size bytes)
+//! ```
+//!
+//! A struct opts into the section by holding a `TaggedFields` field, which keeps
+//! the tags this broker does not know about so they round-trip untouched.
+//! Known tagged fields are declared as regular fields with a `tag=N` attribute,
+//! placed after the `TaggedFields` field:
+//!
+//! ```ignore
+//! pub tagged_fields: TaggedFields,
+//! #[serde(rename = "cluster_id;tag=0", default, skip_serializing_if = "is_default")]
+//! pub cluster_id: Option<CompactString>,
+//! ```
+//!
+//! `skip_serializing_if = "is_default"` leaves the field out of the section
+//! when it has its default value, as Kafka does.
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{
+    de::{self, MapAccess, Visitor},
+    ser::{SerializeTuple, SerializeTupleStruct},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+pub const TAGGED_FIELDS_NAME: &str = "TaggedFields";
+
+/// The unknown entries of a tagged field section, by tag.
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
+pub struct TaggedFields(pub BTreeMap<u32, Vec<u8>>);
+
+pub fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+impl Serialize for TaggedFields {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut fields = serializer.serialize_tuple_struct(TAGGED_FIELDS_NAME, self.0.len())?;
+        for (tag, data) in &self.0 {
+            fields.serialize_field(&UnknownTaggedField(*tag, data))?;
+        }
+        fields.end()
+    }
+}
+
+/// Serialized as a newtype variant whose index is the tag.
+struct UnknownTaggedField<'a>(u32, &'a [u8]);
+
+impl Serialize for UnknownTaggedField<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_newtype_variant(TAGGED_FIELDS_NAME, self.0, "", &RawBytes(self.1))
+    }
+}
+
+/// Serialized as a tuple so the bytes are written without a length prefix.
+struct RawBytes<'a>(&'a [u8]);
+
+impl Serialize for RawBytes<'_> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut tuple = serializer.serialize_tuple(self.0.len())?;
+        for byte in self.0 {
+            tuple.serialize_element(byte)?;
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for TaggedFields {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct TaggedFieldsVisitor;
+
+        impl<'de> Visitor<'de> for TaggedFieldsVisitor {
+            type Value = TaggedFields;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a map of tagged fields")
+            }
+
+            fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+            where
+                A: MapAccess<'de>,
+            {
+                let mut fields = BTreeMap::new();
+                while let Some((tag, TaggedFieldData(data))) = map.next_entry()? {
+                    fields.insert(tag, data);
+                }
+                Ok(TaggedFields(fields))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(TAGGED_FIELDS_NAME, TaggedFieldsVisitor)
+    }
+}
+
+struct TaggedFieldData(Vec<u8>);
+
+impl<'de> Deserialize<'de> for TaggedFieldData {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct DataVisitor;
+
+        impl<'de> Visitor<'de> for DataVisitor {
+            type Value = TaggedFieldData;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("tagged field bytes")
+            }
+
+            fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Ok(TaggedFieldData(v.to_vec()))
+            }
+        }
+
+        deserializer.deserialize_bytes(DataVisitor)
+    }
+}
@@ -44,3 +44,57 @@ where
 
     deserializer.deserialize_tuple(16, UuidVisitor)
 }
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct UuidBytes(#[serde(with = "self")] Uuid);
+
+/// `Vec<Uuid>` fields, used as `#[serde(with = "uuid_as_bytes::vec")]`.
+pub mod vec {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use uuid::Uuid;
+
+    use super::UuidBytes;
+
+    pub fn serialize<S>(uuids: &[Uuid], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(uuids.iter().copied().map(UuidBytes))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<Uuid>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let uuids = Vec::<UuidBytes>::deserialize(deserializer)?;
+        Ok(uuids.into_iter().map(|uuid| uuid.0).collect())
+    }
+}
+
+/// `Option<Vec<Uuid>>` fields, nullable arrays, used as
+/// `#[serde(with = "uuid_as_bytes::nullable_vec")]`.
+pub mod nullable_vec {
+    use serde::{Deserializer, Serializer};
+    use uuid::Uuid;
+
+    use super::UuidBytes;
+    use crate::serde_kafka::nullable;
+
+    pub fn serialize<S>(uuids: &Option<Vec<Uuid>>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let uuids: Option<Vec<_>> = uuids
+            .as_ref()
+            .map(|uuids| uuids.iter().copied().map(UuidBytes).collect());
+        nullable::serialize(&uuids, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Vec<Uuid>>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let uuids: Option<Vec<UuidBytes>> = nullable::deserialize(deserializer)?;
+        Ok(uuids.map(|uuids| uuids.into_iter().map(|uuid| uuid.0).collect()))
+    }
+}
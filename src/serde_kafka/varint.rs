@@ -0,0 +1,208 @@
+use std::ops::Deref;
+
+use bytes::{Buf, BufMut};
+use serde::{Deserialize, Serialize};
+
+use super::error::{Error, ErrorKind, Result};
+
+pub const UNSIGNED_VARINT_NAME: &str = "UnsignedVarInt";
+pub const VARINT_NAME: &str = "VarInt";
+pub const VARLONG_NAME: &str = "VarLong";
+
+/// Kafka `UNSIGNED_VARINT`: a little-endian base 128 integer, 7 bits per byte.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct UnsignedVarInt(pub u32);
+
+/// Kafka `VARINT`: a zigzag encoded `i32` written as an unsigned varint.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct VarInt(pub i32);
+
+/// Kafka `VARLONG`: a zigzag encoded `i64` written as an unsigned varint.
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct VarLong(pub i64);
+
+macro_rules! impl_varint_newtype {
+    ($name:ident, $inner:ty) => {
+        impl Deref for $name {
+            type Target = $inner;
+
+            fn deref(&self) -> &Self::Target {
+                &self.0
+            }
+        }
+
+        impl From<$inner> for $name {
+            fn from(v: $inner) -> Self {
+                $name(v)
+            }
+        }
+    };
+}
+
+impl_varint_newtype!(UnsignedVarInt, u32);
+impl_varint_newtype!(VarInt, i32);
+impl_varint_newtype!(VarLong, i64);
+
+pub(crate) fn put_unsigned_varint<B: BufMut>(buf: &mut B, value: u32) {
+    put_unsigned_varlong(buf, value.into());
+}
+
+pub(crate) fn put_unsigned_varlong<B: BufMut>(buf: &mut B, mut value: u64) {
+    while value >= 0x80 {
+        buf.put_u8((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.put_u8(value as u8);
+}
+
+pub(crate) fn put_varint<B: BufMut>(buf: &mut B, value: i32) {
+    put_unsigned_varint(buf, ((value << 1) ^ (value >> 31)) as u32);
+}
+
+pub(crate) fn put_varlong<B: BufMut>(buf: &mut B, value: i64) {
+    put_unsigned_varlong(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+pub(crate) fn get_unsigned_varint<B: Buf>(buf: &mut B) -> Result<u32> {
+    Ok(get_unsigned(buf, u32::BITS)? as u32)
+}
+
+pub(crate) fn get_unsigned_varlong<B: Buf>(buf: &mut B) -> Result<u64> {
+    get_unsigned(buf, u64::BITS)
+}
+
+pub(crate) fn get_varint<B: Buf>(buf: &mut B) -> Result<i32> {
+    let value = get_unsigned_varint(buf)?;
+    Ok(((value >> 1) as i32) ^ -((value & 1) as i32))
+}
+
+pub(crate) fn get_varlong<B: Buf>(buf: &mut B) -> Result<i64> {
+    let value = get_unsigned_varlong(buf)?;
+    Ok(((value >> 1) as i64) ^ -((value & 1) as i64))
+}
+
+/// Reads an unsigned varint of at most `bits` bits, rejecting encodings with
+/// more bytes than that takes, or whose last byte holds bits beyond it.
+fn get_unsigned<B: Buf>(buf: &mut B, bits: u32) -> Result<u64> {
+    let mut value = 0u64;
+
+    for shift in (0..bits).step_by(7) {
+        if !buf.has_remaining() {
+            return Err(Error::eof(1, 0));
+        }
+
+        let byte = buf.get_u8();
+        if bits - shift < 7 && byte >> (bits - shift) != 0 {
+            return Err(ErrorKind::InvalidVarint.into());
+        }
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(ErrorKind::InvalidVarint.into())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+
+    use super::*;
+
+    #[test]
+    fn test_unsigned_varint() {
+        let cases: &[(u32, &[u8])] = &[
+            (0, &[0x00]),
+            (1, &[0x01]),
+            (127, &[0x7f]),
+            (128, &[0x80, 0x01]),
+            (300, &[0xac, 0x02]),
+            (u32::MAX, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ];
+
+        for (value, encoded) in cases {
+            let mut output = BytesMut::new();
+            put_unsigned_varint(&mut output, *value);
+            assert_eq!(&output[..], *encoded);
+
+            let mut input = *encoded;
+            assert_eq!(get_unsigned_varint(&mut input).unwrap(), *value);
+            assert!(input.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_varint_zigzag() {
+        let cases: &[(i32, &[u8])] = &[
+            (0, &[0x00]),
+            (-1, &[0x01]),
+            (1, &[0x02]),
+            (29, &[0x3a]),
+            (72, &[0x90, 0x01]),
+            (i32::MIN, &[0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ];
+
+        for (value, encoded) in cases {
+            let mut output = BytesMut::new();
+            put_varint(&mut output, *value);
+            assert_eq!(&output[..], *encoded);
+
+            let mut input = *encoded;
+            assert_eq!(get_varint(&mut input).unwrap(), *value);
+        }
+    }
+
+    #[test]
+    fn test_varlong_zigzag() {
+        for value in [0, -1, 1, 1_726_045_943_832, i64::MIN, i64::MAX] {
+            let mut output = BytesMut::new();
+            put_varlong(&mut output, value);
+
+            let mut input = &output[..];
+            assert_eq!(get_varlong(&mut input).unwrap(), value);
+            assert!(input.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_varint_errors() {
+        let mut truncated: &[u8] = &[0x80, 0x80];
+        assert!(matches!(
+            get_unsigned_varint(&mut truncated).unwrap_err().kind(),
+            ErrorKind::Eof { .. }
+        ));
+
+        let mut too_long: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert!(matches!(
+            get_unsigned_varint(&mut too_long).unwrap_err().kind(),
+            ErrorKind::InvalidVarint
+        ));
+
+        let mut overflow: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0x1f];
+        assert!(matches!(
+            get_unsigned_varint(&mut overflow).unwrap_err().kind(),
+            ErrorKind::InvalidVarint
+        ));
+
+        let mut max: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        assert_eq!(get_unsigned_varlong(&mut max).unwrap(), u64::MAX);
+
+        let mut overflow: &[u8] = &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02];
+        assert!(matches!(
+            get_unsigned_varlong(&mut overflow).unwrap_err().kind(),
+            ErrorKind::InvalidVarint
+        ));
+
+        let mut too_long: &[u8] = &[
+            0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00,
+        ];
+        assert!(matches!(
+            get_unsigned_varlong(&mut too_long).unwrap_err().kind(),
+            ErrorKind::InvalidVarint
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+//! Strings, bytes and arrays with a `VARINT` length, where -1 is null, like
+//! the keys, values and headers of records. Used as
+//! `#[serde(with = "varint_length")]`, or
+//! `#[serde(with = "varint_length::nullable_bytes")]` on `Option<Bytes>`.
+//!
+//! Like `serde_kafka::int32_array`, the value is wrapped in a newtype named
+//! after its wire type.
+
+use std::{fmt, marker::PhantomData};
+
+use serde::{de::Visitor, Deserialize, Deserializer, Serialize, Serializer};
+
+pub const VARINT_LENGTH_NAME: &str = "VarIntLength";
+
+pub fn serialize<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: ?Sized + Serialize,
+    S: Serializer,
+{
+    serializer.serialize_newtype_struct(VARINT_LENGTH_NAME, value)
+}
+
+pub fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    struct VarIntLengthVisitor<T>(PhantomData<T>);
+
+    impl<'de, T> Visitor<'de> for VarIntLengthVisitor<T>
+    where
+        T: Deserialize<'de>,
+    {
+        type Value = T;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a value with a VARINT length")
+        }
+
+        fn visit_newtype_struct<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            T::deserialize(deserializer)
+        }
+    }
+
+    deserializer.deserialize_newtype_struct(VARINT_LENGTH_NAME, VarIntLengthVisitor(PhantomData))
+}
+
+pub mod nullable_bytes {
+    use bytes::Bytes;
+    use serde::{Deserializer, Serialize, Serializer};
+
+    use crate::serde_kafka::shared_bytes::share;
+
+    // Serialized as bytes rather than a sequence of `u8`.
+    struct BytesRef<'a>(&'a [u8]);
+
+    impl Serialize for BytesRef<'_> {
+        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    pub fn serialize<S>(value: &Option<Bytes>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        super::serialize(&value.as_deref().map(BytesRef), serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Bytes>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        super::deserialize::<Option<&'de [u8]>, D>(deserializer).map(|value| value.map(share))
+    }
+}
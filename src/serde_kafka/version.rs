@@ -0,0 +1,98 @@
+//! Message versions.
+//!
+//! Every Kafka message is encoded at the version negotiated for its API. A
+//! struct describes all versions of a message at once: fields that only exist
+//! in some versions carry a `versions` attribute (`versions=3+`, `versions=0-2`
+//! or `versions=1`) in the same syntax as the Kafka JSON message specs, and
+//! need a `#[serde(default)]` for the versions they are missing from:
+//!
+//! ```ignore
+//! #[serde(rename = "throttle_time;versions=1+", default)]
+//! pub throttle_time: i32,
+//! ```
+//!
+//! Flexible versions (KIP-482) encode strings and arrays with compact lengths
+//! and end each struct with a tagged field section.
+
+use super::error::{Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Version {
+    pub version: i16,
+    pub flexible: bool,
+}
+
+impl Version {
+    /// The version used by `to_bytes_mut` and `from_bytes`: every field is
+    /// present and the encoding is flexible.
+    pub const LATEST: Version = Version {
+        version: i16::MAX,
+        flexible: true,
+    };
+}
+
+/// A `versions` field attribute.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Versions {
+    min: i16,
+    max: i16,
+}
+
+impl Versions {
+    pub(crate) fn parse(versions: &str) -> Result<Self> {
+        let parse = |v: &str| {
+            v.parse()
+                .map_err(|_| Error::message(format!("Invalid versions `{versions}`")))
+        };
+
+        let (min, max) = if let Some(min) = versions.strip_suffix('+') {
+            (parse(min)?, i16::MAX)
+        } else if let Some((min, max)) = versions.split_once('-') {
+            (parse(min)?, parse(max)?)
+        } else {
+            let version = parse(versions)?;
+            (version, version)
+        };
+
+        Ok(Versions { min, max })
+    }
+
+    pub(crate) fn contains(&self, version: Version) -> bool {
+        (self.min..=self.max).contains(&version.version)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(version: i16) -> Version {
+        Version {
+            version,
+            flexible: false,
+        }
+    }
+
+    #[test]
+    fn test_versions() {
+        let versions = Versions::parse("3+").unwrap();
+        assert!(!versions.contains(version(2)));
+        assert!(versions.contains(version(3)));
+        assert!(versions.contains(Version::LATEST));
+
+        let versions = Versions::parse("0-2").unwrap();
+        assert!(versions.contains(version(0)));
+        assert!(versions.contains(version(2)));
+        assert!(!versions.contains(version(3)));
+
+        let versions = Versions::parse("1").unwrap();
+        assert!(!versions.contains(version(0)));
+        assert!(versions.contains(version(1)));
+        assert!(!versions.contains(version(2)));
+
+        assert!(Versions::parse("a+").is_err());
+        assert!(Versions::parse("").is_err());
+    }
+}
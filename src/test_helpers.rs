@@ -1,24 +1,57 @@
-use std::io;
+use std::{fs, io, net::SocketAddr, path::PathBuf};
 
-use crate::serde_kafka;
+use crate::{
+    cluster_metadata::ClusterMetadata,
+    config::BrokerConfig,
+    constants::ApiKey,
+    headers::{self, RequestHeader, ResponseHeader},
+    messages::{
+        produce_request::{PartitionProduceData, ProduceRequest, TopicProduceData},
+        produce_response::ProduceResponse,
+    },
+    modules::metadata_log_file::payloads::RecordValue,
+    records::{Record, RecordBatch},
+    serde_kafka::{self, TaggedFields, VarInt},
+    Serve,
+};
+use bytes::Bytes;
 use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
+    io::AsyncWriteExt,
     net::{TcpListener, TcpStream},
     task::JoinHandle,
 };
+use uuid::Uuid;
+
+pub struct Request<B> {
+    pub header: RequestHeader,
+    pub body: B,
+}
+
+pub struct Response<B> {
+    pub header: ResponseHeader,
+    pub body: B,
+}
 
 pub struct TestContext {
     pub serve_handle: JoinHandle<()>,
     pub client_io: TcpStream,
+    pub listener_addr: SocketAddr,
 }
 
 impl TestContext {
     pub async fn new() -> Self {
+        Self::with_serve(|serve| serve).await
+    }
+
+    /// A context whose server is configured by `configure`.
+    pub async fn with_serve(configure: impl FnOnce(Serve) -> Serve) -> Self {
         let listener = TcpListener::bind(("0.0.0.0", 0)).await.unwrap();
         let listener_addr = listener.local_addr().unwrap();
 
+        let serve = configure(crate::serve(listener));
         let serve_handle = tokio::spawn(async {
-            crate::serve(listener).run().await.unwrap();
+            serve.run().await.unwrap();
         });
 
         let client_io = TcpStream::connect(listener_addr).await.unwrap();
@@ -26,25 +59,54 @@ impl TestContext {
         Self {
             serve_handle,
             client_io,
+            listener_addr,
         }
     }
 
-    pub async fn parse_response<D>(&mut self) -> io::Result<D>
+    /// A context whose server has `topics`, see `cluster_metadata`, and the
+    /// empty log directory of a test named `name`, returned with it.
+    pub async fn with_logs(name: &str, topics: &[(&str, Uuid, i32)]) -> (Self, PathBuf) {
+        let log_dir = log_dir(name);
+        let log_dirs = vec![log_dir.clone()];
+        let ctx = Self::with_serve(|serve| {
+            serve
+                .config(BrokerConfig {
+                    log_dirs,
+                    ..BrokerConfig::default()
+                })
+                .metadata(cluster_metadata(topics))
+        })
+        .await;
+
+        (ctx, log_dir)
+    }
+
+    /// Reads the response to a request of `api_key` at `api_version`.
+    pub async fn parse_response<B>(
+        &mut self,
+        api_key: ApiKey,
+        api_version: i16,
+    ) -> io::Result<Response<B>>
     where
-        D: DeserializeOwned,
+        B: DeserializeOwned,
     {
-        Ok(
-            serde_kafka::from_async_reader_with_message_size(&mut self.client_io)
-                .await
-                .unwrap(),
-        )
+        read_response(&mut self.client_io, api_key, api_version).await
     }
 
-    pub async fn send_request<S>(&mut self, request: &S) -> serde_kafka::Result<()>
+    /// Sends a request with `header` and `body`, each encoded at its own
+    /// version.
+    pub async fn send_request<B>(
+        &mut self,
+        header: &RequestHeader,
+        body: &B,
+    ) -> serde_kafka::Result<()>
     where
-        S: Serialize,
+        B: Serialize,
     {
-        serde_kafka::to_async_writer_with_message_size(&mut self.client_io, &request).await
+        let message = headers::encode_request(header, body)?;
+        self.client_io.write_all(&message).await?;
+
+        Ok(())
     }
 }
 
@@ -53,3 +115,136 @@ impl Drop for TestContext {
         self.serve_handle.abort();
     }
 }
+
+/// Reads the response to a request of `api_key` at `api_version` from `io`.
+pub async fn read_response<B>(
+    io: &mut TcpStream,
+    api_key: ApiKey,
+    api_version: i16,
+) -> io::Result<Response<B>>
+where
+    B: DeserializeOwned,
+{
+    let message = serde_kafka::read_message(io, usize::MAX).await.unwrap();
+    let (header, body) = headers::decode_response(&message, api_key, api_version).unwrap();
+
+    Ok(Response { header, body })
+}
+
+/// Metadata of `topics`, each with its id and number of partitions, all led
+/// by node 1.
+pub fn cluster_metadata(topics: &[(&str, Uuid, i32)]) -> ClusterMetadata {
+    let mut metadata = ClusterMetadata::default();
+    for &(name, id, partitions) in topics {
+        let topic = RecordValue::TopicRecordValue {
+            frame_version: 1,
+            value_type: 2,
+            version: 0,
+            topic_name: name.into(),
+            topic_uuid: id,
+            tagged_fields: TaggedFields::default(),
+        };
+        metadata.replay(topic).unwrap();
+
+        for partition_id in 0..partitions {
+            let partition = RecordValue::PartitionRecordValue {
+                frame_version: 1,
+                value_type: 3,
+                version: 1,
+                partition_id,
+                topic_uuid: id,
+                replicas: vec![1],
+                in_sync_replicas: vec![1],
+                removing_replicas: vec![],
+                adding_replicas: vec![],
+                leader: 1,
+                leader_epoch: 0,
+                partition_epoch: 0,
+                directories: vec![],
+                tagged_fields: TaggedFields::default(),
+                eligible_leader_replicas: None,
+                last_known_elr: None,
+            };
+            metadata.replay(partition).unwrap();
+        }
+    }
+
+    metadata
+}
+
+/// An empty directory for the logs of a test named `name`.
+pub fn log_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A record batch as producers send it, of a record for each of `values`.
+pub fn batch(values: &[&'static [u8]]) -> Bytes {
+    let batch = RecordBatch {
+        last_offset_delta: values.len() as i32 - 1,
+        producer_id: -1,
+        producer_epoch: -1,
+        base_sequence: -1,
+        records: values
+            .iter()
+            .enumerate()
+            .map(|(offset_delta, &value)| Record {
+                offset_delta: VarInt(offset_delta as i32),
+                value: Some(Bytes::from_static(value)),
+                ..Record::default()
+            })
+            .collect(),
+        ..RecordBatch::default()
+    };
+
+    serde_kafka::to_bytes_mut(&batch).unwrap().freeze()
+}
+
+/// A produce request at `api_version` with `acks`, of a batch to each of
+/// `partitions`, by topic name.
+pub fn produce_request(
+    api_version: i16,
+    acks: i16,
+    partitions: Vec<(&str, i32, Bytes)>,
+) -> Request<ProduceRequest> {
+    Request {
+        header: RequestHeader {
+            request_api_key: ApiKey::Produce.into(),
+            request_api_version: api_version,
+            correlation_id: 7,
+            ..RequestHeader::default()
+        },
+        body: ProduceRequest {
+            acks,
+            timeout_ms: 1000,
+            topic_data: partitions
+                .into_iter()
+                .map(|(name, index, records)| TopicProduceData {
+                    name: name.into(),
+                    partition_data: vec![PartitionProduceData {
+                        index,
+                        records: Some(records),
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        },
+    }
+}
+
+/// Sends a produce `request` through `io` and reads its response.
+pub async fn produce(
+    io: &mut TcpStream,
+    request: &Request<ProduceRequest>,
+) -> Response<ProduceResponse> {
+    let message = headers::encode_request(&request.header, &request.body).unwrap();
+    io.write_all(&message).await.unwrap();
+
+    read_response(io, ApiKey::Produce, request.header.request_api_version)
+        .await
+        .unwrap()
+}
@@ -2,31 +2,39 @@ use serde::{Deserialize, Serialize};
 
 use codecrafters_kafka::{
     constants::{ApiKey, ErrorCode},
-    headers::RequestHeaderV2,
-    modules::api_versions::payloads::{ApiVersionsRequestBody, ApiVersionsResponse},
-    test_helpers::TestContext,
+    headers::RequestHeader,
+    messages::{
+        api_versions_request::ApiVersionsRequest, api_versions_response::ApiVersionsResponse,
+    },
+    test_helpers::{Response, TestContext},
 };
 
-#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub struct ApiVersionsRequest {
-    pub header: RequestHeaderV2,
-    pub body: ApiVersionsRequestBody,
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApiVersionsRequestMessage {
+    pub header: RequestHeader,
+    pub body: ApiVersionsRequest,
 }
 
 #[tokio::test]
 async fn test_response_same_request_correlation_id() {
     let mut ctx = TestContext::new().await;
 
-    let request = ApiVersionsRequest {
-        header: RequestHeaderV2 {
-            api_key: ApiKey::ApiVersions,
-            ..RequestHeaderV2::default()
+    let request = ApiVersionsRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 4,
+            ..RequestHeader::default()
         },
-        ..ApiVersionsRequest::default()
+        ..ApiVersionsRequestMessage::default()
     };
-    ctx.send_request(&request).await.unwrap();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
 
-    let response: ApiVersionsResponse = ctx.parse_response().await.unwrap();
+    let response: Response<ApiVersionsResponse> = ctx
+        .parse_response(ApiKey::ApiVersions, request.header.request_api_version)
+        .await
+        .unwrap();
 
     assert_eq!(
         request.header.correlation_id,
@@ -38,52 +46,97 @@ async fn test_response_same_request_correlation_id() {
 async fn test_unsupported_api_versions_version() {
     let mut ctx = TestContext::new().await;
 
-    let request = ApiVersionsRequest {
-        header: RequestHeaderV2 {
-            api_key: ApiKey::ApiVersions,
-            api_version: 5,
-            ..RequestHeaderV2::default()
+    let request = ApiVersionsRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 5,
+            ..RequestHeader::default()
         },
-        ..ApiVersionsRequest::default()
+        ..ApiVersionsRequestMessage::default()
     };
-    ctx.send_request(&request).await.unwrap();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
 
-    let response: ApiVersionsResponse = ctx.parse_response().await.unwrap();
+    // Unsupported versions are answered with v0.
+    let response: Response<ApiVersionsResponse> =
+        ctx.parse_response(ApiKey::ApiVersions, 0).await.unwrap();
 
-    assert_eq!(response.body.error_code, ErrorCode::UnsupportedVersion);
+    assert_eq!(
+        response.body.error_code,
+        ErrorCode::UnsupportedVersion as i16
+    );
+
+    // With the versions to retry with.
+    let api_keys = &response.body.api_keys;
+    assert_eq!(api_keys.len(), 1);
+    assert_eq!(ApiKey::from(api_keys[0].api_key), ApiKey::ApiVersions);
+    assert_eq!(api_keys[0].min_version, 0);
+    assert_eq!(api_keys[0].max_version, 4);
 }
 
 #[tokio::test]
 async fn test_api_versions() {
     let mut ctx = TestContext::new().await;
 
-    let request = ApiVersionsRequest {
-        header: RequestHeaderV2 {
-            api_key: ApiKey::ApiVersions,
-            ..RequestHeaderV2::default()
+    let request = ApiVersionsRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 4,
+            ..RequestHeader::default()
         },
-        ..ApiVersionsRequest::default()
+        ..ApiVersionsRequestMessage::default()
     };
-    ctx.send_request(&request).await.unwrap();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
 
-    let response: ApiVersionsResponse = ctx.parse_response().await.unwrap();
+    let response: Response<ApiVersionsResponse> = ctx
+        .parse_response(ApiKey::ApiVersions, request.header.request_api_version)
+        .await
+        .unwrap();
 
-    assert_eq!(response.body.error_code, ErrorCode::NoError);
+    assert_eq!(response.body.error_code, ErrorCode::NoError as i16);
     assert_eq!(
         response.header.correlation_id,
         request.header.correlation_id
     );
 
-    assert_eq!(response.body.api_versions[0].api_key, ApiKey::Fetch);
-    assert_eq!(response.body.api_versions[0].max_supported_api_version, 17);
+    assert_eq!(
+        ApiKey::from(response.body.api_keys[0].api_key),
+        ApiKey::Fetch
+    );
+    assert_eq!(response.body.api_keys[0].max_version, 17);
 
-    assert_eq!(response.body.api_versions[1].api_key, ApiKey::ApiVersions);
-    assert_eq!(response.body.api_versions[1].max_supported_api_version, 4);
+    assert_eq!(
+        ApiKey::from(response.body.api_keys[1].api_key),
+        ApiKey::ApiVersions
+    );
+    assert_eq!(response.body.api_keys[1].max_version, 4);
 
     assert_eq!(
-        response.body.api_versions[2].api_key,
+        ApiKey::from(response.body.api_keys[2].api_key),
         ApiKey::DescribeTopicPartitions
     );
+
+    assert_eq!(
+        ApiKey::from(response.body.api_keys[3].api_key),
+        ApiKey::Produce
+    );
+    assert_eq!(response.body.api_keys[3].min_version, 3);
+    assert_eq!(response.body.api_keys[3].max_version, 12);
+
+    assert_eq!(
+        ApiKey::from(response.body.api_keys[4].api_key),
+        ApiKey::Metadata
+    );
+    assert_eq!(response.body.api_keys[4].max_version, 13);
+
+    assert_eq!(
+        ApiKey::from(response.body.api_keys[5].api_key),
+        ApiKey::DescribeCluster
+    );
+    assert_eq!(response.body.api_keys[5].max_version, 2);
 }
 
 #[tokio::test]
@@ -91,17 +144,23 @@ async fn test_serial_requests() {
     let mut ctx = TestContext::new().await;
 
     for i in 0..2 {
-        let request = ApiVersionsRequest {
-            header: RequestHeaderV2 {
-                api_key: ApiKey::ApiVersions,
+        let request = ApiVersionsRequestMessage {
+            header: RequestHeader {
+                request_api_key: ApiKey::ApiVersions.into(),
+                request_api_version: 4,
                 correlation_id: i,
-                ..RequestHeaderV2::default()
+                ..RequestHeader::default()
             },
-            ..ApiVersionsRequest::default()
+            ..ApiVersionsRequestMessage::default()
         };
-        ctx.send_request(&request).await.unwrap();
+        ctx.send_request(&request.header, &request.body)
+            .await
+            .unwrap();
 
-        let response: ApiVersionsResponse = ctx.parse_response().await.unwrap();
+        let response: Response<ApiVersionsResponse> = ctx
+            .parse_response(ApiKey::ApiVersions, request.header.request_api_version)
+            .await
+            .unwrap();
 
         assert_eq!(
             request.header.correlation_id,
@@ -109,3 +168,31 @@ async fn test_serial_requests() {
         );
     }
 }
+
+#[tokio::test]
+async fn test_non_flexible_version() {
+    let mut ctx = TestContext::new().await;
+
+    // v2 requests have a v1 header, without tagged fields.
+    let request = ApiVersionsRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 2,
+            correlation_id: 9,
+            client_id: Some("cli".into()),
+            ..RequestHeader::default()
+        },
+        ..ApiVersionsRequestMessage::default()
+    };
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+
+    let response: Response<ApiVersionsResponse> = ctx
+        .parse_response(ApiKey::ApiVersions, request.header.request_api_version)
+        .await
+        .unwrap();
+
+    assert_eq!(response.header.correlation_id, 9);
+    assert_eq!(response.body.error_code, ErrorCode::NoError as i16);
+}
@@ -0,0 +1,380 @@
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::oneshot,
+    time,
+};
+
+use codecrafters_kafka::{
+    constants::{ApiKey, ErrorCode},
+    headers::{self, RequestHeader},
+    messages::{
+        api_versions_request::ApiVersionsRequest, api_versions_response::ApiVersionsResponse,
+    },
+    modules::error_response::payloads::ErrorResponseBody,
+    test_helpers::{read_response, Response, TestContext},
+    ClosePolicy,
+};
+
+#[derive(Debug, Serialize)]
+struct Request<B> {
+    header: RequestHeader,
+    body: B,
+}
+
+fn unknown_request() -> Request<[u8; 3]> {
+    Request {
+        header: RequestHeader {
+            request_api_key: ApiKey::Unknown(0x7fff).into(),
+            correlation_id: 7,
+            ..RequestHeader::default()
+        },
+        // A body the broker can't know the shape of.
+        body: [0x01, 0x02, 0x03],
+    }
+}
+
+fn api_versions_request() -> Request<ApiVersionsRequest> {
+    Request {
+        header: RequestHeader {
+            request_api_key: ApiKey::ApiVersions.into(),
+            request_api_version: 4,
+            correlation_id: 8,
+            ..RequestHeader::default()
+        },
+        body: ApiVersionsRequest::default(),
+    }
+}
+
+#[tokio::test]
+async fn test_malformed_request_closes_connection() {
+    let mut ctx = TestContext::new().await;
+
+    // A request of 2 bytes, too short for a request header.
+    ctx.client_io
+        .write_all(&[0x00, 0x00, 0x00, 0x02, 0x00, 0x12])
+        .await
+        .unwrap();
+
+    let mut buf = Vec::new();
+    let read = ctx.client_io.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_unknown_api_key() {
+    let mut ctx = TestContext::new().await;
+
+    let request = unknown_request();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    let response: Response<ErrorResponseBody> =
+        ctx.parse_response(ApiKey::ApiVersions, 0).await.unwrap();
+    assert_eq!(response.header.correlation_id, 7);
+    assert_eq!(response.body.error_code, ErrorCode::UnsupportedVersion);
+
+    // The connection is still usable.
+    let request = api_versions_request();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    let response: Response<ApiVersionsResponse> =
+        ctx.parse_response(ApiKey::ApiVersions, 4).await.unwrap();
+    assert_eq!(response.header.correlation_id, 8);
+    assert_eq!(response.body.error_code, ErrorCode::NoError as i16);
+}
+
+#[tokio::test]
+async fn test_unsupported_version() {
+    let mut ctx = TestContext::new().await;
+
+    for (api_key, api_version) in [
+        (ApiKey::Fetch, 18),
+        (ApiKey::Produce, 2),
+        (ApiKey::DescribeTopicPartitions, 1),
+        (ApiKey::Metadata, 14),
+    ] {
+        let request = Request {
+            header: RequestHeader {
+                request_api_key: api_key.into(),
+                request_api_version: api_version,
+                correlation_id: 9,
+                ..RequestHeader::default()
+            },
+            body: [0x00],
+        };
+        ctx.send_request(&request.header, &request.body)
+            .await
+            .unwrap();
+
+        // No response is valid for a version the client can't expect the
+        // shape of, so the connection is closed, whatever the close policy.
+        let mut buf = Vec::new();
+        let read = ctx.client_io.read_to_end(&mut buf).await.unwrap();
+        assert_eq!(read, 0);
+
+        ctx.client_io = TcpStream::connect(ctx.listener_addr).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_invalid_request_body() {
+    let mut ctx = TestContext::new().await;
+
+    // An ApiVersions v3 body with a client id longer than the request.
+    let mut request = api_versions_request();
+    request.header.request_api_version = 3;
+    let truncated = Request {
+        header: request.header,
+        body: [0x05, b'c'],
+    };
+    ctx.send_request(&truncated.header, &truncated.body)
+        .await
+        .unwrap();
+
+    let response: Response<ErrorResponseBody> =
+        ctx.parse_response(ApiKey::ApiVersions, 0).await.unwrap();
+    assert_eq!(response.header.correlation_id, 8);
+    assert_eq!(response.body.error_code, ErrorCode::InvalidRequest);
+
+    // Other APIs' responses can't be an error code alone.
+    let metadata = Request {
+        header: RequestHeader {
+            request_api_key: ApiKey::Metadata.into(),
+            request_api_version: 12,
+            correlation_id: 9,
+            ..RequestHeader::default()
+        },
+        body: [0x05],
+    };
+    ctx.send_request(&metadata.header, &metadata.body)
+        .await
+        .unwrap();
+    let mut buf = Vec::new();
+    let read = ctx.client_io.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_close_on_error() {
+    let mut ctx =
+        TestContext::with_serve(|serve| serve.close_policy(ClosePolicy::CloseOnError)).await;
+
+    let request = unknown_request();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    let response: Response<ErrorResponseBody> =
+        ctx.parse_response(ApiKey::ApiVersions, 0).await.unwrap();
+    assert_eq!(response.body.error_code, ErrorCode::UnsupportedVersion);
+
+    let mut buf = Vec::new();
+    let read = ctx.client_io.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_idle_connection_closed() {
+    let mut ctx = TestContext::with_serve(|serve| {
+        serve.connections_max_idle(Some(Duration::from_millis(50)))
+    })
+    .await;
+
+    let mut buf = Vec::new();
+    let read = time::timeout(Duration::from_secs(5), ctx.client_io.read_to_end(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 0);
+
+    // Or stalled in the middle of a request.
+    let mut client_io = TcpStream::connect(ctx.listener_addr).await.unwrap();
+    client_io
+        .write_all(&[0x00, 0x00, 0x00, 0x20, 0x00])
+        .await
+        .unwrap();
+    let read = time::timeout(Duration::from_secs(5), client_io.read_to_end(&mut buf))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_graceful_shutdown() {
+    let listener = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let listener_addr = listener.local_addr().unwrap();
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel();
+    let serve_handle = tokio::spawn(
+        codecrafters_kafka::serve(listener)
+            .with_graceful_shutdown(async {
+                shutdown_rx.await.ok();
+            })
+            .run(),
+    );
+
+    let mut client_io = TcpStream::connect(listener_addr).await.unwrap();
+    // A client that connects and leaves without a request.
+    drop(TcpStream::connect(listener_addr).await.unwrap());
+    // And one stalled in the middle of a request.
+    let mut stalled_io = TcpStream::connect(listener_addr).await.unwrap();
+    stalled_io
+        .write_all(&[0x00, 0x00, 0x00, 0x20, 0x00])
+        .await
+        .unwrap();
+
+    let request = api_versions_request();
+    let message = headers::encode_request(&request.header, &request.body).unwrap();
+    client_io.write_all(&message).await.unwrap();
+    let response: Response<ApiVersionsResponse> =
+        read_response(&mut client_io, ApiKey::ApiVersions, 4)
+            .await
+            .unwrap();
+    assert_eq!(response.header.correlation_id, 8);
+
+    // Open connections are closed, and no more are accepted.
+    shutdown_tx.send(()).unwrap();
+    time::timeout(Duration::from_secs(5), serve_handle)
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+
+    let mut buf = Vec::new();
+    assert_eq!(client_io.read_to_end(&mut buf).await.unwrap(), 0);
+    assert_eq!(stalled_io.read_to_end(&mut buf).await.unwrap(), 0);
+    assert!(TcpStream::connect(listener_addr).await.is_err());
+}
+
+#[tokio::test]
+async fn test_pipelined_requests() {
+    let mut ctx = TestContext::with_serve(|serve| serve.max_in_flight_requests(2)).await;
+
+    // Every request is written before any response is read.
+    let mut requests = Vec::new();
+    for correlation_id in 0..10 {
+        let message = if correlation_id % 2 == 0 {
+            let mut request = api_versions_request();
+            request.header.correlation_id = correlation_id;
+            headers::encode_request(&request.header, &request.body)
+        } else {
+            let mut request = unknown_request();
+            request.header.correlation_id = correlation_id;
+            headers::encode_request(&request.header, &request.body)
+        };
+        requests.extend_from_slice(&message.unwrap());
+    }
+    ctx.client_io.write_all(&requests).await.unwrap();
+
+    for correlation_id in 0..10 {
+        if correlation_id % 2 == 0 {
+            let response: Response<ApiVersionsResponse> =
+                ctx.parse_response(ApiKey::ApiVersions, 4).await.unwrap();
+            assert_eq!(response.header.correlation_id, correlation_id);
+        } else {
+            let response: Response<ErrorResponseBody> =
+                ctx.parse_response(ApiKey::ApiVersions, 0).await.unwrap();
+            assert_eq!(response.header.correlation_id, correlation_id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_request_queue() {
+    let mut metrics = None;
+    let mut ctx = TestContext::with_serve(|serve| {
+        metrics = Some(serve.metrics());
+        serve.num_io_threads(1).queued_max_requests(1)
+    })
+    .await;
+    let metrics = metrics.unwrap();
+
+    let request = api_versions_request();
+    let message = headers::encode_request(&request.header, &request.body).unwrap();
+    ctx.client_io.write_all(&message.repeat(5)).await.unwrap();
+
+    for _ in 0..5 {
+        let response: Response<ApiVersionsResponse> =
+            ctx.parse_response(ApiKey::ApiVersions, 4).await.unwrap();
+        assert_eq!(response.header.correlation_id, 8);
+    }
+    assert_eq!(metrics.requests(), 5);
+}
+
+#[tokio::test]
+async fn test_request_too_large() {
+    let mut ctx = TestContext::with_serve(|serve| serve.socket_request_max_bytes(1024)).await;
+
+    // A size of 2 GiB, refused before the request is read.
+    ctx.client_io
+        .write_all(&[0x7f, 0xff, 0xff, 0xff, 0x00, 0x12])
+        .await
+        .unwrap();
+
+    let mut buf = Vec::new();
+    let read = ctx.client_io.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(read, 0);
+}
+
+#[tokio::test]
+async fn test_max_connections_per_ip() {
+    let mut ctx = TestContext::with_serve(|serve| serve.max_connections_per_ip(1)).await;
+
+    let request = api_versions_request();
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    let _: Response<ApiVersionsResponse> =
+        ctx.parse_response(ApiKey::ApiVersions, 4).await.unwrap();
+
+    // A second connection from the same address is closed right away.
+    let mut excess_io = TcpStream::connect(ctx.client_io.peer_addr().unwrap())
+        .await
+        .unwrap();
+    let mut buf = Vec::new();
+    let read = excess_io.read_to_end(&mut buf).await.unwrap();
+    assert_eq!(read, 0);
+
+    // The first one is still served.
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    let response: Response<ApiVersionsResponse> =
+        ctx.parse_response(ApiKey::ApiVersions, 4).await.unwrap();
+    assert_eq!(response.header.correlation_id, 8);
+}
+
+#[tokio::test]
+async fn test_multiple_listeners() {
+    let plaintext = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let internal = TcpListener::bind(("127.0.0.1", 0)).await.unwrap();
+    let addrs = [
+        plaintext.local_addr().unwrap(),
+        internal.local_addr().unwrap(),
+    ];
+
+    let serve_handle = tokio::spawn(
+        codecrafters_kafka::serve_listeners([("PLAINTEXT", plaintext), ("INTERNAL", internal)])
+            .run(),
+    );
+
+    // Each listener serves its own connections.
+    let request = api_versions_request();
+    let message = headers::encode_request(&request.header, &request.body).unwrap();
+    for addr in addrs {
+        let mut client_io = TcpStream::connect(addr).await.unwrap();
+        client_io.write_all(&message).await.unwrap();
+        let response: Response<ApiVersionsResponse> =
+            read_response(&mut client_io, ApiKey::ApiVersions, 4)
+                .await
+                .unwrap();
+        assert_eq!(response.header.correlation_id, 8);
+    }
+
+    serve_handle.abort();
+}
@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use codecrafters_kafka::{
+    cluster_metadata::ClusterMetadata,
+    config::BrokerConfig,
+    constants::{ApiKey, ErrorCode, CLUSTER_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        describe_cluster_request::DescribeClusterRequest,
+        describe_cluster_response::DescribeClusterResponse,
+    },
+    test_helpers::{Response, TestContext},
+};
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DescribeClusterRequestMessage {
+    pub header: RequestHeader,
+    pub body: DescribeClusterRequest,
+}
+
+fn describe_cluster_request(api_version: i16) -> DescribeClusterRequestMessage {
+    DescribeClusterRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::DescribeCluster.into(),
+            request_api_version: api_version,
+            correlation_id: 6,
+            ..RequestHeader::default()
+        },
+        body: DescribeClusterRequest::default(),
+    }
+}
+
+async fn describe_cluster(
+    ctx: &mut TestContext,
+    request: &DescribeClusterRequestMessage,
+) -> Response<DescribeClusterResponse> {
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+
+    ctx.parse_response(ApiKey::DescribeCluster, request.header.request_api_version)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_describe_cluster() {
+    let config = BrokerConfig {
+        node_id: 3,
+        advertised_listeners: vec!["PLAINTEXT://kafka.example:19092".parse().unwrap()],
+        ..BrokerConfig::default()
+    };
+    let metadata = ClusterMetadata::default().with_cluster_id("cluster");
+    let mut ctx = TestContext::with_serve(|serve| serve.config(config).metadata(metadata)).await;
+
+    for api_version in 0..=2 {
+        let mut request = describe_cluster_request(api_version);
+        request.body.include_cluster_authorized_operations = api_version == 2;
+        let response = describe_cluster(&mut ctx, &request).await;
+        assert_eq!(response.header.correlation_id, 6);
+
+        let body = &response.body;
+        assert_eq!(body.error_code, ErrorCode::NoError as i16);
+        assert_eq!(*body.cluster_id, "cluster");
+        assert_eq!(body.controller_id, 3);
+        assert_eq!(body.brokers.len(), 1);
+        assert_eq!(body.brokers[0].broker_id, 3);
+        assert_eq!(*body.brokers[0].host, "kafka.example");
+        assert_eq!(body.brokers[0].port, 19092);
+        assert!(!body.brokers[0].is_fenced);
+        let operations = match api_version {
+            2 => CLUSTER_AUTHORIZED_OPERATIONS,
+            _ => i32::MIN,
+        };
+        assert_eq!(body.cluster_authorized_operations, operations);
+    }
+}
+
+#[tokio::test]
+async fn test_controller_endpoint_type() {
+    let mut ctx = TestContext::new().await;
+
+    for (endpoint_type, error_code) in [
+        (2, ErrorCode::MismatchedEndpointType),
+        (3, ErrorCode::UnsupportedEndpointType),
+    ] {
+        let mut request = describe_cluster_request(1);
+        request.body.endpoint_type = endpoint_type;
+        let response = describe_cluster(&mut ctx, &request).await;
+
+        assert_eq!(response.body.error_code, error_code as i16);
+        assert_eq!(response.body.endpoint_type, endpoint_type);
+        assert!(response.body.brokers.is_empty());
+    }
+}
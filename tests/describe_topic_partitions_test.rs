@@ -1,50 +1,204 @@
 use serde::{Deserialize, Serialize};
 
 use codecrafters_kafka::{
-    constants::{ApiKey, ErrorCode},
-    headers::RequestHeaderV2,
-    modules::describe_topic_partitions::payloads::{
-        DescribeTopicPartitionsRequestBody, DescribeTopicPartitionsResponse, TopicRequest,
+    constants::{ApiKey, ErrorCode, TOPIC_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        describe_topic_partitions_request::{Cursor, DescribeTopicPartitionsRequest, TopicRequest},
+        describe_topic_partitions_response::DescribeTopicPartitionsResponse,
     },
-    test_helpers::TestContext,
+    modules::metadata_log_file::payloads::{RecordValue, NO_LEADER_CHANGE},
+    serde_kafka::TaggedFields,
+    test_helpers::{cluster_metadata, Response, TestContext},
 };
 use uuid::Uuid;
 
-#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
-pub struct DescribeTopicPartitionsRequest {
-    pub header: RequestHeaderV2,
-    pub body: DescribeTopicPartitionsRequestBody,
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DescribeTopicPartitionsRequestMessage {
+    pub header: RequestHeader,
+    pub body: DescribeTopicPartitionsRequest,
 }
 
-#[tokio::test]
-async fn test_unknown_topic() {
-    let mut ctx = TestContext::new().await;
+const FOO_ID: Uuid = Uuid::from_u128(0x91);
+const BAR_ID: Uuid = Uuid::from_u128(0x92);
 
-    let request = DescribeTopicPartitionsRequest {
-        header: RequestHeaderV2 {
-            api_key: ApiKey::DescribeTopicPartitions,
-            ..RequestHeaderV2::default()
+fn describe_request(
+    names: &[&str],
+    limit: i32,
+    cursor: Option<Cursor>,
+) -> DescribeTopicPartitionsRequestMessage {
+    DescribeTopicPartitionsRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::DescribeTopicPartitions.into(),
+            ..RequestHeader::default()
         },
-        body: DescribeTopicPartitionsRequestBody {
-            topics: vec![TopicRequest {
-                name: "foo".into(),
-                ..Default::default()
-            }],
-            response_partition_limit: 100,
-            cursor: 0xff,
+        body: DescribeTopicPartitionsRequest {
+            topics: names
+                .iter()
+                .map(|&name| TopicRequest {
+                    name: name.into(),
+                    ..Default::default()
+                })
+                .collect(),
+            response_partition_limit: limit,
+            cursor,
             ..Default::default()
         },
-    };
-    ctx.send_request(&request).await.unwrap();
+    }
+}
 
-    let response: DescribeTopicPartitionsResponse = ctx.parse_response().await.unwrap();
+async fn describe(
+    ctx: &mut TestContext,
+    request: &DescribeTopicPartitionsRequestMessage,
+) -> Response<DescribeTopicPartitionsResponse> {
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
 
-    assert_eq!(response.body.topics[0].uuid, Uuid::nil());
+    ctx.parse_response(
+        ApiKey::DescribeTopicPartitions,
+        request.header.request_api_version,
+    )
+    .await
+    .unwrap()
+}
+
+/// The topic and partition index of each partition described.
+fn described(response: &DescribeTopicPartitionsResponse) -> Vec<(String, i32)> {
+    response
+        .topics
+        .iter()
+        .flat_map(|topic| {
+            let name = topic.name.as_deref().unwrap().to_string();
+            topic
+                .partitions
+                .iter()
+                .map(move |partition| (name.clone(), partition.partition_index))
+        })
+        .collect()
+}
+
+fn next_cursor(response: &DescribeTopicPartitionsResponse) -> Option<(&str, i32)> {
+    let cursor = response.next_cursor.as_ref()?;
+    Some((cursor.topic_name.as_str(), cursor.partition_index))
+}
+
+#[tokio::test]
+async fn test_unknown_topic() {
+    let mut ctx = TestContext::new().await;
+
+    let request = describe_request(&["foo"], 100, None);
+    let response = describe(&mut ctx, &request).await;
+
+    assert_eq!(response.body.topics[0].topic_id, Uuid::nil());
 
     assert_eq!(
         request.header.correlation_id,
         response.header.correlation_id
     );
-    assert_eq!(response.body.topics[0].error_code, ErrorCode::UnknownTopic);
-    assert_eq!(response.body.topics[0].name, response.body.topics[0].name);
+    assert_eq!(
+        response.body.topics[0].error_code,
+        ErrorCode::UnknownTopic as i16
+    );
+    assert_eq!(
+        response.body.topics[0].name.as_deref().map(String::as_str),
+        Some("foo")
+    );
+    assert_eq!(response.body.next_cursor, None);
+}
+
+#[tokio::test]
+async fn test_topics() {
+    let mut metadata = cluster_metadata(&[("foo", FOO_ID, 2), ("bar", BAR_ID, 1)]);
+    let elr_change = RecordValue::PartitionChangeRecordValue {
+        frame_version: 1,
+        value_type: 5,
+        version: 2,
+        partition_id: 1,
+        topic_uuid: FOO_ID,
+        tagged_fields: TaggedFields::default(),
+        in_sync_replicas: None,
+        leader: NO_LEADER_CHANGE,
+        replicas: Some(vec![1, 2]),
+        eligible_leader_replicas: Some(vec![2]),
+        last_known_elr: Some(vec![3]),
+        directories: None,
+    };
+    metadata.replay(elr_change).unwrap();
+    let mut ctx = TestContext::with_serve(|serve| serve.metadata(metadata)).await;
+
+    // Described by name, whatever order they're requested in.
+    let request = describe_request(&["foo", "baz", "bar"], 100, None);
+    let response = describe(&mut ctx, &request).await.body;
+
+    let topics = &response.topics;
+    assert_eq!(topics.len(), 3);
+    assert_eq!(topics[0].topic_id, BAR_ID);
+    assert_eq!(topics[1].error_code, ErrorCode::UnknownTopic as i16);
+    assert_eq!(topics[2].error_code, ErrorCode::NoError as i16);
+    assert_eq!(topics[2].topic_id, FOO_ID);
+    assert!(!topics[2].is_internal);
+    assert_eq!(
+        topics[2].topic_authorized_operations,
+        TOPIC_AUTHORIZED_OPERATIONS
+    );
+
+    let partition = &topics[2].partitions[1];
+    assert_eq!(partition.partition_index, 1);
+    assert_eq!(partition.leader_id, 1);
+    assert_eq!(partition.leader_epoch, 0);
+    assert_eq!(partition.replica_nodes, [1, 2]);
+    assert_eq!(partition.isr_nodes, [1]);
+    assert_eq!(partition.eligible_leader_replicas, Some(vec![2]));
+    assert_eq!(partition.last_known_elr, Some(vec![3]));
+    // Only this broker, node 1, is alive.
+    assert_eq!(partition.offline_replicas, [2]);
+    assert_eq!(topics[2].partitions[0].offline_replicas, Vec::<i32>::new());
+    assert_eq!(
+        topics[2].partitions[0].eligible_leader_replicas,
+        Some(vec![])
+    );
+    assert_eq!(response.next_cursor, None);
+
+    // Every topic when none are requested.
+    let response = describe(&mut ctx, &describe_request(&[], 100, None))
+        .await
+        .body;
+    assert_eq!(
+        described(&response),
+        [("bar".into(), 0), ("foo".into(), 0), ("foo".into(), 1)]
+    );
+}
+
+#[tokio::test]
+async fn test_pagination() {
+    let metadata = cluster_metadata(&[("foo", FOO_ID, 3), ("bar", BAR_ID, 1)]);
+    let mut ctx = TestContext::with_serve(|serve| serve.metadata(metadata)).await;
+
+    let response = describe(&mut ctx, &describe_request(&["foo", "bar"], 2, None))
+        .await
+        .body;
+    assert_eq!(described(&response), [("bar".into(), 0), ("foo".into(), 0)]);
+    assert_eq!(next_cursor(&response), Some(("foo", 1)));
+
+    let cursor = Cursor {
+        topic_name: "foo".into(),
+        partition_index: 1,
+        ..Default::default()
+    };
+    let response = describe(
+        &mut ctx,
+        &describe_request(&["foo", "bar"], 2, Some(cursor)),
+    )
+    .await
+    .body;
+    assert_eq!(described(&response), [("foo".into(), 1), ("foo".into(), 2)]);
+    assert_eq!(next_cursor(&response), None);
+
+    // A page ending with a topic continues at the next one.
+    let response = describe(&mut ctx, &describe_request(&[], 1, None))
+        .await
+        .body;
+    assert_eq!(described(&response), [("bar".into(), 0)]);
+    assert_eq!(next_cursor(&response), Some(("foo", 0)));
 }
@@ -0,0 +1,179 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use tokio::{net::TcpStream, time};
+
+use codecrafters_kafka::{
+    constants::{ApiKey, ErrorCode},
+    headers::RequestHeader,
+    messages::{
+        fetch_request::{FetchPartition, FetchRequest, FetchTopic},
+        fetch_response::FetchResponse,
+    },
+    records::{Record, RecordBatch},
+    serde_kafka,
+    test_helpers::{batch, produce, produce_request, Response, TestContext},
+};
+use uuid::Uuid;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FetchRequestMessage {
+    pub header: RequestHeader,
+    pub body: FetchRequest,
+}
+
+const TOPIC_ID: Uuid = Uuid::from_u128(0x72);
+
+/// Produces `records` to partition 0 of `foo` through a connection of its own,
+/// so responses to the fetches of `ctx` don't hold it up.
+async fn produce_to(ctx: &TestContext, records: Bytes) {
+    let mut io = TcpStream::connect(ctx.listener_addr).await.unwrap();
+    let request = produce_request(12, 1, vec![("foo", 0, records)]);
+    let response = produce(&mut io, &request).await;
+    let partition = &response.body.responses[0].partition_responses[0];
+    assert_eq!(partition.error_code, ErrorCode::NoError as i16);
+}
+
+fn fetch_request(api_version: i16, partitions: Vec<(i32, i64)>) -> FetchRequestMessage {
+    FetchRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::Fetch.into(),
+            request_api_version: api_version,
+            correlation_id: 7,
+            ..RequestHeader::default()
+        },
+        body: FetchRequest {
+            topics: vec![FetchTopic {
+                topic: "foo".into(),
+                topic_id: TOPIC_ID,
+                partitions: partitions
+                    .into_iter()
+                    .map(|(partition, fetch_offset)| FetchPartition {
+                        partition,
+                        fetch_offset,
+                        partition_max_bytes: 1 << 20,
+                        ..Default::default()
+                    })
+                    .collect(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        },
+    }
+}
+
+async fn fetch(ctx: &mut TestContext, request: &FetchRequestMessage) -> Response<FetchResponse> {
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+
+    ctx.parse_response(ApiKey::Fetch, request.header.request_api_version)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_fetch() {
+    let (mut ctx, log_dir) = TestContext::with_logs("fetch-test", &[("foo", TOPIC_ID, 2)]).await;
+    let first = batch(&[b"a", b"b"]);
+    let second = batch(&[b"c"]);
+    produce_to(&ctx, first.clone()).await;
+    produce_to(&ctx, second.clone()).await;
+
+    // By topic name, then by topic id.
+    for api_version in [4, 12, 16] {
+        let request = fetch_request(api_version, vec![(0, 1), (1, 0)]);
+        let response = fetch(&mut ctx, &request).await;
+
+        assert_eq!(response.header.correlation_id, 7);
+        assert_eq!(response.body.error_code, ErrorCode::NoError as i16);
+        let topic = &response.body.responses[0];
+        if api_version < 13 {
+            assert_eq!(*topic.topic, "foo");
+        } else {
+            assert_eq!(topic.topic_id, TOPIC_ID);
+        }
+
+        // Whole batches, from the one holding the fetch offset.
+        let partition = &topic.partitions[0];
+        assert_eq!(partition.error_code, ErrorCode::NoError as i16);
+        assert_eq!(partition.high_watermark, 3);
+        let records = partition.records.as_ref().unwrap();
+        let (batch, rest): (RecordBatch<Record>, _) =
+            serde_kafka::from_bytes_trail(records).unwrap();
+        assert_eq!(batch.base_offset, 0);
+        assert_eq!(rest.len(), second.len());
+
+        let partition = &topic.partitions[1];
+        assert_eq!(partition.high_watermark, 0);
+        assert_eq!(partition.records.as_deref(), Some(&[][..]));
+    }
+    // Fetching a partition doesn't create its log.
+    assert!(log_dir.join("foo-0").exists());
+    assert!(!log_dir.join("foo-1").exists());
+
+    // The first batch is returned even when it's over the limits.
+    let mut request = fetch_request(16, vec![(0, 0)]);
+    request.body.max_bytes = 1;
+    let response = fetch(&mut ctx, &request).await;
+    let partition = &response.body.responses[0].partitions[0];
+    assert_eq!(partition.records.as_ref().unwrap(), &first);
+}
+
+#[tokio::test]
+async fn test_fetch_errors() {
+    let (mut ctx, _) = TestContext::with_logs("fetch-errors-test", &[("foo", TOPIC_ID, 2)]).await;
+
+    let request = fetch_request(16, vec![(0, 1), (2, 0)]);
+    let response = fetch(&mut ctx, &request).await;
+    let partitions = &response.body.responses[0].partitions;
+    assert_eq!(partitions[0].error_code, ErrorCode::OffsetOutOfRange as i16);
+    assert_eq!(partitions[1].error_code, ErrorCode::UnknownTopic as i16);
+
+    let mut request = fetch_request(16, vec![(0, 0)]);
+    request.body.topics[0].topic_id = Uuid::from_u128(0x73);
+    let response = fetch(&mut ctx, &request).await;
+    let partition = &response.body.responses[0].partitions[0];
+    assert_eq!(partition.error_code, ErrorCode::UnknownTopicId as i16);
+
+    let mut request = fetch_request(12, vec![(0, 0)]);
+    request.body.topics[0].topic = "bar".into();
+    let response = fetch(&mut ctx, &request).await;
+    let partition = &response.body.responses[0].partitions[0];
+    assert_eq!(partition.error_code, ErrorCode::UnknownTopic as i16);
+}
+
+#[tokio::test]
+async fn test_long_polling() {
+    let (mut ctx, _) =
+        TestContext::with_logs("fetch-long-polling-test", &[("foo", TOPIC_ID, 2)]).await;
+
+    // Nothing to read before the max wait time runs out.
+    let mut request = fetch_request(16, vec![(0, 0)]);
+    request.body.min_bytes = 1;
+    request.body.max_wait_ms = 50;
+    let response = fetch(&mut ctx, &request).await;
+    let partition = &response.body.responses[0].partitions[0];
+    assert_eq!(partition.error_code, ErrorCode::NoError as i16);
+    assert_eq!(partition.records.as_deref(), Some(&[][..]));
+
+    // Answered as soon as records are appended.
+    request.body.max_wait_ms = 10_000;
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+    time::sleep(Duration::from_millis(50)).await;
+    let records = batch(&[b"a"]);
+    produce_to(&ctx, records.clone()).await;
+
+    let response: Response<FetchResponse> = time::timeout(
+        Duration::from_secs(5),
+        ctx.parse_response(ApiKey::Fetch, 16),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let partition = &response.body.responses[0].partitions[0];
+    assert_eq!(partition.records.as_ref().unwrap(), &records);
+}
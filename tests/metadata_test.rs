@@ -0,0 +1,186 @@
+use serde::{Deserialize, Serialize};
+
+use codecrafters_kafka::{
+    config::BrokerConfig,
+    constants::{ApiKey, ErrorCode, TOPIC_AUTHORIZED_OPERATIONS},
+    headers::RequestHeader,
+    messages::{
+        metadata_request::{MetadataRequest, MetadataRequestTopic},
+        metadata_response::{MetadataResponse, MetadataResponseTopic},
+    },
+    test_helpers::{cluster_metadata, Response, TestContext},
+};
+use uuid::Uuid;
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataRequestMessage {
+    pub header: RequestHeader,
+    pub body: MetadataRequest,
+}
+
+const FOO_ID: Uuid = Uuid::from_u128(0x81);
+const BAR_ID: Uuid = Uuid::from_u128(0x82);
+
+async fn context(config: BrokerConfig) -> TestContext {
+    let metadata =
+        cluster_metadata(&[("foo", FOO_ID, 2), ("bar", BAR_ID, 1)]).with_cluster_id("cluster");
+    TestContext::with_serve(|serve| serve.config(config).metadata(metadata)).await
+}
+
+fn metadata_request(
+    api_version: i16,
+    topics: Option<Vec<MetadataRequestTopic>>,
+) -> MetadataRequestMessage {
+    MetadataRequestMessage {
+        header: RequestHeader {
+            request_api_key: ApiKey::Metadata.into(),
+            request_api_version: api_version,
+            correlation_id: 7,
+            ..RequestHeader::default()
+        },
+        body: MetadataRequest {
+            topics,
+            ..Default::default()
+        },
+    }
+}
+
+fn by_name(name: &str) -> MetadataRequestTopic {
+    MetadataRequestTopic {
+        name: Some(name.into()),
+        ..Default::default()
+    }
+}
+
+fn name(topic: &MetadataResponseTopic) -> Option<&str> {
+    topic.name.as_deref().map(String::as_str)
+}
+
+async fn metadata(
+    ctx: &mut TestContext,
+    request: &MetadataRequestMessage,
+) -> Response<MetadataResponse> {
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+
+    ctx.parse_response(ApiKey::Metadata, request.header.request_api_version)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_all_topics() {
+    let mut ctx = context(BrokerConfig::default()).await;
+
+    for request in [
+        metadata_request(12, None),
+        metadata_request(0, Some(vec![])),
+    ] {
+        let response = metadata(&mut ctx, &request).await;
+        assert_eq!(response.header.correlation_id, 7);
+
+        let body = response.body;
+        assert_eq!(body.brokers.len(), 1);
+        let broker = &body.brokers[0];
+        assert_eq!(broker.node_id, 1);
+        // The advertised listener leaves the host out.
+        assert_eq!(
+            *broker.host,
+            ctx.client_io.peer_addr().unwrap().ip().to_string()
+        );
+        assert_eq!(broker.port, 9092);
+
+        let names: Vec<_> = body.topics.iter().map(name).collect();
+        assert_eq!(names, [Some("bar"), Some("foo")]);
+        let foo = &body.topics[1];
+        assert_eq!(foo.error_code, ErrorCode::NoError as i16);
+        assert_eq!(foo.partitions.len(), 2);
+        assert_eq!(foo.partitions[1].partition_index, 1);
+        assert_eq!(foo.partitions[1].leader_id, 1);
+        assert_eq!(foo.partitions[1].replica_nodes, [1]);
+        assert_eq!(foo.partitions[1].isr_nodes, [1]);
+        assert_eq!(foo.partitions[1].offline_replicas, Vec::<i32>::new());
+
+        if request.header.request_api_version > 0 {
+            assert_eq!(
+                body.cluster_id.as_deref().map(String::as_str),
+                Some("cluster")
+            );
+            assert_eq!(body.controller_id, 1);
+            assert_eq!(foo.topic_id, FOO_ID);
+        }
+    }
+
+    // An empty array asks for no topics from v1.
+    let response = metadata(&mut ctx, &metadata_request(1, Some(vec![]))).await;
+    assert!(response.body.topics.is_empty());
+}
+
+#[tokio::test]
+async fn test_requested_topics() {
+    let mut ctx = context(BrokerConfig::default()).await;
+
+    let mut request = metadata_request(8, Some(vec![by_name("foo"), by_name("baz")]));
+    request.body.include_topic_authorized_operations = true;
+    request.body.allow_auto_topic_creation = false;
+    let response = metadata(&mut ctx, &request).await;
+
+    let topics = &response.body.topics;
+    assert_eq!(topics.len(), 2);
+    assert_eq!(topics[0].error_code, ErrorCode::NoError as i16);
+    assert_eq!(
+        topics[0].topic_authorized_operations,
+        TOPIC_AUTHORIZED_OPERATIONS
+    );
+    assert_eq!(topics[1].error_code, ErrorCode::UnknownTopic as i16);
+
+    // Nor auto-created when asked to, including before v4, which always
+    // asks.
+    for api_version in [3, 8] {
+        let request = metadata_request(api_version, Some(vec![by_name("baz")]));
+        assert!(request.body.allow_auto_topic_creation);
+        let response = metadata(&mut ctx, &request).await;
+        assert_eq!(
+            response.body.topics[0].error_code,
+            ErrorCode::UnknownTopic as i16
+        );
+    }
+    assert_eq!(name(&topics[1]), Some("baz"));
+
+    let by_id = |topic_id| MetadataRequestTopic {
+        topic_id,
+        name: None,
+        ..Default::default()
+    };
+    let request = metadata_request(12, Some(vec![by_id(BAR_ID), by_id(Uuid::from_u128(0x83))]));
+    let response = metadata(&mut ctx, &request).await;
+
+    let topics = &response.body.topics;
+    assert_eq!(name(&topics[0]), Some("bar"));
+    assert_eq!(topics[0].topic_authorized_operations, i32::MIN);
+    assert_eq!(topics[1].error_code, ErrorCode::UnknownTopicId as i16);
+    assert_eq!(topics[1].name, None);
+}
+
+#[tokio::test]
+async fn test_advertised_listener() {
+    let config = BrokerConfig {
+        node_id: 3,
+        advertised_listeners: vec!["PLAINTEXT://kafka.example:19092".parse().unwrap()],
+        ..BrokerConfig::default()
+    };
+    let mut ctx = context(config).await;
+
+    let response = metadata(&mut ctx, &metadata_request(13, None)).await;
+    let broker = &response.body.brokers[0];
+    assert_eq!(broker.node_id, 3);
+    assert_eq!(*broker.host, "kafka.example");
+    assert_eq!(broker.port, 19092);
+    assert_eq!(response.body.controller_id, 3);
+
+    // Led by node 1, which isn't this broker, so it's offline.
+    let partition = &response.body.topics[0].partitions[0];
+    assert_eq!(partition.leader_id, 1);
+    assert_eq!(partition.offline_replicas, [1]);
+}
@@ -0,0 +1,112 @@
+use std::fs;
+
+use bytes::Bytes;
+use tokio::time;
+
+use codecrafters_kafka::{
+    constants::ErrorCode,
+    headers::RequestHeader,
+    messages::produce_request::ProduceRequest,
+    records::{Record, RecordBatch},
+    serde_kafka,
+    test_helpers::{batch, produce, produce_request, Request, TestContext},
+};
+use uuid::Uuid;
+
+const TOPIC_ID: Uuid = Uuid::from_u128(0x71);
+
+#[tokio::test]
+async fn test_produce() {
+    let (mut ctx, log_dir) = TestContext::with_logs("produce-test", &[("foo", TOPIC_ID, 2)]).await;
+
+    for (api_version, base_offset) in [(3, 0), (12, 2)] {
+        let request = produce_request(api_version, -1, vec![("foo", 1, batch(&[b"a", b"b"]))]);
+        let response = produce(&mut ctx.client_io, &request).await;
+
+        assert_eq!(response.header.correlation_id, 7);
+        let topic = &response.body.responses[0];
+        assert_eq!(*topic.name, "foo");
+        let partition = &topic.partition_responses[0];
+        assert_eq!(partition.index, 1);
+        assert_eq!(partition.error_code, ErrorCode::NoError as i16);
+        assert_eq!(partition.base_offset, base_offset);
+    }
+
+    let log = fs::read(log_dir.join("foo-1/00000000000000000000.log")).unwrap();
+    let (first, rest): (RecordBatch<Record>, _) = serde_kafka::from_bytes_trail(&log).unwrap();
+    let second: RecordBatch<Record> = serde_kafka::from_bytes(rest).unwrap();
+    assert_eq!(first.base_offset, 0);
+    assert_eq!(second.base_offset, 2);
+    assert_eq!(second.records[1].value.as_deref(), Some(&b"b"[..]));
+}
+
+#[tokio::test]
+async fn test_partition_errors() {
+    let (mut ctx, _) = TestContext::with_logs("produce-errors-test", &[("foo", TOPIC_ID, 2)]).await;
+
+    let request = produce_request(
+        9,
+        1,
+        vec![
+            ("foo", 2, batch(&[b"a"])),
+            ("bar", 0, batch(&[b"a"])),
+            ("foo", 0, Bytes::from_static(&[0x00, 0x01])),
+        ],
+    );
+    let response = produce(&mut ctx.client_io, &request).await;
+
+    let error_codes: Vec<_> = response
+        .body
+        .responses
+        .iter()
+        .map(|topic| topic.partition_responses[0].error_code)
+        .collect();
+    assert_eq!(
+        error_codes,
+        [
+            ErrorCode::UnknownTopic as i16,
+            ErrorCode::UnknownTopic as i16,
+            ErrorCode::CorruptMessage as i16,
+        ]
+    );
+
+    let request = produce_request(9, 2, vec![("foo", 0, batch(&[b"a"]))]);
+    let response = produce(&mut ctx.client_io, &request).await;
+    let partition = &response.body.responses[0].partition_responses[0];
+    assert_eq!(partition.error_code, ErrorCode::InvalidRequiredAcks as i16);
+}
+
+#[tokio::test]
+async fn test_acks_zero() {
+    let (mut ctx, _) =
+        TestContext::with_logs("produce-acks-zero-test", &[("foo", TOPIC_ID, 2)]).await;
+
+    // Not answered, so the next response is the one to the second request.
+    let request = produce_request(12, 0, vec![("foo", 0, batch(&[b"a"]))]);
+    ctx.send_request(&request.header, &request.body)
+        .await
+        .unwrap();
+
+    let request = Request {
+        header: RequestHeader {
+            correlation_id: 8,
+            ..request.header
+        },
+        body: ProduceRequest {
+            acks: 1,
+            ..request.body
+        },
+    };
+    let response = time::timeout(
+        time::Duration::from_secs(5),
+        produce(&mut ctx.client_io, &request),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(response.header.correlation_id, 8);
+    assert_eq!(
+        response.body.responses[0].partition_responses[0].base_offset,
+        1
+    );
+}